@@ -0,0 +1,10 @@
+-- Reject reversed or empty timespans in the database, rather than letting them silently
+-- pass (or fail) the overlap checks.
+alter table entries
+    add constraint entries_time_range_check check (start_time < end_time);
+
+alter table planned
+    add constraint planned_time_range_check check (start_time < end_time);
+
+alter table allocations
+    add constraint allocations_time_range_check check (start_time < end_time);
@@ -0,0 +1,27 @@
+-- An entry is only in conflict with an unplanned outage if it overlaps the sliding window,
+-- entries that have already ended before the outage started are of no concern.
+create or replace function unplanned_outage_entry_overlap_check()
+    returns trigger
+    language plpgsql
+    as
+$$
+declare
+    _entry_overlap_count int;
+begin
+    -- Our only responsiblity here is to ensure that there are no allocations
+    -- that overlap with the initial insertion window.
+    select count(*) from allocations
+    where system_id = new.system_id
+        and new.start_time < end_time
+        and (new.start_time + new.sliding_window) > start_time
+        and new.capabilities & capabilities != 0
+        and kind = 'entry'
+    into _entry_overlap_count;
+
+    if _entry_overlap_count != 0 then
+        raise exception 'cannot insert unplanned outage in conflict with entries within sliding window';
+    end if;
+
+    return new;
+end;
+$$;
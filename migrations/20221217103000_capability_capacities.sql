@@ -0,0 +1,79 @@
+-- Optional concurrent capacity of each individual capability on a system, enforced on top of
+-- the total capacity of the system.
+create table if not exists system_capacities (
+    system_id uuid references systems(system_id) not null,
+    -- A single capability bit
+    capability int not null,
+    capacity int not null,
+    primary key (system_id, capability)
+);
+
+create or replace function allocation_overlap_check()
+    returns trigger
+    language plpgsql
+    as
+$$
+declare
+    _outage_overlaps int;
+    _entry_overlaps int;
+    _system_capacity int;
+    _capability_capacity record;
+begin
+    -- Check that the new allocation does not conflict with any existing for any _outages_
+    -- This is applicable for all allocation types, even outages themselves.
+    -- This is to ensure that no duplicate outage entries are added that cover the same timespan.
+    select count(*)
+    from allocations
+    where system_id = new.system_id
+        and new.start_time < end_time
+        and new.end_time > start_time
+        -- If any of the capabilities of the existing rows overlap this the new one
+        and new.capabilities & capabilities != 0
+        and kind != 'entry'
+    into _outage_overlaps;
+
+    if _outage_overlaps != 0 then
+        raise exception 'cannot insert overlapping outage';
+    end if;
+
+    -- Check new 'entry' allocation for concurrent capacity violations
+    if new.kind = 'entry' then
+        select count(*)
+        from allocations
+        where system_id = new.system_id
+            and new.start_time < end_time
+            and new.end_time > start_time
+            and kind = 'entry'
+        into _entry_overlaps;
+
+        select capacity from systems where system_id = new.system_id
+        into _system_capacity;
+
+        if (_entry_overlaps + 1) > _system_capacity then
+            raise exception 'system capacity at max';
+        end if;
+
+        -- Every capability of the entry with a capacity of its own must have room as well
+        for _capability_capacity in
+            select capability, capacity from system_capacities
+            where system_id = new.system_id
+                and new.capabilities & capability != 0
+        loop
+            select count(*)
+            from allocations
+            where system_id = new.system_id
+                and new.start_time < end_time
+                and new.end_time > start_time
+                and capabilities & _capability_capacity.capability != 0
+                and kind = 'entry'
+            into _entry_overlaps;
+
+            if (_entry_overlaps + 1) > _capability_capacity.capacity then
+                raise exception 'system capacity at max';
+            end if;
+        end loop;
+    end if;
+
+    return new;
+end;
+$$;
@@ -0,0 +1,12 @@
+-- Capabilities are a 64 bit mask, allowing systems with more than a handful of capabilities.
+alter table systems alter column capabilities type bigint;
+alter table unplanned alter column capabilities type bigint;
+alter table planned alter column capabilities type bigint;
+alter table allocations alter column capabilities type bigint;
+alter table system_capacities alter column capability type bigint;
+
+-- Outages of the entire system must cover the newly added capabilities as well.
+update allocations set capabilities = 255 where kind = 'full';
+update unplanned set capabilities = 255;
+update planned set capabilities = 255
+where allocation_id in (select allocation_id from allocations where kind = 'full');
@@ -0,0 +1,2 @@
+-- The sliding window used for unplanned outages on the system, unless given explicitly.
+alter table systems add column default_sliding_window interval minute;
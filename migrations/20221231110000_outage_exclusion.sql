@@ -0,0 +1,31 @@
+-- Let the database itself refuse overlapping outages sharing any capability on a system, even
+-- for writes bypassing the insert triggers, e.g. updating the timespan of an existing outage.
+--
+-- Entries can not be covered by an exclusion constraint, as a system with a capacity above one
+-- allows them to overlap. The following invariants thus remain enforced by the insert triggers
+-- and the application alone:
+-- - The concurrent entries on a system do not exceed its capacity, or that of a capability.
+-- - Entries do not overlap outages sharing any of their capabilities.
+-- - Entries do not overlap the sliding window of an unplanned outage.
+create extension if not exists btree_gist;
+create extension if not exists intarray;
+
+-- The indices of the bits set in a capability mask, such that masks sharing any capability
+-- have overlapping arrays.
+create function capability_bits(capabilities bigint)
+    returns int[]
+    language sql
+    immutable
+    as
+$$
+    select coalesce(array_agg(bit), '{}')
+    from generate_series(0, 63) bit
+    where capabilities & (1::bigint << bit) != 0;
+$$;
+
+alter table allocations add constraint allocations_outage_overlap_excl
+exclude using gist (
+    system_id with =,
+    tstzrange(start_time, end_time) with &&,
+    capability_bits(capabilities) gist__int_ops with &&
+) where (kind != 'entry');
@@ -0,0 +1,3 @@
+-- When an allocation was created, such that the latest entries may be picked out when a system
+-- is shrunk below what is already allocated on it.
+alter table allocations add column created_at timestamptz not null default now();
@@ -0,0 +1,5 @@
+-- The series a recurring entry belongs to, shared by all of its occurrences.
+alter table entries add column recurrence_group uuid;
+
+create index entries_recurrence_group_idx on entries (recurrence_group)
+where recurrence_group is not null;
@@ -0,0 +1,148 @@
+-- Tentative entries, counting against capacity like any other entry until they either are
+-- confirmed into an entry or expire. The allocation of a hold is an ordinary 'entry'
+-- allocation without a row in entries, this table only tracks when it expires.
+create table if not exists holds (
+    allocation_id uuid primary key,
+    expires_at timestamptz not null
+);
+
+-- Whether the allocation is a hold that has expired, and thus no longer occupies its timespan.
+-- Expired holds linger until removed, every check over the allocations must skip them.
+create function hold_expired(allocation_id uuid)
+    returns boolean
+    language sql
+    stable
+    as
+$$
+    select exists (
+        select 1 from holds h
+        where h.allocation_id = hold_expired.allocation_id
+            and h.expires_at <= now()
+    );
+$$;
+
+create or replace function unplanned_outage_entry_overlap_check()
+    returns trigger
+    language plpgsql
+    as
+$$
+declare
+    _entry_overlap_count int;
+begin
+    -- Our only responsiblity here is to ensure that there are no allocations
+    -- that overlap with the initial insertion window.
+    select count(*) from allocations
+    where system_id = new.system_id
+        and new.start_time < end_time
+        and (new.start_time + new.sliding_window) > start_time
+        and new.capabilities & capabilities != 0
+        and kind = 'entry'
+        and not hold_expired(allocation_id)
+    into _entry_overlap_count;
+
+    if _entry_overlap_count != 0 then
+        raise exception 'cannot insert unplanned outage in conflict with entries within sliding window';
+    end if;
+
+    return new;
+end;
+$$;
+
+create or replace function planned_outage_entry_overlap_check()
+    returns trigger
+    language plpgsql
+    as
+$$
+declare
+    _entry_overlap_count int;
+begin
+    -- Our only responsibility is to assert that no entries with the same capabilities are
+    -- in conflict for the entire finite outage timespan.
+    select count(*) from allocations
+    where system_id = new.system_id
+        and new.start_time < end_time
+        and new.end_time > start_time
+        and new.capabilities & capabilities != 0
+        and kind = 'entry'
+        and not hold_expired(allocation_id)
+    into _entry_overlap_count;
+
+    if _entry_overlap_count != 0 then
+        raise exception 'cannot insert planned outage in conflict with entries';
+    end if;
+
+    return new;
+end;
+$$;
+
+create or replace function allocation_overlap_check()
+    returns trigger
+    language plpgsql
+    as
+$$
+declare
+    _outage_overlaps int;
+    _entry_overlaps int;
+    _system_capacity int;
+    _capability_capacity record;
+begin
+    -- Check that the new allocation does not conflict with any existing for any _outages_
+    -- This is applicable for all allocation types, even outages themselves.
+    -- This is to ensure that no duplicate outage entries are added that cover the same timespan.
+    select count(*)
+    from allocations
+    where system_id = new.system_id
+        and new.start_time < end_time
+        and new.end_time > start_time
+        -- If any of the capabilities of the existing rows overlap this the new one
+        and new.capabilities & capabilities != 0
+        and kind != 'entry'
+    into _outage_overlaps;
+
+    if _outage_overlaps != 0 then
+        raise exception 'cannot insert overlapping outage';
+    end if;
+
+    -- Check new 'entry' allocation for concurrent capacity violations
+    if new.kind = 'entry' then
+        select count(*)
+        from allocations
+        where system_id = new.system_id
+            and new.start_time < end_time
+            and new.end_time > start_time
+            and kind = 'entry'
+            and not hold_expired(allocation_id)
+        into _entry_overlaps;
+
+        select capacity from systems where system_id = new.system_id
+        into _system_capacity;
+
+        if (_entry_overlaps + 1) > _system_capacity then
+            raise exception 'system capacity at max';
+        end if;
+
+        -- Every capability of the entry with a capacity of its own must have room as well
+        for _capability_capacity in
+            select capability, capacity from system_capacities
+            where system_id = new.system_id
+                and new.capabilities & capability != 0
+        loop
+            select count(*)
+            from allocations
+            where system_id = new.system_id
+                and new.start_time < end_time
+                and new.end_time > start_time
+                and capabilities & _capability_capacity.capability != 0
+                and kind = 'entry'
+                and not hold_expired(allocation_id)
+            into _entry_overlaps;
+
+            if (_entry_overlaps + 1) > _capability_capacity.capacity then
+                raise exception 'system capacity at max';
+            end if;
+        end loop;
+    end if;
+
+    return new;
+end;
+$$;
@@ -0,0 +1,2 @@
+-- How far ahead of time entries must be inserted on the system, if at all.
+alter table systems add column min_notice interval;
@@ -0,0 +1,12 @@
+-- Entries forcefully removed by the sweep of unplanned outage windows, such that their owners
+-- may be notified after the fact. Rows are kept as an audit trail, nothing refers to them.
+create table if not exists evictions (
+    allocation_id uuid primary key,
+    system_id uuid not null references systems(system_id),
+    start_time timestamptz not null,
+    end_time timestamptz not null,
+    capabilities bigint not null,
+    evicted_at timestamptz not null default now()
+);
+
+create index if not exists evictions_system_evicted_at on evictions (system_id, evicted_at);
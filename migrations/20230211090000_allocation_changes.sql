@@ -0,0 +1,43 @@
+-- Notify listeners on the 'allocation_changes' channel of every allocation inserted, updated or
+-- removed, by any path including the sweep. The payload is the row after the change, or before
+-- it when removed, tagged with the event. Notifications are only delivered once the transaction
+-- commits.
+create function notify_allocation_change()
+    returns trigger
+    language plpgsql
+    as
+$$
+declare
+    _row allocations;
+begin
+    if tg_op = 'DELETE' then
+        _row := old;
+    else
+        _row := new;
+    end if;
+
+    perform pg_notify('allocation_changes', json_build_object(
+        'event', case tg_op
+            when 'INSERT' then 'inserted'
+            when 'UPDATE' then 'updated'
+            else 'removed'
+        end,
+        'allocation', json_build_object(
+            'system_id', _row.system_id,
+            'allocation_id', _row.allocation_id,
+            'kind', _row.kind,
+            'planned', _row.planned,
+            'start_time', _row.start_time,
+            'end_time', nullif(_row.end_time, 'infinity'),
+            'capabilities', _row.capabilities
+        )
+    )::text);
+
+    return null;
+end;
+$$;
+
+create trigger allocation_change_notify
+after insert or update or delete on allocations
+for each row
+execute function notify_allocation_change();
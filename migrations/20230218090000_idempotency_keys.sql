@@ -0,0 +1,7 @@
+-- Keys supplied by callers to make inserting an entry idempotent, mapping each key to the entry
+-- first inserted with it. The key outlives moves of the entry, which keep its allocation id, and
+-- is reused once the entry is removed.
+create table if not exists idempotency_keys (
+    idempotency_key uuid primary key,
+    allocation_id uuid not null
+);
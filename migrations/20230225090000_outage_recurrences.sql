@@ -0,0 +1,18 @@
+-- Planned outages recurring at a local time of day, such as standing maintenance windows. Their
+-- occurrences are inserted as planned outages up to a rolling horizon ahead of now, having been
+-- inserted up until materialized_until.
+create table if not exists outage_recurrences (
+    recurrence_id uuid primary key,
+    system_id uuid references systems(system_id) not null,
+    -- The days from monday the outage occurs on, or every day if empty.
+    weekdays smallint[] not null,
+    local_start time not null,
+    -- The IANA name of the timezone local_start is in.
+    timezone text not null,
+    duration interval not null,
+    horizon interval not null,
+    materialized_until timestamptz not null
+);
+
+-- The recurrence a planned outage is an occurrence of, if any.
+alter table planned add column recurrence_id uuid references outage_recurrences(recurrence_id);
@@ -0,0 +1,11 @@
+-- Who an entry belongs to, and what it is for, as given when it was inserted.
+alter table entries add column owner uuid;
+alter table entries add column label text;
+alter table entries add column metadata jsonb;
+
+create index entries_owner_idx on entries (owner) where owner is not null;
+
+-- Kept along with an evicted entry, such that its owner may be notified.
+alter table evictions add column owner uuid;
+alter table evictions add column label text;
+alter table evictions add column metadata jsonb;
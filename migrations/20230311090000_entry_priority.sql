@@ -0,0 +1,2 @@
+-- How important an entry is, such that it may only be displaced by entries of a higher priority.
+alter table entries add column priority integer not null default 0;
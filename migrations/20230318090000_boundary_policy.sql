@@ -0,0 +1,6 @@
+-- Whether allocations on a system may touch, one ending exactly as another starts, or must
+-- leave a gap between them. Only enforced by the application, the insert triggers and the
+-- outage exclusion constraint keep treating timespans as half-open.
+create type boundary_policy as enum ('touching', 'exclusive');
+
+alter table systems add column boundary_policy boundary_policy not null default 'touching';
@@ -0,0 +1,3 @@
+-- Entries which must never be forcefully removed, e.g. by the sweep of an unplanned outage, but
+-- left in place for someone to resolve by hand.
+alter table entries add column pinned boolean not null default false;
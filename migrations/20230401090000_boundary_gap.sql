@@ -0,0 +1,10 @@
+-- The minimum gap to leave between allocations in conflict on a system, under the 'gap' boundary
+-- policy. Like the policy itself, only enforced by the application.
+alter type boundary_policy add value 'gap';
+
+alter table systems add column boundary_gap interval;
+-- Compared as text, as the new enum value may not be used within the transaction adding it.
+alter table systems add constraint systems_boundary_gap_check check (
+    (boundary_policy::text = 'gap') = (boundary_gap is not null)
+    and boundary_gap > interval '0'
+);
@@ -0,0 +1,18 @@
+-- Unplanned reductions of the concurrent capacity of a system, e.g. one of several identical
+-- fixtures failing, rather than blocking it entirely. Like an unplanned outage, a reduction lasts
+-- until resolved, and entries over the reduced capacity within its sliding window are removed.
+-- Reductions in effect at the same time sum up.
+--
+-- Only the application enforces the reduced capacity, the capacity check of
+-- `allocation_overlap_check` stays at the declared capacity.
+create table if not exists capacity_reductions (
+    reduction_id uuid primary key,
+    system_id uuid not null references systems(system_id),
+    start_time timestamptz not null,
+    sliding_window interval minute not null,
+    reduction int not null check (reduction > 0),
+    resolved_at timestamptz,
+    constraint capacity_reductions_time_range_check check (resolved_at > start_time)
+);
+
+create index if not exists capacity_reductions_system on capacity_reductions (system_id);
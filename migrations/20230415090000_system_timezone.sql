@@ -0,0 +1,3 @@
+-- The IANA name of the timezone the operators of the system think in, if any. Timestamps are
+-- stored in UTC regardless, this is only for presenting them.
+alter table systems add column timezone text;
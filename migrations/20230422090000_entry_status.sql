@@ -0,0 +1,152 @@
+-- The lifecycle of an entry, from scheduled until it either completes or is cancelled. A
+-- completed entry is shrunk to when it actually ended, and a cancelled one stays in place, both
+-- to be listed along with their status.
+create type entry_status as enum ('scheduled', 'active', 'completed', 'cancelled');
+
+alter table entries add column status entry_status not null default 'scheduled';
+alter table entries add column cancel_reason text;
+
+-- Whether the allocation is an entry that has completed or been cancelled, and thus no longer
+-- occupies its timespan. Like expired holds, every check over the allocations must skip them.
+create function entry_finished(allocation_id uuid)
+    returns boolean
+    language sql
+    stable
+    as
+$$
+    select exists (
+        select 1 from entries e
+        where e.allocation_id = entry_finished.allocation_id
+            and e.status in ('completed', 'cancelled')
+    );
+$$;
+
+create or replace function unplanned_outage_entry_overlap_check()
+    returns trigger
+    language plpgsql
+    as
+$$
+declare
+    _entry_overlap_count int;
+begin
+    -- Our only responsiblity here is to ensure that there are no allocations
+    -- that overlap with the initial insertion window.
+    select count(*) from allocations
+    where system_id = new.system_id
+        and new.start_time < end_time
+        and (new.start_time + new.sliding_window) > start_time
+        and new.capabilities & capabilities != 0
+        and kind = 'entry'
+        and not hold_expired(allocation_id)
+        and not entry_finished(allocation_id)
+    into _entry_overlap_count;
+
+    if _entry_overlap_count != 0 then
+        raise exception 'cannot insert unplanned outage in conflict with entries within sliding window';
+    end if;
+
+    return new;
+end;
+$$;
+
+create or replace function planned_outage_entry_overlap_check()
+    returns trigger
+    language plpgsql
+    as
+$$
+declare
+    _entry_overlap_count int;
+begin
+    -- Our only responsibility is to assert that no entries with the same capabilities are
+    -- in conflict for the entire finite outage timespan.
+    select count(*) from allocations
+    where system_id = new.system_id
+        and new.start_time < end_time
+        and new.end_time > start_time
+        and new.capabilities & capabilities != 0
+        and kind = 'entry'
+        and not hold_expired(allocation_id)
+        and not entry_finished(allocation_id)
+    into _entry_overlap_count;
+
+    if _entry_overlap_count != 0 then
+        raise exception 'cannot insert planned outage in conflict with entries';
+    end if;
+
+    return new;
+end;
+$$;
+
+create or replace function allocation_overlap_check()
+    returns trigger
+    language plpgsql
+    as
+$$
+declare
+    _outage_overlaps int;
+    _entry_overlaps int;
+    _system_capacity int;
+    _capability_capacity record;
+begin
+    -- Check that the new allocation does not conflict with any existing for any _outages_
+    -- This is applicable for all allocation types, even outages themselves.
+    -- This is to ensure that no duplicate outage entries are added that cover the same timespan.
+    select count(*)
+    from allocations
+    where system_id = new.system_id
+        and new.start_time < end_time
+        and new.end_time > start_time
+        -- If any of the capabilities of the existing rows overlap this the new one
+        and new.capabilities & capabilities != 0
+        and kind != 'entry'
+    into _outage_overlaps;
+
+    if _outage_overlaps != 0 then
+        raise exception 'cannot insert overlapping outage';
+    end if;
+
+    -- Check new 'entry' allocation for concurrent capacity violations
+    if new.kind = 'entry' then
+        select count(*)
+        from allocations
+        where system_id = new.system_id
+            and new.start_time < end_time
+            and new.end_time > start_time
+            and kind = 'entry'
+            and not hold_expired(allocation_id)
+            and not entry_finished(allocation_id)
+        into _entry_overlaps;
+
+        select capacity from systems where system_id = new.system_id
+        into _system_capacity;
+
+        if (_entry_overlaps + 1) > _system_capacity then
+            raise exception 'system capacity at max';
+        end if;
+
+        -- Every capability of the entry with a capacity of its own must have room as well
+        for _capability_capacity in
+            select capability, capacity from system_capacities
+            where system_id = new.system_id
+                and new.capabilities & capability != 0
+        loop
+            select count(*)
+            from allocations
+            where system_id = new.system_id
+                and new.start_time < end_time
+                and new.end_time > start_time
+                and capabilities & _capability_capacity.capability != 0
+                and kind = 'entry'
+                and not hold_expired(allocation_id)
+                and not entry_finished(allocation_id)
+            into _entry_overlaps;
+
+            if (_entry_overlaps + 1) > _capability_capacity.capacity then
+                raise exception 'system capacity at max';
+            end if;
+        end loop;
+    end if;
+
+    return new;
+end;
+$$;
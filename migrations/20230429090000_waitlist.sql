@@ -0,0 +1,15 @@
+-- Requests for a timeslot on a system without room for them, queued to be inserted as entries
+-- once room is freed, the earliest joined first. A promoted request becomes the entry with the
+-- same id, and is removed from the waitlist.
+create table if not exists waitlist (
+    waitlist_id uuid primary key,
+    system_id uuid not null references systems(system_id),
+    start_time timestamptz not null,
+    end_time timestamptz not null,
+    capabilities bigint not null,
+    -- The wall clock rather than the transaction time, keeping several joined at once in order.
+    joined_at timestamptz not null default clock_timestamp(),
+    constraint waitlist_time_range_check check (start_time < end_time)
+);
+
+create index if not exists waitlist_system on waitlist (system_id, start_time);
@@ -0,0 +1,2 @@
+-- How far ahead of time entries may be inserted on the system at most, if limited.
+alter table systems add column max_horizon interval;
@@ -0,0 +1,2 @@
+-- Kept along with an evicted entry, as its other details are.
+alter table evictions add column priority integer not null default 0;
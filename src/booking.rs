@@ -0,0 +1,200 @@
+//! How far ahead of time entries may be inserted on a system, at the least and at the most.
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::postgres::{types::PgInterval, PgExecutor, Postgres};
+use sqlx::Transaction;
+use uuid::Uuid;
+
+use crate::interval::interval_to_duration;
+use crate::{insert_entry, AllocationError, Capabilities, EntryParams, SystemAllocation};
+
+/// When new entries may start on a system, relative to the time they are inserted, see
+/// [`SystemAllocation::set_booking_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BookingPolicy {
+    /// How far ahead of time entries must be inserted, if at all.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::interval::serde_seconds::option")
+    )]
+    pub min_notice: Option<Duration>,
+    /// How far ahead of time entries may be inserted at most, if limited.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::interval::serde_seconds::option")
+    )]
+    pub max_horizon: Option<Duration>,
+}
+
+impl BookingPolicy {
+    /// Fail unless an entry starting at `start` may be inserted at `now`.
+    pub(crate) fn check(
+        &self,
+        now: DateTime<Utc>,
+        start: DateTime<Utc>,
+    ) -> Result<(), AllocationError> {
+        let after = |offset: Duration| {
+            now.checked_add_signed(offset)
+                .unwrap_or(DateTime::<Utc>::MAX_UTC)
+        };
+        if let Some(earliest) = self.min_notice.map(after) {
+            if start < earliest {
+                return Err(AllocationError::TooSoon { start, earliest });
+            }
+        }
+        if let Some(latest) = self.max_horizon.map(after) {
+            if start > latest {
+                return Err(AllocationError::TooFarInFuture { start, latest });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl SystemAllocation {
+    /// Set the booking policy of the system, replacing the current one. A system has no booking
+    /// policy until one is set, such that entries may start at any time.
+    ///
+    /// New entries and holds starting sooner than the minimum notice from now are rejected with
+    /// [`AllocationError::TooSoon`], and those starting later than the maximum horizon from now
+    /// with [`AllocationError::TooFarInFuture`]. So are entries moved to start then by
+    /// [`SystemAllocation::update_entry`] or [`SystemAllocation::shift_entries`], while those
+    /// otherwise modified are left as they are.
+    /// Outages are never subject to the policy, nor are entries moved by
+    /// [`SystemAllocation::insert_planned_outage_rescheduling`]. See
+    /// [`SystemAllocation::insert_entry_bypassing_policy`] for administrative tooling.
+    ///
+    /// Fails with [`AllocationError::Validation`] if either is negative, or the minimum notice is
+    /// beyond the maximum horizon.
+    #[tracing::instrument(skip(self))]
+    pub async fn set_booking_policy(
+        &self,
+        system: Uuid,
+        policy: BookingPolicy,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.set_booking_policy_tx(&mut tx, system, policy).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::set_booking_policy`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn set_booking_policy_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        policy: BookingPolicy,
+    ) -> Result<(), AllocationError> {
+        if let (Some(notice), Some(horizon)) = (policy.min_notice, policy.max_horizon) {
+            if notice > horizon {
+                return Err(AllocationError::Validation(format!(
+                    "minimum notice {notice} is beyond the maximum horizon {horizon}"
+                )));
+            }
+        }
+        let interval = |offset: Option<Duration>, what: &str| {
+            offset
+                .map(|offset| {
+                    PgInterval::try_from(offset)
+                        .ok()
+                        .filter(|_| offset >= Duration::zero())
+                        .ok_or_else(|| {
+                            AllocationError::Validation(format!("invalid {what} {offset}"))
+                        })
+                })
+                .transpose()
+        };
+        let min_notice = interval(policy.min_notice, "minimum notice")?;
+        let max_horizon = interval(policy.max_horizon, "maximum horizon")?;
+
+        let updated = sqlx::query!(
+            r#"
+        UPDATE systems SET min_notice = $2, max_horizon = $3 WHERE system_id = $1
+            "#,
+            system,
+            min_notice,
+            max_horizon,
+        )
+        .execute(&mut *tx)
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Err(AllocationError::SystemNotFound(system));
+        }
+
+        Ok(())
+    }
+
+    /// Insert a single entry as with [`SystemAllocation::insert_entry_with_params`], regardless
+    /// of the booking policy of the system, e.g. for administrative tooling.
+    ///
+    /// The entry is evaluated against the allocations as any other entry.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_entry_bypassing_policy(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+        params: EntryParams,
+    ) -> Result<Uuid, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let allocation_id = self
+            .insert_entry_bypassing_policy_tx(&mut tx, system, start, end, capabilities, params)
+            .await?;
+        tx.commit().await?;
+
+        Ok(allocation_id)
+    }
+
+    /// As [`SystemAllocation::insert_entry_bypassing_policy`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_entry_bypassing_policy_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+        params: EntryParams,
+    ) -> Result<Uuid, AllocationError> {
+        let allocation_id = Uuid::new_v4();
+        insert_entry(
+            &mut *tx,
+            system,
+            allocation_id,
+            start,
+            Some(end),
+            capabilities,
+            &params,
+        )
+        .await?;
+
+        Ok(allocation_id)
+    }
+}
+
+/// The booking policy of `system`, which is empty for a system that has not been declared.
+pub(crate) async fn booking_policy(
+    executor: impl PgExecutor<'_>,
+    system: Uuid,
+) -> Result<BookingPolicy, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+    SELECT min_notice, max_horizon FROM systems WHERE system_id = $1
+        "#,
+        system,
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row
+        .map(|row| BookingPolicy {
+            min_notice: row.min_notice.map(interval_to_duration),
+            max_horizon: row.max_horizon.map(interval_to_duration),
+        })
+        .unwrap_or_default())
+}
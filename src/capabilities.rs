@@ -0,0 +1,153 @@
+//! The capabilities a system may provide, and entries and outages may cover.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bitflags::bitflags;
+
+bitflags! {
+    #[derive(Default)]
+    /// A set of capabilities, stored as a 64 bit mask.
+    pub struct Capabilities: u64 {
+        const A = 0b00000001;
+        const B = 0b00000010;
+        const C = 0b00000100;
+        const D = 0b00001000;
+        const E = 0b00010000;
+        const F = 0b00100000;
+        const G = 0b01000000;
+        const H = 0b10000000;
+    }
+}
+
+/// The name of every single capability, in bit order.
+const NAMES: &[(&str, Capabilities)] = &[
+    ("A", Capabilities::A),
+    ("B", Capabilities::B),
+    ("C", Capabilities::C),
+    ("D", Capabilities::D),
+    ("E", Capabilities::E),
+    ("F", Capabilities::F),
+    ("G", Capabilities::G),
+    ("H", Capabilities::H),
+];
+
+/// The name displayed for no capabilities at all.
+const NONE: &str = "NONE";
+
+impl Capabilities {
+    /// Convert from the raw bits, failing on any bits not corresponding to a capability rather
+    /// than silently dropping them as [`Capabilities::from_bits_truncate`] does.
+    pub fn try_from_bits_strict(bits: u64) -> Result<Self, ParseCapabilitiesError> {
+        Self::from_bits(bits)
+            .ok_or_else(|| ParseCapabilitiesError(format!("{:#x}", bits & !Self::all().bits())))
+    }
+}
+
+/// Capabilities are displayed by their names separated by `|`, e.g. `A|C`, and as `NONE` when
+/// empty.
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = NAMES.iter().filter(|(_, c)| self.contains(*c));
+        match names.next() {
+            Some((first, _)) => f.write_str(first)?,
+            None => f.write_str(NONE)?,
+        }
+        for (name, _) in names {
+            write!(f, "|{name}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A capability name, or the bits of capabilities, that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown capability `{0}`")]
+pub struct ParseCapabilitiesError(pub String);
+
+/// Capabilities are parsed from their case-insensitive names separated by `,` or `|`, e.g.
+/// `a,C`, such that the output of [`Display`](fmt::Display) is parsed as well. Both `NONE` and
+/// the empty string parse as no capabilities.
+impl FromStr for Capabilities {
+    type Err = ParseCapabilitiesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut capabilities = Capabilities::empty();
+        if s.trim().is_empty() || s.trim().eq_ignore_ascii_case(NONE) {
+            return Ok(capabilities);
+        }
+        for token in s.split([',', '|']).map(str::trim) {
+            let (_, capability) = NAMES
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(token))
+                .ok_or_else(|| ParseCapabilitiesError(token.to_string()))?;
+            capabilities |= *capability;
+        }
+        Ok(capabilities)
+    }
+}
+
+/// Capabilities are serialized as a list of their names, e.g. `["A","C"]`, such that payloads
+/// remain readable and independent of the bit each capability is assigned.
+///
+/// Either a list of names or the raw bits are deserialized, the latter for clients passing on
+/// masks as read from the database. Unknown names and bits are rejected rather than dropped.
+#[cfg(feature = "serde")]
+mod serialization {
+    use std::fmt;
+
+    use serde::de::{self, SeqAccess, Visitor};
+    use serde::ser::SerializeSeq;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{Capabilities, NAMES};
+
+    impl Serialize for Capabilities {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let names: Vec<_> = NAMES.iter().filter(|(_, c)| self.contains(*c)).collect();
+            let mut seq = serializer.serialize_seq(Some(names.len()))?;
+            for (name, _) in names {
+                seq.serialize_element(name)?;
+            }
+            seq.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Capabilities {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(CapabilitiesVisitor)
+        }
+    }
+
+    struct CapabilitiesVisitor;
+
+    impl<'de> Visitor<'de> for CapabilitiesVisitor {
+        type Value = Capabilities;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a list of capability names, or the bits of a capability mask")
+        }
+
+        fn visit_u64<E: de::Error>(self, bits: u64) -> Result<Self::Value, E> {
+            Capabilities::try_from_bits_strict(bits).map_err(E::custom)
+        }
+
+        fn visit_i64<E: de::Error>(self, bits: i64) -> Result<Self::Value, E> {
+            let bits = u64::try_from(bits)
+                .map_err(|_| E::invalid_value(de::Unexpected::Signed(bits), &self))?;
+            self.visit_u64(bits)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut capabilities = Capabilities::empty();
+            while let Some(name) = seq.next_element::<String>()? {
+                let (_, capability) = NAMES
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .ok_or_else(|| de::Error::custom(format!("unknown capability `{name}`")))?;
+                capabilities |= *capability;
+            }
+            Ok(capabilities)
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! A stream of the changes to the allocations, for reacting to e.g. evictions and new outages.
+
+use futures_util::stream::{self, Stream};
+use sqlx::postgres::PgListener;
+
+use crate::{Allocation, AllocationError, SystemAllocation};
+
+/// The channel notified by the database of every change to the allocations.
+const CHANNEL: &str = "allocation_changes";
+
+/// How long to wait before listening again, after failing to reconnect.
+const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// A change to a single allocation, as committed to the database.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "event", content = "allocation", rename_all = "lowercase")]
+pub enum AllocationEvent {
+    Inserted(Allocation),
+    /// The allocation as it is after the update, e.g. a modified entry or a resolved outage.
+    Updated(Allocation),
+    /// The allocation as it was before it was removed, be it by an operation or a sweep.
+    Removed(Allocation),
+}
+
+impl SystemAllocation {
+    /// Subscribe to every change to the allocations of all systems, from when this returns.
+    ///
+    /// Should the connection to the database be lost, it is reconnected to without ending the
+    /// stream. Any changes committed in the meantime are missed, and a warning is logged.
+    #[tracing::instrument(skip(self))]
+    pub async fn subscribe_changes(
+        &self,
+    ) -> Result<impl Stream<Item = AllocationEvent> + Send, AllocationError> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(CHANNEL).await?;
+
+        Ok(stream::unfold(listener, |mut listener| async move {
+            loop {
+                match listener.try_recv().await {
+                    Ok(Some(notification)) => match serde_json::from_str(notification.payload()) {
+                        Ok(event) => return Some((event, listener)),
+                        Err(err) => tracing::warn!(%err, "malformed allocation change"),
+                    },
+                    // The next attempt to receive reconnects, and listens again.
+                    Ok(None) => {
+                        tracing::warn!("lost the connection listening for allocation changes")
+                    }
+                    Err(err) => {
+                        tracing::warn!(%err, "failed to listen for allocation changes");
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        }))
+    }
+}
@@ -0,0 +1,368 @@
+//! Diagnostics of the allocations table against the tables it mirrors, which only the
+//! application keeps in sync.
+
+use chrono::{DateTime, Utc};
+use sqlx::postgres::Postgres;
+use sqlx::Transaction;
+use uuid::Uuid;
+
+use crate::{AllocationError, AllocationKind, SystemAllocation};
+
+/// A table whose rows are mirrored into the allocations table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum SourceTable {
+    Entries,
+    Planned,
+    Unplanned,
+}
+
+impl SourceTable {
+    fn from_name(name: &str) -> Result<Self, sqlx::Error> {
+        match name {
+            "entries" => Ok(Self::Entries),
+            "planned" => Ok(Self::Planned),
+            "unplanned" => Ok(Self::Unplanned),
+            _ => Err(sqlx::Error::Decode(format!("unknown table {name}").into())),
+        }
+    }
+}
+
+/// The columns of an allocation mirrored from its row, with the capabilities as the raw bits
+/// stored, as they may not be valid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MirroredColumns {
+    pub system_id: Uuid,
+    pub start_time: DateTime<Utc>,
+    /// `None` when the allocation has no known end.
+    pub end_time: Option<DateTime<Utc>>,
+    pub capabilities: i64,
+}
+
+/// A violation of the invariants between the allocations table and the tables it mirrors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Inconsistency {
+    /// A row of `table` without an allocation. Repaired by removing the row.
+    OrphanedRow {
+        table: SourceTable,
+        allocation_id: Uuid,
+    },
+    /// An allocation without a row in any table, nor being a hold. Repaired by removing the
+    /// allocation.
+    OrphanedAllocation {
+        allocation_id: Uuid,
+        system_id: Uuid,
+        kind: AllocationKind,
+    },
+    /// An allocation whose kind, or whether it is planned, does not match the row of `table` it
+    /// mirrors, e.g. an entry allocated as an outage. Left to be repaired by hand, as either may
+    /// be the one in error.
+    KindMismatch {
+        table: SourceTable,
+        allocation_id: Uuid,
+        kind: AllocationKind,
+        planned: bool,
+    },
+    /// An allocation whose system, timespan or capabilities differ from the row of `table` it
+    /// mirrors. Repaired by updating the allocation to the `expected` columns of the row.
+    ColumnMismatch {
+        table: SourceTable,
+        allocation_id: Uuid,
+        found: MirroredColumns,
+        expected: MirroredColumns,
+    },
+}
+
+/// The outcome of [`SystemAllocation::verify_consistency`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConsistencyReport {
+    /// Every violation found, whether repaired or not.
+    pub inconsistencies: Vec<Inconsistency>,
+    /// The number of rows removed or updated to repair them, being zero unless asked to.
+    pub repaired: u64,
+}
+
+impl SystemAllocation {
+    /// Check the allocations table against the entries, planned and unplanned tables it
+    /// mirrors, reporting every violation found, see [`Inconsistency`].
+    ///
+    /// With `repair`, orphaned rows and allocations are removed and mismatched allocations are
+    /// updated to match their rows, within the same transaction. A repair rejected by the
+    /// database, e.g. for an outage updated to overlap another, fails the check as a whole.
+    ///
+    /// This is intended to run periodically, e.g. nightly, and is a no-op on a consistent
+    /// database.
+    #[tracing::instrument(skip(self))]
+    pub async fn verify_consistency(
+        &self,
+        repair: bool,
+    ) -> Result<ConsistencyReport, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let report = self.verify_consistency_tx(&mut tx, repair).await?;
+        tx.commit().await?;
+
+        Ok(report)
+    }
+
+    /// As [`SystemAllocation::verify_consistency`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn verify_consistency_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        repair: bool,
+    ) -> Result<ConsistencyReport, AllocationError> {
+        let mut inconsistencies = orphaned_rows(&mut *tx).await?;
+        inconsistencies.extend(orphaned_allocations(&mut *tx).await?);
+        inconsistencies.extend(kind_mismatches(&mut *tx).await?);
+        inconsistencies.extend(column_mismatches(&mut *tx).await?);
+        for inconsistency in &inconsistencies {
+            tracing::warn!(?inconsistency, "inconsistent allocation");
+        }
+
+        let repaired = match repair {
+            true => repair_all(tx, &inconsistencies).await?,
+            false => 0,
+        };
+
+        Ok(ConsistencyReport {
+            inconsistencies,
+            repaired,
+        })
+    }
+}
+
+async fn orphaned_rows(
+    tx: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<Inconsistency>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+    SELECT 'entries' AS "source!", allocation_id AS "allocation_id!" FROM entries
+    WHERE allocation_id NOT IN (SELECT allocation_id FROM allocations)
+    UNION ALL
+    SELECT 'planned', allocation_id FROM planned
+    WHERE allocation_id NOT IN (SELECT allocation_id FROM allocations)
+    UNION ALL
+    SELECT 'unplanned', allocation_id FROM unplanned
+    WHERE allocation_id NOT IN (SELECT allocation_id FROM allocations)
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(Inconsistency::OrphanedRow {
+                table: SourceTable::from_name(&row.source)?,
+                allocation_id: row.allocation_id,
+            })
+        })
+        .collect()
+}
+
+async fn orphaned_allocations(
+    tx: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<Inconsistency>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+    SELECT allocation_id, system_id, kind AS "kind: AllocationKind" FROM allocations
+    WHERE allocation_id NOT IN (SELECT allocation_id FROM entries)
+        AND allocation_id NOT IN (SELECT allocation_id FROM holds)
+        AND allocation_id NOT IN (SELECT allocation_id FROM planned)
+        AND allocation_id NOT IN (SELECT allocation_id FROM unplanned)
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Inconsistency::OrphanedAllocation {
+            allocation_id: row.allocation_id,
+            system_id: row.system_id,
+            kind: row.kind,
+        })
+        .collect())
+}
+
+async fn kind_mismatches(
+    tx: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<Inconsistency>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+    SELECT 'entries' AS "source!", a.allocation_id AS "allocation_id!",
+        a.kind AS "kind!: AllocationKind", a.planned AS "planned!"
+    FROM allocations a JOIN entries USING (allocation_id)
+    WHERE a.kind != 'entry' OR NOT a.planned
+    UNION ALL
+    SELECT 'planned', a.allocation_id, a.kind, a.planned
+    FROM allocations a JOIN planned USING (allocation_id)
+    WHERE a.kind = 'entry' OR NOT a.planned
+    UNION ALL
+    SELECT 'unplanned', a.allocation_id, a.kind, a.planned
+    FROM allocations a JOIN unplanned USING (allocation_id)
+    WHERE a.kind = 'entry' OR a.planned
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(Inconsistency::KindMismatch {
+                table: SourceTable::from_name(&row.source)?,
+                allocation_id: row.allocation_id,
+                kind: row.kind,
+                planned: row.planned,
+            })
+        })
+        .collect()
+}
+
+/// The allocations of the matching kind whose mirrored columns differ from their rows. Entries
+/// only mirror their timespan, the system and capabilities are those of the allocation.
+async fn column_mismatches(
+    tx: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<Inconsistency>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+    WITH expected AS (
+        SELECT 'entries' AS source, a.allocation_id, a.system_id, e.start_time, e.end_time,
+            a.capabilities
+        FROM allocations a JOIN entries e USING (allocation_id)
+        WHERE a.kind = 'entry'
+        UNION ALL
+        SELECT 'planned', p.allocation_id, p.system_id, p.start_time, p.end_time, p.capabilities
+        FROM allocations a JOIN planned p USING (allocation_id)
+        WHERE a.kind != 'entry' AND a.planned
+        UNION ALL
+        SELECT 'unplanned', u.allocation_id, u.system_id, u.start_time,
+            COALESCE(u.resolved_at, 'infinity'), u.capabilities
+        FROM allocations a JOIN unplanned u USING (allocation_id)
+        WHERE a.kind != 'entry' AND NOT a.planned
+    )
+    SELECT x.source AS "source!", a.allocation_id,
+        a.system_id, a.start_time, NULLIF(a.end_time, 'infinity') AS end_time, a.capabilities,
+        x.system_id AS "expected_system_id!", x.start_time AS "expected_start_time!",
+        NULLIF(x.end_time, 'infinity') AS expected_end_time,
+        x.capabilities AS "expected_capabilities!"
+    FROM allocations a JOIN expected x USING (allocation_id)
+    WHERE (a.system_id, a.start_time, a.end_time, a.capabilities)
+        IS DISTINCT FROM (x.system_id, x.start_time, x.end_time, x.capabilities)
+    ORDER BY a.start_time
+        "#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(Inconsistency::ColumnMismatch {
+                table: SourceTable::from_name(&row.source)?,
+                allocation_id: row.allocation_id,
+                found: MirroredColumns {
+                    system_id: row.system_id,
+                    start_time: row.start_time,
+                    end_time: row.end_time,
+                    capabilities: row.capabilities,
+                },
+                expected: MirroredColumns {
+                    system_id: row.expected_system_id,
+                    start_time: row.expected_start_time,
+                    end_time: row.expected_end_time,
+                    capabilities: row.expected_capabilities,
+                },
+            })
+        })
+        .collect()
+}
+
+/// Repair `inconsistencies` as described by [`Inconsistency`], returning the number of rows
+/// removed or updated.
+async fn repair_all(
+    tx: &mut Transaction<'_, Postgres>,
+    inconsistencies: &[Inconsistency],
+) -> Result<u64, AllocationError> {
+    let mut repaired = 0;
+    for inconsistency in inconsistencies {
+        let result = match inconsistency {
+            Inconsistency::OrphanedRow {
+                table: SourceTable::Entries,
+                allocation_id,
+            } => {
+                sqlx::query!(
+                    r#"
+                DELETE FROM entries WHERE allocation_id = $1
+                    "#,
+                    allocation_id,
+                )
+                .execute(&mut *tx)
+                .await?
+            }
+            Inconsistency::OrphanedRow {
+                table: SourceTable::Planned,
+                allocation_id,
+            } => {
+                sqlx::query!(
+                    r#"
+                DELETE FROM planned WHERE allocation_id = $1
+                    "#,
+                    allocation_id,
+                )
+                .execute(&mut *tx)
+                .await?
+            }
+            Inconsistency::OrphanedRow {
+                table: SourceTable::Unplanned,
+                allocation_id,
+            } => {
+                sqlx::query!(
+                    r#"
+                DELETE FROM unplanned WHERE allocation_id = $1
+                    "#,
+                    allocation_id,
+                )
+                .execute(&mut *tx)
+                .await?
+            }
+            Inconsistency::OrphanedAllocation { allocation_id, .. } => {
+                sqlx::query!(
+                    r#"
+                DELETE FROM allocations WHERE allocation_id = $1
+                    "#,
+                    allocation_id,
+                )
+                .execute(&mut *tx)
+                .await?
+            }
+            Inconsistency::KindMismatch { .. } => continue,
+            Inconsistency::ColumnMismatch {
+                allocation_id,
+                expected,
+                ..
+            } => {
+                sqlx::query!(
+                    r#"
+                UPDATE allocations
+                SET system_id = $2, start_time = $3, end_time = COALESCE($4, 'infinity'::timestamptz),
+                    capabilities = $5
+                WHERE allocation_id = $1
+                    "#,
+                    allocation_id,
+                    expected.system_id,
+                    expected.start_time,
+                    expected.end_time,
+                    expected.capabilities,
+                )
+                .execute(&mut *tx)
+                .await?
+            }
+        };
+        repaired += result.rows_affected();
+    }
+
+    Ok(repaired)
+}
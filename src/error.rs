@@ -0,0 +1,266 @@
+//! Errors returned by the allocation operations.
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::{AllocationKind, Capabilities, CapacityOccupancy, ConflictInfo, Entry};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AllocationError {
+    /// The requested timespan is in conflict with the listed allocations.
+    ///
+    /// For any of the conflict variants, the list may be empty if the database rejected the
+    /// allocation without us being able to determine what it was in conflict with.
+    #[error("in conflict with {} existing allocation(s)", conflicts.len())]
+    Conflict { conflicts: Vec<ConflictInfo> },
+    /// The entry would exceed the capacity of the system, in conflict with the listed allocations.
+    ///
+    /// The `occupancy` tells how much of the capacity is taken where the entry would exceed it,
+    /// `None` if the conflicts span several systems or were not determined.
+    #[error(
+        "system capacity exceeded by {} overlapping allocation(s){}",
+        conflicts.len(),
+        occupied(occupancy)
+    )]
+    CapacityExceeded {
+        conflicts: Vec<ConflictInfo>,
+        occupancy: Option<CapacityOccupancy>,
+    },
+    /// The entry overlaps a planned outage of one or more of its capabilities.
+    ///
+    /// The listed allocations include every allocation the entry is in conflict with.
+    #[error("in conflict with a planned outage")]
+    CapabilityOutage { conflicts: Vec<ConflictInfo> },
+    /// The entry overlaps an unplanned outage, which disallows any new entries from its start.
+    ///
+    /// The listed allocations include every allocation the entry is in conflict with.
+    #[error("in conflict with an unplanned outage")]
+    WindowViolation { conflicts: Vec<ConflictInfo> },
+    /// The capabilities can not be removed from the system, as the listed allocations yet to end
+    /// require them.
+    #[error("capabilities {removed:?} are in use by {} allocation(s)", conflicts.len())]
+    CapabilitiesInUse {
+        removed: Capabilities,
+        conflicts: Vec<ConflictInfo>,
+    },
+    /// The system has not been declared.
+    #[error("system {0} does not exist")]
+    SystemNotFound(Uuid),
+    /// The timespan does not end after it starts.
+    #[error("invalid range, {start} is not before {end}")]
+    InvalidRange {
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    },
+    /// The requested capabilities are not all provided by the system.
+    #[error("capabilities {requested:?} are not all supported by the system, only {supported:?}")]
+    UnsupportedCapabilities {
+        requested: Capabilities,
+        supported: Capabilities,
+    },
+    /// The sliding window of an unplanned outage is not positive, or cannot be stored as an
+    /// interval.
+    #[error("invalid sliding window {0}")]
+    InvalidSlidingWindow(Duration),
+    /// The entry starts sooner than the minimum notice of the system allows, see
+    /// [`SystemAllocation::set_booking_policy`](crate::SystemAllocation::set_booking_policy).
+    #[error(
+        "entry starting at {start} must start at {earliest} or later, due to the minimum notice"
+    )]
+    TooSoon {
+        start: DateTime<Utc>,
+        earliest: DateTime<Utc>,
+    },
+    /// The entry starts later than the maximum horizon of the system allows, see
+    /// [`SystemAllocation::set_booking_policy`](crate::SystemAllocation::set_booking_policy).
+    #[error(
+        "entry starting at {start} must start at {latest} or earlier, due to the booking horizon"
+    )]
+    TooFarInFuture {
+        start: DateTime<Utc>,
+        latest: DateTime<Utc>,
+    },
+    /// The request is not valid, regardless of the current allocations.
+    #[error("invalid request: {0}")]
+    Validation(String),
+    /// No allocation exists with the given allocation id.
+    #[error("no allocation with id {0}")]
+    NotFound(Uuid),
+    /// The allocation exists, but is not of the kind the operation applies to.
+    #[error("allocation {allocation_id} is of kind {kind:?}")]
+    WrongKind {
+        allocation_id: Uuid,
+        kind: AllocationKind,
+    },
+    /// An entry in the way of an outage could not be rescheduled within the horizon.
+    #[error("entry {0} could not be rescheduled within the horizon")]
+    NotRescheduled(Uuid),
+    /// The occurrences of a recurring entry starting at the listed instants are in conflict, and
+    /// thus none of them were inserted.
+    #[error("{} occurrence(s) are in conflict, starting at {starts:?}", starts.len())]
+    RecurrenceConflict { starts: Vec<DateTime<Utc>> },
+    /// None of the given number of systems accepts the entry.
+    #[error("none of the {0} system(s) can accept the entry")]
+    NoSystemAvailable(usize),
+    /// An entry of a batch could not be inserted, and thus neither was any of the batch.
+    #[error("entry {index} of the batch: {error}")]
+    Batch {
+        index: usize,
+        #[source]
+        error: Box<AllocationError>,
+    },
+    /// The database schema does not match the migrations embedded in the crate, as of the
+    /// latest migration `applied` to it.
+    #[error(
+        "database schema at version {applied:?} does not match the expected version {expected}"
+    )]
+    SchemaMismatch { applied: Option<i64>, expected: i64 },
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+// SQLSTATE codes raised by the constraints and triggers in the migrations.
+const UNIQUE_VIOLATION: &str = "23505";
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+const CHECK_VIOLATION: &str = "23514";
+const EXCLUSION_VIOLATION: &str = "23P01";
+const RAISE_EXCEPTION: &str = "P0001";
+
+// Message raised by `allocation_overlap_check` when an entry would exceed the system capacity.
+const CAPACITY_EXCEEDED_MESSAGE: &str = "system capacity at max";
+
+/// Describe how much of the capacity is taken, for the message of
+/// [`AllocationError::CapacityExceeded`].
+fn occupied(occupancy: &Option<CapacityOccupancy>) -> String {
+    let Some(o) = occupancy else {
+        return String::new();
+    };
+    let of = match o.capability {
+        Some(capability) => format!(" of capability {capability}"),
+        None => String::new(),
+    };
+    match o.peak_end {
+        Some(end) => format!(
+            ", {} of {}{of} taken from {} to {end}",
+            o.occupied, o.capacity, o.peak_start
+        ),
+        None => format!(
+            ", {} of {}{of} taken from {}",
+            o.occupied, o.capacity, o.peak_start
+        ),
+    }
+}
+
+impl AllocationError {
+    /// Whether the error is due to a conflict with existing allocations, as opposed to an
+    /// invalid request or a failure to reach the database.
+    pub fn is_conflict(&self) -> bool {
+        match self {
+            Self::Batch { error, .. } => error.is_conflict(),
+            _ => matches!(
+                self,
+                Self::Conflict { .. }
+                    | Self::CapacityExceeded { .. }
+                    | Self::CapabilityOutage { .. }
+                    | Self::WindowViolation { .. }
+                    | Self::CapabilitiesInUse { .. }
+                    | Self::RecurrenceConflict { .. }
+                    | Self::NoSystemAvailable(_)
+            ),
+        }
+    }
+
+    /// The entries among the allocations in conflict, e.g. those to be rescheduled before an
+    /// outage can be inserted.
+    pub fn conflicting_entries(&self) -> Vec<Entry> {
+        let conflicts = match self {
+            Self::Conflict { conflicts }
+            | Self::CapacityExceeded { conflicts, .. }
+            | Self::CapabilityOutage { conflicts }
+            | Self::WindowViolation { conflicts }
+            | Self::CapabilitiesInUse { conflicts, .. } => conflicts,
+            Self::Batch { error, .. } => return error.conflicting_entries(),
+            _ => return Vec::new(),
+        };
+        conflicts
+            .iter()
+            .filter(|c| c.allocation.kind == AllocationKind::Entry)
+            .map(|c| {
+                let a = &c.allocation;
+                Entry {
+                    allocation_id: a.allocation_id,
+                    start_time: a.start_time,
+                    end_time: a.end_time,
+                    capabilities: a.capabilities,
+                    params: c.entry.clone().unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+
+    /// The allocations in conflict, being empty unless [`AllocationError::is_conflict`].
+    pub(crate) fn into_conflicts(self) -> Vec<ConflictInfo> {
+        match self {
+            Self::Conflict { conflicts }
+            | Self::CapacityExceeded { conflicts, .. }
+            | Self::CapabilityOutage { conflicts }
+            | Self::WindowViolation { conflicts }
+            | Self::CapabilitiesInUse { conflicts, .. } => conflicts,
+            Self::Batch { error, .. } => error.into_conflicts(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Attribute the error to the entry at `index` of a batch.
+    pub(crate) fn in_batch(self, index: usize) -> Self {
+        Self::Batch {
+            index,
+            error: Box::new(self),
+        }
+    }
+
+    /// Map an error from inserting or updating an allocation for `system` over `range` into the
+    /// variant describing the constraint or trigger that rejected it.
+    ///
+    /// `range` is `None` for open-ended allocations.
+    pub(crate) fn from_insert(
+        err: sqlx::Error,
+        system: Uuid,
+        range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> Self {
+        let Some(db_err) = err.as_database_error() else {
+            return Self::Database(err);
+        };
+        if matches!(
+            db_err.code().as_deref(),
+            Some(RAISE_EXCEPTION | EXCLUSION_VIOLATION)
+        ) {
+            // The checks preceding the write should have caught this, unless raced by another.
+            tracing::warn!(%system, reason = db_err.message(), "rejected by the database");
+        }
+        match (db_err.code().as_deref(), db_err.constraint(), range) {
+            (Some(FOREIGN_KEY_VIOLATION), Some(c), _) if c.ends_with("_system_id_fkey") => {
+                Self::SystemNotFound(system)
+            }
+            (Some(CHECK_VIOLATION), Some(c), Some((start, end)))
+                if c.ends_with("_time_range_check") =>
+            {
+                Self::InvalidRange { start, end }
+            }
+            // Taken by a concurrent insert, with the same idempotency key for another system.
+            (Some(UNIQUE_VIOLATION), Some("idempotency_keys_pkey"), _) => Self::Conflict {
+                conflicts: Vec::new(),
+            },
+            (Some(RAISE_EXCEPTION), _, _) if db_err.message() == CAPACITY_EXCEEDED_MESSAGE => {
+                Self::CapacityExceeded {
+                    conflicts: Vec::new(),
+                    occupancy: None,
+                }
+            }
+            (Some(RAISE_EXCEPTION | EXCLUSION_VIOLATION), _, _) => Self::Conflict {
+                conflicts: Vec::new(),
+            },
+            _ => Self::Database(err),
+        }
+    }
+}
@@ -0,0 +1,444 @@
+//! Allocating entries across a fleet of interchangeable systems.
+
+use std::collections::{hash_map, HashMap};
+
+use chrono::{DateTime, Utc};
+use sqlx::postgres::Postgres;
+use sqlx::{Acquire, Transaction};
+use uuid::Uuid;
+
+use crate::interval::interval_to_duration;
+use crate::reduction::{capacity_reductions, CapacityReduction};
+use crate::{
+    availability, capacity_peak, check_entry_capabilities, check_range, classify_conflicts,
+    decode_capabilities, Allocation, AllocationError, AllocationKind, Availability, BookingPolicy,
+    Capabilities, CapacityLimits, SystemAllocation,
+};
+
+impl SystemAllocation {
+    /// Find every system among `systems` which would accept an entry as with
+    /// [`SystemAllocation::insert_entry`], ordered by how loaded they are, least first.
+    ///
+    /// The load of a system is the peak number of concurrent entries over the timespan, relative
+    /// to its capacity. Systems that are not declared, do not provide all `capabilities`, or
+    /// whose booking policy rejects the entry, are left out. All systems are evaluated together,
+    /// by a fixed number of queries regardless of how many there are.
+    #[tracing::instrument(skip(self))]
+    pub async fn find_available_systems(
+        &self,
+        systems: &[Uuid],
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<Vec<Uuid>, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let available =
+            available_systems(&mut tx, systems, start, end, capabilities, self.now()).await?;
+        tx.rollback().await?;
+
+        Ok(available)
+    }
+
+    /// Free capacity for entries requiring `capabilities` on each of `systems` over the timespan
+    /// `[start, end)`, as with [`SystemAllocation::get_availability`].
+    ///
+    /// All systems are evaluated together, by a single query regardless of how many there are.
+    /// Fails with [`AllocationError::InvalidRange`] if `start` is not before `end`,
+    /// [`AllocationError::SystemNotFound`] if any of them has not been declared, and
+    /// [`AllocationError::UnsupportedCapabilities`] if any of them does not provide all of
+    /// `capabilities`.
+    #[tracing::instrument(skip(self))]
+    pub async fn availability_multi(
+        &self,
+        systems: &[Uuid],
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<HashMap<Uuid, Availability>, AllocationError> {
+        check_range(start, end)?;
+
+        // Every system is listed along with its limits, once for each of its allocations
+        // overlapping the timespan.
+        let rows = sqlx::query!(
+            r#"
+        SELECT s.system_id, s.capacity, s.capabilities AS supported,
+            c.capabilities AS limited, c.capacities AS limits,
+            r.starts AS reduction_starts,
+            r.ends AS "reduction_ends: Vec<Option<DateTime<Utc>>>",
+            r.window_ends AS "reduction_window_ends: Vec<Option<DateTime<Utc>>>",
+            r.reductions,
+            a.allocation_id AS "allocation_id?", a.kind AS "kind?: AllocationKind",
+            a.planned AS "planned?", a.start_time AS "start_time?",
+            NULLIF(a.end_time, 'infinity') AS end_time, a.capabilities AS "capabilities?"
+        FROM systems s
+        CROSS JOIN LATERAL (
+            SELECT array_agg(capability ORDER BY capability) AS capabilities,
+                array_agg(capacity ORDER BY capability) AS capacities
+            FROM system_capacities
+            WHERE system_id = s.system_id AND capability & $4 != 0
+        ) c
+        CROSS JOIN LATERAL (
+            SELECT array_agg(start_time ORDER BY start_time, reduction_id) AS starts,
+                array_agg(resolved_at ORDER BY start_time, reduction_id) AS ends,
+                array_agg(
+                    CASE WHEN resolved_at IS NULL THEN GREATEST(start_time, now()) + sliding_window
+                    END
+                    ORDER BY start_time, reduction_id
+                ) AS window_ends,
+                array_agg(reduction ORDER BY start_time, reduction_id) AS reductions
+            FROM capacity_reductions
+            WHERE system_id = s.system_id
+                AND start_time < $3
+                AND (resolved_at IS NULL OR resolved_at > $2)
+        ) r
+        LEFT JOIN allocations a ON a.system_id = s.system_id
+            AND $2 < a.end_time
+            AND $3 > a.start_time
+            AND NOT hold_expired(a.allocation_id)
+            AND NOT entry_finished(a.allocation_id)
+        WHERE s.system_id = ANY($1)
+        ORDER BY a.start_time
+            "#,
+            systems,
+            start,
+            end,
+            capabilities.bits() as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut limits = HashMap::new();
+        let mut overlapping: HashMap<Uuid, Vec<Allocation>> = HashMap::new();
+        for row in rows {
+            if let hash_map::Entry::Vacant(vacant) = limits.entry(row.system_id) {
+                let supported = decode_capabilities(row.supported)?;
+                if !supported.contains(capabilities) {
+                    return Err(AllocationError::UnsupportedCapabilities {
+                        requested: capabilities,
+                        supported,
+                    });
+                }
+                let limited = row.limited.unwrap_or_default().into_iter();
+                let reductions = row.reduction_starts.unwrap_or_default().into_iter();
+                let limit = CapacityLimits {
+                    system: row.capacity,
+                    capabilities: limited
+                        .map(decode_capabilities)
+                        .zip(row.limits.unwrap_or_default())
+                        .map(|(capability, capacity)| Ok((capability?, capacity)))
+                        .collect::<Result<_, AllocationError>>()?,
+                    reductions: reductions
+                        .zip(row.reduction_ends.unwrap_or_default())
+                        .zip(row.reduction_window_ends.unwrap_or_default())
+                        .zip(row.reductions.unwrap_or_default())
+                        .map(
+                            |(((start, end), window_end), reduction)| CapacityReduction {
+                                start,
+                                end,
+                                window_end,
+                                reduction,
+                            },
+                        )
+                        .collect(),
+                };
+                vacant.insert(limit);
+            }
+            let allocations = overlapping.entry(row.system_id).or_default();
+            if let (Some(allocation_id), Some(kind), Some(planned), Some(start_time), Some(bits)) = (
+                row.allocation_id,
+                row.kind,
+                row.planned,
+                row.start_time,
+                row.capabilities,
+            ) {
+                allocations.push(Allocation {
+                    system_id: row.system_id,
+                    allocation_id,
+                    kind,
+                    planned,
+                    start_time,
+                    end_time: row.end_time,
+                    capabilities: decode_capabilities(bits)?,
+                });
+            }
+        }
+        if let Some(missing) = systems.iter().find(|s| !limits.contains_key(s)) {
+            return Err(AllocationError::SystemNotFound(*missing));
+        }
+
+        Ok(limits
+            .into_iter()
+            .map(|(system, limits)| {
+                let allocations = overlapping.remove(&system).unwrap_or_default();
+                let free = availability(&limits, &allocations, start, end, capabilities);
+                (system, free)
+            })
+            .collect())
+    }
+
+    /// Insert an entry on the least loaded system among `systems` which accepts it, as found by
+    /// [`SystemAllocation::find_available_systems`], returning the system and the allocation id.
+    ///
+    /// Should another writer take the timeslot of that system first, the next system is tried.
+    /// Fails with [`AllocationError::NoSystemAvailable`] if none of them accepts the entry.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_entry_any(
+        &self,
+        systems: &[Uuid],
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<(Uuid, Uuid), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let inserted = self
+            .insert_entry_any_tx(&mut tx, systems, start, end, capabilities)
+            .await?;
+        tx.commit().await?;
+
+        Ok(inserted)
+    }
+
+    /// As [`SystemAllocation::insert_entry_any`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_entry_any_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        systems: &[Uuid],
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<(Uuid, Uuid), AllocationError> {
+        for system in
+            available_systems(&mut *tx, systems, start, end, capabilities, self.now()).await?
+        {
+            // Each attempt is made within a savepoint, as a rejected insert may abort the
+            // transaction.
+            let mut attempt = tx.begin().await?;
+            match self
+                .insert_entry_tx(&mut attempt, system, start, end, capabilities)
+                .await
+            {
+                Ok(allocation_id) => {
+                    attempt.commit().await?;
+                    return Ok((system, allocation_id));
+                }
+                Err(err) if err.is_conflict() => {
+                    tracing::debug!(%system, %err, "taken since evaluated");
+                    attempt.rollback().await?;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(AllocationError::NoSystemAvailable(systems.len()))
+    }
+
+    /// Insert an entry on the first system among `candidates` which accepts it, trying them in
+    /// the order given, and returning that system.
+    ///
+    /// Unlike [`SystemAllocation::insert_entry_any`], the load of the systems is not considered.
+    /// Fails with [`AllocationError::CapacityExceeded`] if none of them accepts the entry,
+    /// listing the conflicts of all of them, each of which names its system. Any other failure,
+    /// e.g. a candidate which has not been declared, fails right away.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_entry_on_any(
+        &self,
+        candidates: &[Uuid],
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<Uuid, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let system = self
+            .insert_entry_on_any_tx(&mut tx, candidates, start, end, capabilities)
+            .await?;
+        tx.commit().await?;
+
+        Ok(system)
+    }
+
+    /// As [`SystemAllocation::insert_entry_on_any`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_entry_on_any_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        candidates: &[Uuid],
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<Uuid, AllocationError> {
+        let mut conflicts = Vec::new();
+        for &system in candidates {
+            // As above, a rejected insert may abort the transaction.
+            let mut attempt = tx.begin().await?;
+            match self
+                .insert_entry_tx(&mut attempt, system, start, end, capabilities)
+                .await
+            {
+                Ok(_) => {
+                    attempt.commit().await?;
+                    return Ok(system);
+                }
+                Err(err) if err.is_conflict() => {
+                    tracing::debug!(%system, %err, "no room");
+                    attempt.rollback().await?;
+                    conflicts.extend(err.into_conflicts());
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(AllocationError::CapacityExceeded {
+            conflicts,
+            occupancy: None,
+        })
+    }
+}
+
+/// The systems among `systems` accepting an entry over `[start, end)` for `capabilities`,
+/// ordered by their load, see [`SystemAllocation::find_available_systems`].
+async fn available_systems(
+    tx: &mut Transaction<'_, Postgres>,
+    systems: &[Uuid],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    capabilities: Capabilities,
+    now: DateTime<Utc>,
+) -> Result<Vec<Uuid>, AllocationError> {
+    check_range(start, end)?;
+    check_entry_capabilities(capabilities)?;
+
+    let rows = sqlx::query!(
+        r#"
+    SELECT system_id, capacity, capabilities, min_notice, max_horizon
+    FROM systems
+    WHERE system_id = ANY($1)
+        "#,
+        systems,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut limits = HashMap::new();
+    for row in rows {
+        let policy = BookingPolicy {
+            min_notice: row.min_notice.map(interval_to_duration),
+            max_horizon: row.max_horizon.map(interval_to_duration),
+        };
+        if decode_capabilities(row.capabilities)?.contains(capabilities)
+            && policy.check(now, start).is_ok()
+        {
+            let limit = CapacityLimits {
+                system: row.capacity,
+                capabilities: Vec::new(),
+                reductions: Vec::new(),
+            };
+            limits.insert(row.system_id, limit);
+        }
+    }
+
+    load_limits(&mut *tx, systems, capabilities, &mut limits).await?;
+
+    let rows = sqlx::query!(
+        r#"
+    SELECT a.system_id, a.allocation_id, a.kind AS "kind: AllocationKind", a.planned,
+        a.start_time, NULLIF(a.end_time, 'infinity') AS end_time, a.capabilities
+    FROM allocations a
+    JOIN systems s USING (system_id)
+    WHERE a.system_id = ANY($1)
+        AND ($2 < a.end_time + COALESCE(s.boundary_gap, interval '0')
+            OR (s.boundary_policy = 'exclusive' AND $2 = a.end_time))
+        AND ($3::timestamptz + COALESCE(s.boundary_gap, interval '0') > a.start_time
+            OR (s.boundary_policy = 'exclusive' AND $3 = a.start_time))
+        AND NOT hold_expired(a.allocation_id)
+        AND NOT entry_finished(a.allocation_id)
+    ORDER BY a.start_time
+        "#,
+        systems,
+        start,
+        end,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut overlapping: HashMap<Uuid, Vec<Allocation>> = HashMap::new();
+    for row in rows {
+        overlapping
+            .entry(row.system_id)
+            .or_default()
+            .push(Allocation {
+                system_id: row.system_id,
+                allocation_id: row.allocation_id,
+                kind: row.kind,
+                planned: row.planned,
+                start_time: row.start_time,
+                end_time: row.end_time,
+                capabilities: decode_capabilities(row.capabilities)?,
+            });
+    }
+
+    // As (system, peak concurrent entries, capacity), in the order the systems were given.
+    let mut available: Vec<_> = systems
+        .iter()
+        .filter_map(|system| {
+            // Removed, such that a system listed twice is only evaluated once.
+            let limits = limits.remove(system)?;
+            let overlapping = overlapping.remove(system).unwrap_or_default();
+            let conflicts = classify_conflicts(
+                overlapping.iter().cloned(),
+                &limits,
+                (start, Some(end)),
+                capabilities,
+                None,
+                &[],
+            );
+            if !conflicts.is_empty() {
+                return None;
+            }
+            let entries: Vec<_> = overlapping
+                .into_iter()
+                .filter(|a| a.kind == AllocationKind::Entry)
+                .collect();
+            let peak = capacity_peak(&entries, start, end).concurrent as i64;
+            Some((*system, peak, limits.system.max(1) as i64))
+        })
+        .collect();
+    // Compare the ratios of load to capacity without dividing, keeping ties in order.
+    available.sort_by(|(_, a_peak, a_capacity), (_, b_peak, b_capacity)| {
+        (a_peak * b_capacity).cmp(&(b_peak * a_capacity))
+    });
+
+    Ok(available.into_iter().map(|(system, ..)| system).collect())
+}
+
+/// Add to the `limits` of each of `systems` the capacities of the capabilities among
+/// `capabilities`, and the capacity reductions, as [`CapacityLimits::load`] does for a single
+/// system. Systems without limits are skipped.
+async fn load_limits(
+    tx: &mut Transaction<'_, Postgres>,
+    systems: &[Uuid],
+    capabilities: Capabilities,
+    limits: &mut HashMap<Uuid, CapacityLimits>,
+) -> Result<(), AllocationError> {
+    let rows = sqlx::query!(
+        r#"
+    SELECT system_id, capability, capacity FROM system_capacities
+    WHERE system_id = ANY($1) AND capability & $2 != 0
+        "#,
+        systems,
+        capabilities.bits() as i64,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    for row in rows {
+        if let Some(limit) = limits.get_mut(&row.system_id) {
+            let capability = decode_capabilities(row.capability)?;
+            limit.capabilities.push((capability, row.capacity));
+        }
+    }
+    for (system, reductions) in capacity_reductions(&mut *tx, systems).await? {
+        if let Some(limit) = limits.get_mut(&system) {
+            limit.reductions = reductions;
+        }
+    }
+
+    Ok(())
+}
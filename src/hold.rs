@@ -0,0 +1,220 @@
+//! Tentative entries, held for a while before they are confirmed or released.
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::postgres::{types::PgInterval, Postgres};
+use sqlx::Transaction;
+use uuid::Uuid;
+
+use crate::{
+    allocation, booking_policy, check_entry_conflicts, check_range, AllocationError,
+    AllocationKind, Capabilities, NewAllocation, SystemAllocation,
+};
+
+/// Identifies a hold, being the allocation id of the entry it becomes once confirmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HoldId(pub Uuid);
+
+impl SystemAllocation {
+    /// Tentatively hold a timeslot on the system for `ttl`, e.g. while a booking is being paid.
+    ///
+    /// A hold is evaluated as with [`SystemAllocation::insert_entry`], and counts against the
+    /// capacity of the system exactly like an entry until it expires. It must be confirmed with
+    /// [`SystemAllocation::confirm_hold`] before then to become an entry.
+    #[tracing::instrument(skip(self))]
+    pub async fn hold_entry(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+        ttl: Duration,
+    ) -> Result<HoldId, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let hold = self
+            .hold_entry_tx(&mut tx, system, start, end, capabilities, ttl)
+            .await?;
+        tx.commit().await?;
+
+        Ok(hold)
+    }
+
+    /// As [`SystemAllocation::hold_entry`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn hold_entry_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+        ttl: Duration,
+    ) -> Result<HoldId, AllocationError> {
+        check_range(start, end)?;
+        let ttl = PgInterval::try_from(ttl)
+            .ok()
+            .filter(|_| ttl > Duration::zero())
+            .ok_or_else(|| AllocationError::Validation(format!("invalid hold ttl {ttl}")))?;
+        booking_policy(&mut *tx, system)
+            .await?
+            .check(self.now(), start)?;
+        check_entry_conflicts(tx, system, start, Some(end), capabilities, None).await?;
+
+        let allocation_id = Uuid::new_v4();
+        sqlx::query!(
+            r#"
+        INSERT INTO holds(allocation_id, expires_at) VALUES ($1, now() + $2)
+            "#,
+            allocation_id,
+            ttl,
+        )
+        .execute(&mut *tx)
+        .await?;
+        NewAllocation {
+            system,
+            allocation_id,
+            kind: AllocationKind::Entry,
+            planned: true,
+            start,
+            end: Some(end),
+            capabilities,
+        }
+        .insert(tx)
+        .await?;
+
+        Ok(HoldId(allocation_id))
+    }
+
+    /// Confirm a hold into an entry, returning the allocation id of the entry.
+    ///
+    /// Fails with [`AllocationError::NotFound`] if the hold has expired, or was released.
+    #[tracing::instrument(skip(self))]
+    pub async fn confirm_hold(&self, hold: HoldId) -> Result<Uuid, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let allocation_id = self.confirm_hold_tx(&mut tx, hold).await?;
+        tx.commit().await?;
+
+        Ok(allocation_id)
+    }
+
+    /// As [`SystemAllocation::confirm_hold`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn confirm_hold_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        hold: HoldId,
+    ) -> Result<Uuid, AllocationError> {
+        let HoldId(allocation_id) = hold;
+        let confirmed = sqlx::query!(
+            r#"
+        DELETE FROM holds WHERE allocation_id = $1 AND expires_at > now()
+            "#,
+            allocation_id,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        // The allocation is gone if the hold was removed as an entry, e.g. by a sweep.
+        let entry = match allocation(&mut *tx, allocation_id).await? {
+            Some(entry) if confirmed != 0 => entry,
+            _ => return Err(AllocationError::NotFound(allocation_id)),
+        };
+
+        sqlx::query!(
+            r#"
+        INSERT INTO entries(allocation_id, start_time, end_time) VALUES ($1, $2, $3)
+            "#,
+            allocation_id,
+            entry.start_time,
+            entry.end_time.expect("entries always have an end"),
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(allocation_id)
+    }
+
+    /// Release a hold before it expires, freeing its timeslot.
+    ///
+    /// Fails with [`AllocationError::NotFound`] if there is no such hold.
+    #[tracing::instrument(skip(self))]
+    pub async fn release_hold(&self, hold: HoldId) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.release_hold_tx(&mut tx, hold).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::release_hold`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn release_hold_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        hold: HoldId,
+    ) -> Result<(), AllocationError> {
+        let HoldId(allocation_id) = hold;
+        let released = sqlx::query!(
+            r#"
+        DELETE FROM holds WHERE allocation_id = $1
+            "#,
+            allocation_id,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if released == 0 {
+            return Err(AllocationError::NotFound(allocation_id));
+        }
+
+        sqlx::query!(
+            r#"
+        DELETE FROM allocations WHERE allocation_id = $1
+            "#,
+            allocation_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove all expired holds, returning how many were removed.
+    ///
+    /// Expired holds no longer count against the capacity of the system regardless, this only
+    /// cleans up after them. It is intended to run periodically.
+    #[tracing::instrument(skip(self))]
+    pub async fn expire_holds(&self) -> Result<u64, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let expired = self.expire_holds_tx(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(expired)
+    }
+
+    /// As [`SystemAllocation::expire_holds`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn expire_holds_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<u64, AllocationError> {
+        let expired = sqlx::query_scalar!(
+            r#"
+        DELETE FROM holds WHERE expires_at <= now() RETURNING allocation_id
+            "#,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+        DELETE FROM allocations WHERE allocation_id = ANY($1)
+            "#,
+            &expired,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(expired.len() as u64)
+    }
+}
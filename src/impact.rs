@@ -0,0 +1,187 @@
+//! Previewing what an outage would affect, without inserting it.
+
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use sqlx::postgres::Postgres;
+use sqlx::Transaction;
+use uuid::Uuid;
+
+use crate::{
+    check_range, entry_params, interval_to_duration, outage_conflicts_in, overlapping_allocations,
+    sliding_window_to_interval, system_capacity, AllocationError, AllocationKind, Blocker,
+    BoundaryPolicy, Capabilities, ConflictInfo, Entry, SystemAllocation,
+};
+
+/// What an outage would affect were it inserted, see
+/// [`SystemAllocation::preview_outage_impact`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImpactReport {
+    /// The entries in conflict, which must be cleared before the outage can be inserted, or are
+    /// removed by a forced unplanned outage. Ordered by their start.
+    pub entries: Vec<Entry>,
+    /// The total time booked by `entries` within the outage, or within the sliding window of an
+    /// unplanned outage.
+    #[cfg_attr(feature = "serde", serde(with = "crate::interval::serde_seconds"))]
+    pub booked: Duration,
+    /// The outages in the way, over which the outage can not be inserted regardless.
+    pub outages: Vec<ConflictInfo>,
+    /// The entries after the sliding window of an unplanned outage, which stay put until the
+    /// window slides over them. Always empty for a planned outage.
+    pub after_window: Vec<Entry>,
+}
+
+impl ImpactReport {
+    /// The entries in conflict grouped by their owner, with those without one under `None`.
+    pub fn by_owner(&self) -> BTreeMap<Option<Uuid>, Vec<&Entry>> {
+        let mut owners: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for entry in &self.entries {
+            owners.entry(entry.params.owner).or_default().push(entry);
+        }
+        owners
+    }
+}
+
+impl SystemAllocation {
+    /// Preview what a planned outage over `[start, end)` of `capabilities` would affect, without
+    /// inserting anything.
+    ///
+    /// This runs the same evaluation as [`SystemAllocation::insert_planned_capability_outage`],
+    /// or [`SystemAllocation::insert_planned_outage`] for [`Capabilities::all`], reporting the
+    /// entries to be cleared first and the outages in the way.
+    #[tracing::instrument(skip(self))]
+    pub async fn preview_outage_impact(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<ImpactReport, AllocationError> {
+        check_range(start, end)?;
+
+        let mut tx = self.pool.begin().await?;
+        // An outage of the entire system covers every capability, declared or not.
+        let required = if capabilities == Capabilities::all() {
+            Capabilities::empty()
+        } else {
+            capabilities
+        };
+        system_capacity(&mut tx, system, required).await?;
+        let conflicts = outage_conflicts_in(
+            &mut tx,
+            system,
+            (start, Some(end)),
+            None,
+            capabilities,
+            None,
+        )
+        .await?;
+        tx.rollback().await?;
+
+        Ok(impact(conflicts, Vec::new()))
+    }
+
+    /// Preview what an unplanned outage from `start` would affect, without inserting anything.
+    ///
+    /// This runs the same evaluation as [`SystemAllocation::insert_unplanned_outage`], reporting
+    /// the entries within the sliding window, which are to be cleared first or forcefully
+    /// removed, and those after it. The latter are left in place, while no entries may be added
+    /// alongside them once the outage has started.
+    #[tracing::instrument(skip(self))]
+    pub async fn preview_unplanned_impact(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        sliding_window: Duration,
+    ) -> Result<ImpactReport, AllocationError> {
+        let window = interval_to_duration(sliding_window_to_interval(sliding_window)?);
+        // Evaluate the window as the database will, with the start truncated to its precision.
+        let start = start
+            .duration_trunc(Duration::microseconds(1))
+            .unwrap_or(start);
+
+        let mut tx = self.pool.begin().await?;
+        system_capacity(&mut tx, system, Capabilities::empty()).await?;
+        let conflicts = outage_conflicts_in(
+            &mut tx,
+            system,
+            (start, None),
+            Some(window),
+            Capabilities::all(),
+            None,
+        )
+        .await?;
+        let after_window = match start.checked_add_signed(window) {
+            Some(window_end) => entries_after(&mut tx, system, window_end, &conflicts).await?,
+            None => Vec::new(),
+        };
+        tx.rollback().await?;
+
+        Ok(impact(conflicts, after_window))
+    }
+}
+
+/// The entries on `system` ongoing at or starting after `from`, other than those in `conflicts`.
+async fn entries_after(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    from: DateTime<Utc>,
+    conflicts: &[ConflictInfo],
+) -> Result<Vec<Entry>, AllocationError> {
+    let within: HashSet<_> = conflicts
+        .iter()
+        .map(|c| c.allocation.allocation_id)
+        .collect();
+    let allocations: Vec<_> = overlapping_allocations(
+        &mut *tx,
+        system,
+        from,
+        DateTime::<Utc>::MAX_UTC,
+        BoundaryPolicy::Touching,
+    )
+    .await?
+    .into_iter()
+    .filter(|a| a.kind == AllocationKind::Entry && !within.contains(&a.allocation_id))
+    .collect();
+    let ids: Vec<_> = allocations.iter().map(|a| a.allocation_id).collect();
+    let mut params = entry_params(&mut *tx, &ids).await?;
+
+    Ok(allocations
+        .into_iter()
+        .map(|a| Entry {
+            allocation_id: a.allocation_id,
+            start_time: a.start_time,
+            end_time: a.end_time,
+            capabilities: a.capabilities,
+            params: params.remove(&a.allocation_id).unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Sum up the `conflicts` of an outage into what it would affect.
+fn impact(conflicts: Vec<ConflictInfo>, after_window: Vec<Entry>) -> ImpactReport {
+    let (entries, outages): (Vec<_>, Vec<_>) = conflicts
+        .into_iter()
+        .partition(|c| c.blocker == Blocker::Entry);
+    let booked = entries
+        .iter()
+        .filter_map(|c| Some(c.overlap_end? - c.overlap_start))
+        .fold(Duration::zero(), |total, overlap| total + overlap);
+
+    ImpactReport {
+        entries: entries
+            .into_iter()
+            .map(|c| Entry {
+                allocation_id: c.allocation.allocation_id,
+                start_time: c.allocation.start_time,
+                end_time: c.allocation.end_time,
+                capabilities: c.allocation.capabilities,
+                params: c.entry.unwrap_or_default(),
+            })
+            .collect(),
+        booked,
+        outages,
+        after_window,
+    }
+}
@@ -0,0 +1,318 @@
+//! Conversions between chrono durations and Postgres intervals, and the overlap rules of the
+//! checks in the migrations, for evaluating them without a round trip to the database.
+//!
+//! Timespans are half-open, `[start, end)`, such that one ending exactly as another starts does
+//! not overlap it, unless the system is declared with [`BoundaryPolicy::Exclusive`] or
+//! [`BoundaryPolicy::Gap`]. An open-ended timespan has no end, as stored as `'infinity'`.
+//!
+//! Sliding windows are stored as an `interval minute`, which Postgres truncates to whole minutes.
+//! To never shorten the window an outage was inserted with, they are rounded up to whole minutes
+//! before being written. When read back, months are counted as 30 days, as that is what Postgres
+//! assumes when justifying intervals.
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::postgres::types::PgInterval;
+
+use crate::{AllocationEnd, AllocationError, BoundaryKind, BoundaryPolicy};
+
+const MICROSECONDS_PER_MINUTE: i64 = 60 * 1_000_000;
+
+/// Convert a sliding window to be written to the database, rounded up to whole minutes.
+///
+/// Fails with [`AllocationError::InvalidSlidingWindow`] unless the window is positive, as it must
+/// extend forward from the start of the outage, and small enough to be represented in
+/// microseconds.
+pub(crate) fn sliding_window_to_interval(
+    sliding_window: Duration,
+) -> Result<PgInterval, AllocationError> {
+    if sliding_window <= Duration::zero() {
+        return Err(AllocationError::InvalidSlidingWindow(sliding_window));
+    }
+    let microseconds = sliding_window
+        .num_microseconds()
+        .and_then(|us| {
+            let minutes =
+                us / MICROSECONDS_PER_MINUTE + i64::from(us % MICROSECONDS_PER_MINUTE != 0);
+            minutes.checked_mul(MICROSECONDS_PER_MINUTE)
+        })
+        .ok_or(AllocationError::InvalidSlidingWindow(sliding_window))?;
+
+    Ok(PgInterval {
+        months: 0,
+        days: 0,
+        microseconds,
+    })
+}
+
+/// Convert an interval read from the database.
+pub(crate) fn interval_to_duration(interval: PgInterval) -> Duration {
+    Duration::days(interval.months as i64 * 30 + interval.days as i64)
+        + Duration::microseconds(interval.microseconds)
+}
+
+/// Whether the half-open timespans `a` and `b` share any instant, where an end of `None` never
+/// ends.
+pub(crate) fn intervals_overlap(
+    (a_start, a_end): (DateTime<Utc>, Option<DateTime<Utc>>),
+    (b_start, b_end): (DateTime<Utc>, Option<DateTime<Utc>>),
+) -> bool {
+    a_end.is_none_or(|end| b_start < end) && b_end.is_none_or(|end| a_start < end)
+}
+
+impl BoundaryPolicy {
+    /// Whether the timespans `a` and `b` are in conflict under this policy, see
+    /// [`intervals_overlap`].
+    pub(crate) fn overlaps(
+        self,
+        a: (DateTime<Utc>, Option<DateTime<Utc>>),
+        b: (DateTime<Utc>, Option<DateTime<Utc>>),
+    ) -> bool {
+        match self {
+            BoundaryPolicy::Touching => intervals_overlap(a, b),
+            BoundaryPolicy::Exclusive => {
+                a.1.is_none_or(|end| b.0 <= end) && b.1.is_none_or(|end| a.0 <= end)
+            }
+            BoundaryPolicy::Gap(_) => {
+                let widen = |end: Option<DateTime<Utc>>| end.and_then(|end| self.after_gap(end));
+                intervals_overlap((a.0, widen(a.1)), (b.0, widen(b.1)))
+            }
+        }
+    }
+
+    /// The earliest start of a timespan following one ending at `end`, being a microsecond
+    /// later under [`BoundaryPolicy::Exclusive`], as that is what Postgres stores timestamps with.
+    pub(crate) fn next_start(self, end: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            BoundaryPolicy::Touching => end,
+            BoundaryPolicy::Exclusive => end + Duration::microseconds(1),
+            BoundaryPolicy::Gap(_) => self.after_gap(end).unwrap_or(DateTime::<Utc>::MAX_UTC),
+        }
+    }
+
+    /// The instant the gap following `end` is over, `None` if beyond the range of [`DateTime`].
+    fn after_gap(self, end: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        end.checked_add_signed(self.gap())
+    }
+
+    /// The gap of [`BoundaryPolicy::Gap`], being zero for the other policies.
+    fn gap(self) -> Duration {
+        match self {
+            BoundaryPolicy::Gap(gap) => gap,
+            BoundaryPolicy::Touching | BoundaryPolicy::Exclusive => Duration::zero(),
+        }
+    }
+
+    /// The gap as an interval for the overlap checks in queries.
+    pub(crate) fn gap_interval(self) -> PgInterval {
+        PgInterval {
+            months: 0,
+            days: 0,
+            microseconds: self.gap().num_microseconds().unwrap_or(i64::MAX),
+        }
+    }
+
+    /// The columns of the policy to be written to the database.
+    ///
+    /// Fails with [`AllocationError::Validation`] unless the gap is positive, and representable
+    /// in microseconds.
+    pub(crate) fn to_columns(self) -> Result<(BoundaryKind, Option<PgInterval>), AllocationError> {
+        match self {
+            BoundaryPolicy::Touching => Ok((BoundaryKind::Touching, None)),
+            BoundaryPolicy::Exclusive => Ok((BoundaryKind::Exclusive, None)),
+            BoundaryPolicy::Gap(gap) => {
+                let interval = PgInterval::try_from(gap)
+                    .ok()
+                    .filter(|_| gap > Duration::zero())
+                    .ok_or_else(|| {
+                        AllocationError::Validation(format!("invalid boundary gap {gap}"))
+                    })?;
+                Ok((BoundaryKind::Gap, Some(interval)))
+            }
+        }
+    }
+
+    /// The policy as read from the columns written by [`BoundaryPolicy::to_columns`].
+    pub(crate) fn from_columns(kind: BoundaryKind, gap: Option<PgInterval>) -> Self {
+        match (kind, gap) {
+            (BoundaryKind::Exclusive, _) => BoundaryPolicy::Exclusive,
+            (BoundaryKind::Gap, Some(gap)) => BoundaryPolicy::Gap(interval_to_duration(gap)),
+            (BoundaryKind::Touching | BoundaryKind::Gap, _) => BoundaryPolicy::Touching,
+        }
+    }
+}
+
+impl AllocationEnd {
+    /// The instant the allocation ends at, `None` if it never does.
+    pub fn at(self) -> Option<DateTime<Utc>> {
+        match self {
+            AllocationEnd::At(end) => Some(end),
+            AllocationEnd::Never => None,
+        }
+    }
+}
+
+impl From<Option<DateTime<Utc>>> for AllocationEnd {
+    fn from(end: Option<DateTime<Utc>>) -> Self {
+        end.map_or(AllocationEnd::Never, AllocationEnd::At)
+    }
+}
+
+impl From<AllocationEnd> for Option<DateTime<Utc>> {
+    fn from(end: AllocationEnd) -> Self {
+        end.at()
+    }
+}
+
+/// Whether `instant` is within the sliding window of an unplanned outage starting at
+/// `outage_start`, being `[outage_start, outage_start + window)`.
+///
+/// A window reaching past the range of [`DateTime`] never ends.
+pub(crate) fn in_sliding_window(
+    outage_start: DateTime<Utc>,
+    window: Duration,
+    instant: DateTime<Utc>,
+) -> bool {
+    outage_start <= instant
+        && outage_start
+            .checked_add_signed(window)
+            .is_none_or(|end| instant < end)
+}
+
+/// (De)serialization of durations as whole seconds, being what sliding windows are stored with
+/// at the least.
+#[cfg(feature = "serde")]
+pub(crate) mod serde_seconds {
+    use chrono::Duration;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(duration.num_seconds())
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        let seconds = i64::deserialize(deserializer)?;
+        if seconds.unsigned_abs() > Duration::max_value().num_seconds().unsigned_abs() {
+            return Err(serde::de::Error::custom(format!(
+                "{seconds} seconds is out of range"
+            )));
+        }
+        Ok(Duration::seconds(seconds))
+    }
+
+    /// As the enclosing module, for optional durations.
+    pub(crate) mod option {
+        use chrono::Duration;
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub(crate) fn serialize<S: Serializer>(
+            duration: &Option<Duration>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match duration {
+                Some(duration) => super::serialize(duration, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Duration>, D::Error> {
+            #[derive(Deserialize)]
+            struct Seconds(#[serde(with = "super")] Duration);
+
+            Ok(Option::<Seconds>::deserialize(deserializer)?.map(|Seconds(d)| d))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2022, 12, 1, hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn overlap_is_half_open() {
+        let span = (at(2), Some(at(4)));
+        assert!(intervals_overlap(span, (at(3), Some(at(5)))));
+        assert!(intervals_overlap(span, (at(1), Some(at(5)))));
+        assert!(intervals_overlap(span, span));
+
+        // Touching at either boundary is not an overlap
+        assert!(!intervals_overlap(span, (at(4), Some(at(5)))));
+        assert!(!intervals_overlap(span, (at(1), Some(at(2)))));
+        assert!(!intervals_overlap((at(4), Some(at(5))), span));
+    }
+
+    #[test]
+    fn overlap_open_ended() {
+        let open = (at(2), None);
+        assert!(intervals_overlap(open, (at(8), Some(at(9)))));
+        assert!(intervals_overlap(open, (at(1), Some(at(3)))));
+        assert!(intervals_overlap(open, (at(5), None)));
+        assert!(!intervals_overlap(open, (at(1), Some(at(2)))));
+    }
+
+    #[test]
+    fn allocation_end() {
+        assert!(AllocationEnd::At(at(2)) < AllocationEnd::At(at(3)));
+        assert!(AllocationEnd::At(at(23)) < AllocationEnd::Never);
+        assert_eq!(AllocationEnd::from(Some(at(2))), AllocationEnd::At(at(2)));
+        assert_eq!(AllocationEnd::from(None), AllocationEnd::Never);
+        assert_eq!(AllocationEnd::Never.at(), None);
+    }
+
+    #[test]
+    fn overlap_exclusive() {
+        let span = (at(2), Some(at(4)));
+        for policy in [BoundaryPolicy::Touching, BoundaryPolicy::Exclusive] {
+            assert!(policy.overlaps(span, (at(3), Some(at(5)))));
+            assert!(!policy.overlaps(span, (at(5), Some(at(6)))));
+            assert!(policy.overlaps((at(1), None), span));
+        }
+
+        let gap = BoundaryPolicy::Gap(Duration::hours(1));
+        assert!(gap.overlaps(span, (at(4), Some(at(5)))));
+        assert!(gap.overlaps(
+            (at(5), Some(at(6))),
+            (at(1), Some(at(4) + Duration::minutes(1)))
+        ));
+        assert!(!gap.overlaps(span, (at(5), Some(at(6)))));
+        assert!(!gap.overlaps((at(0), Some(at(1))), span));
+        assert!(gap.overlaps((at(1), None), (at(5), Some(at(6)))));
+        assert_eq!(gap.next_start(at(4)), at(5));
+
+        let exclusive = BoundaryPolicy::Exclusive;
+        assert!(exclusive.overlaps(span, (at(4), Some(at(5)))));
+        assert!(exclusive.overlaps(span, (at(1), Some(at(2)))));
+        assert!(exclusive.overlaps((at(4), None), span));
+        assert!(!exclusive.overlaps(span, (exclusive.next_start(at(4)), Some(at(5)))));
+    }
+
+    #[test]
+    fn sliding_window_boundaries() {
+        let window = Duration::hours(2);
+        assert!(in_sliding_window(at(2), window, at(2)));
+        assert!(in_sliding_window(at(2), window, at(3)));
+        assert!(!in_sliding_window(at(2), window, at(4)));
+        assert!(!in_sliding_window(at(2), window, at(1)));
+
+        // An empty window covers nothing, not even its start
+        assert!(!in_sliding_window(at(2), Duration::zero(), at(2)));
+
+        assert!(in_sliding_window(
+            at(2),
+            Duration::max_value(),
+            DateTime::<Utc>::MAX_UTC
+        ));
+    }
+}
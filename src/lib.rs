@@ -3,226 +3,4281 @@
 //! This mechanism ensures:
 //! - A single point in time is only covered by N entries.
 //! - There are multiple capabilities where some may have total outage, disallowing any entries at
-//! that point in time.
+//!   that point in time.
 //! - An unplanned outage will disallow overlaps in a sliding window forward.
 //!     * On insert, all entries in conflict from start of unplanned outage plus the sliding window
-//!     must be cleared prior to allowing the unplanned outage to be entered.
+//!       must be cleared prior to allowing the unplanned outage to be entered.
 //!     * It is NOT possible to _add_ entries outside the window
 //!     * it SHOULD be possible to _modify_ entries outside window
 //!
 //!  - A contineous job should run to pick up any entries that fall within the window,
-//!  by forcfully removing them.
+//!    by forcfully removing them.
 //!
 
-use bitflags::bitflags;
-use chrono::{DateTime, Duration, Utc};
-use sqlx::postgres::{types::PgInterval, PgPool};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use chrono_tz::Tz;
+use sqlx::postgres::{types::PgInterval, PgExecutor, PgPool, Postgres};
+use sqlx::Transaction;
 use uuid::Uuid;
 
-bitflags! {
-    #[derive(Default)]
-    pub struct Capabilities: u32 {
-        const A = 0b00000001;
-        const B = 0b00000010;
-        const C = 0b00000100;
-    }
-}
+mod booking;
+mod capabilities;
+#[cfg(feature = "notify")]
+mod changes;
+mod consistency;
+mod error;
+mod fleet;
+mod hold;
+mod impact;
+mod interval;
+mod lifecycle;
+mod maintenance;
+mod preemption;
+mod purge;
+mod recurrence;
+mod reduction;
+mod reschedule;
+mod schema;
+mod shift;
+mod store;
+mod suggest;
+mod sweep;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+mod utilization;
+mod waitlist;
+
+pub use booking::BookingPolicy;
+pub use capabilities::{Capabilities, ParseCapabilitiesError};
+#[cfg(feature = "notify")]
+pub use changes::AllocationEvent;
+pub use consistency::{ConsistencyReport, Inconsistency, MirroredColumns, SourceTable};
+pub use error::AllocationError;
+pub use hold::HoldId;
+pub use impact::ImpactReport;
+pub use lifecycle::EntryStatus;
+pub use maintenance::{MaintenanceSchedule, OccurrenceConflict, RecurringOutage};
+pub use recurrence::Recurrence;
+pub use reschedule::{EntryMove, ReschedulePolicy};
+pub use schema::MIGRATOR;
+pub use store::AllocationStore;
+pub use suggest::SuggestedWindow;
+pub use sweep::{Eviction, SweepReport};
+pub use utilization::UtilizationBucket;
+pub use waitlist::{WaitlistId, WaitlistRequest};
 
-#[derive(Debug, sqlx::Type)]
+use booking::booking_policy;
+use interval::{
+    in_sliding_window, interval_to_duration, intervals_overlap, sliding_window_to_interval,
+};
+use reduction::{capacity_reductions, peak_reduction, CapacityReduction};
+use waitlist::promote_waitlist;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 #[sqlx(type_name = "allocation_kind", rename_all = "lowercase")]
-enum AllocationKind {
+pub enum AllocationKind {
     Entry,
     Full,
     Capability,
 }
 
+/// Whether allocations on a system may touch, see
+/// [`SystemAllocation::declare_system_with_boundary_policy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum BoundaryPolicy {
+    /// Timespans are half-open, `[start, end)`, such that one may start exactly as another ends.
+    #[default]
+    Touching,
+    /// Timespans are closed, `[start, end]`, such that one ending exactly as another starts is in
+    /// conflict with it.
+    Exclusive,
+    /// At least the given, positive, duration must be left between the end of one timespan and
+    /// the start of another, such that one starting any earlier is in conflict with it.
+    Gap(#[cfg_attr(feature = "serde", serde(with = "interval::serde_seconds"))] Duration),
+}
+
+/// The `boundary_policy` column of a system, with the duration of [`BoundaryPolicy::Gap`] kept
+/// in the `boundary_gap` column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "boundary_policy", rename_all = "lowercase")]
+enum BoundaryKind {
+    Touching,
+    Exclusive,
+    Gap,
+}
+
+/// A single row of the allocations table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Allocation {
+    pub system_id: Uuid,
+    pub allocation_id: Uuid,
+    pub kind: AllocationKind,
+    /// `false` for unplanned outages.
+    pub planned: bool,
+    pub start_time: DateTime<Utc>,
+    /// `None` when the allocation has no known end, e.g. an unresolved unplanned outage.
+    pub end_time: Option<DateTime<Utc>>,
+    pub capabilities: Capabilities,
+}
+
+/// The end of an allocation, see [`Allocation::end`] and [`Outage::end`].
+///
+/// Ordered such that [`AllocationEnd::Never`] is after any instant. It is written to the
+/// database as `'infinity'`, which is never handed out as an instant itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AllocationEnd {
+    At(DateTime<Utc>),
+    /// An unresolved unplanned outage, or an open-ended entry.
+    Never,
+}
+
+impl Allocation {
+    /// When the allocation ends, as [`Allocation::end_time`] with the open end spelled out.
+    pub fn end(&self) -> AllocationEnd {
+        self.end_time.into()
+    }
+}
+
+/// An entry occupying a timeslot on a system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Entry {
+    pub allocation_id: Uuid,
+    pub start_time: DateTime<Utc>,
+    /// `None` until an open-ended entry is closed, see [`SystemAllocation::insert_open_entry`].
+    pub end_time: Option<DateTime<Utc>>,
+    pub capabilities: Capabilities,
+    /// Whom the entry belongs to and what it is for, as given when inserted.
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub params: EntryParams,
+}
+
+impl Entry {
+    /// The start and end of the entry in `timezone`, e.g. that of its system, see
+    /// [`SystemAllocation::get_system_timezone`].
+    pub fn local_times(&self, timezone: Tz) -> (DateTime<Tz>, Option<DateTime<Tz>>) {
+        (
+            self.start_time.with_timezone(&timezone),
+            self.end_time.map(|end| end.with_timezone(&timezone)),
+        )
+    }
+}
+
+/// The optional details of an entry, identifying whom to notify should it be removed.
+///
+/// These are not evaluated when inserting, see [`SystemAllocation::insert_entry_with_params`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EntryParams {
+    pub owner: Option<Uuid>,
+    /// A human readable description, e.g. the name of the booking.
+    pub label: Option<String>,
+    /// An arbitrary payload for the caller, stored as is.
+    pub metadata: Option<serde_json::Value>,
+    /// Never forcefully removed, see [`SystemAllocation::set_pinned`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pinned: bool,
+    /// Only displaced by entries of a higher priority, see
+    /// [`SystemAllocation::insert_entry_preempting`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub priority: i32,
+    /// Where the entry is in its lifecycle, see [`SystemAllocation::start_entry`]. Ignored when
+    /// inserting, as every entry starts out scheduled.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub status: EntryStatus,
+    /// Why the entry was cancelled, see [`SystemAllocation::cancel_entry`]. Ignored when
+    /// inserting.
+    pub cancel_reason: Option<String>,
+}
+
+impl EntryParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn owner(mut self, owner: Uuid) -> Self {
+        self.owner = Some(owner);
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn pinned(mut self, pinned: bool) -> Self {
+        self.pinned = pinned;
+        self
+    }
+
+    /// Only displaced by entries of a higher priority, see
+    /// [`SystemAllocation::insert_entry_preempting`].
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+/// An outage registered on a system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Outage {
+    /// A planned outage of the entire system.
+    Planned {
+        allocation_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    },
+    /// A planned outage of a subset of the system capabilities.
+    Capability {
+        allocation_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    },
+    /// An unplanned outage, with no known end until it has been resolved.
+    Unplanned {
+        allocation_id: Uuid,
+        start: DateTime<Utc>,
+        #[cfg_attr(feature = "serde", serde(with = "interval::serde_seconds"))]
+        sliding_window: Duration,
+        resolved_at: Option<DateTime<Utc>>,
+        /// All of them, unless only some capabilities are in outage.
+        capabilities: Capabilities,
+    },
+}
+
+/// The outcome of evaluating whether an entry fits on a system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FitResult {
+    Fits,
+    /// The entry would be rejected, in conflict with the listed allocations.
+    Conflicts(Vec<ConflictInfo>),
+}
+
+/// An allocation in the way of an entry or outage, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConflictInfo {
+    pub allocation: Allocation,
+    pub blocker: Blocker,
+    /// The start of the part of the requested timespan the allocation overlaps.
+    pub overlap_start: DateTime<Utc>,
+    /// The end of the overlap, `None` if both the request and the allocation are open-ended.
+    pub overlap_end: Option<DateTime<Utc>>,
+    /// The requested capabilities the allocation shares, empty for an entry only sharing the
+    /// system capacity.
+    pub capabilities: Capabilities,
+    /// Where the capacity is exceeded within the overlap, for [`Blocker::Capacity`] only.
+    pub peak: Option<CapacityPeak>,
+    /// The details of an entry in the way, `None` for outages and holds.
+    pub entry: Option<EntryParams>,
+}
+
+/// The instant at which the most entries are concurrent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CapacityPeak {
+    pub at: DateTime<Utc>,
+    /// The number of existing entries at that instant.
+    pub concurrent: usize,
+}
+
+/// How much of a capacity is taken where an entry would exceed it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CapacityOccupancy {
+    /// The number of existing entries sharing the capacity at its busiest within the entry.
+    pub occupied: i32,
+    /// The capacity exceeded, be it of the system or of one of the capabilities of the entry.
+    pub capacity: i32,
+    /// The capability whose capacity is exceeded, `None` for the capacity of the system.
+    pub capability: Option<Capabilities>,
+    /// The first timespan within the entry where `occupied` entries are concurrent, with
+    /// `peak_end` being `None` if they all are open-ended along with the entry.
+    pub peak_start: DateTime<Utc>,
+    pub peak_end: Option<DateTime<Utc>>,
+}
+
+/// The reason an allocation is in the way of an entry or outage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Blocker {
+    /// An entry sharing the system capacity, which would be exceeded.
+    Capacity,
+    /// An entry sharing some of the capabilities of an outage, which must be cleared first.
+    Entry,
+    /// A planned outage of the entire system.
+    PlannedOutage,
+    /// A planned outage sharing some of the capabilities of the entry.
+    CapabilityOutage,
+    /// An unplanned outage, disallowing entries from its start or within its sliding window.
+    UnplannedWindow,
+}
+
+impl ConflictInfo {
+    /// The conflict with `allocation` of a request over `[start, end)` for `capabilities`, with
+    /// `end` being `None` for an open-ended request.
+    fn new(
+        allocation: Allocation,
+        blocker: Blocker,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+        capabilities: Capabilities,
+    ) -> Self {
+        let overlap_end = match (allocation.end_time, end) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        Self {
+            overlap_start: allocation.start_time.max(start),
+            overlap_end,
+            capabilities: allocation.capabilities & capabilities,
+            allocation,
+            blocker,
+            peak: None,
+            entry: None,
+        }
+    }
+}
+
+impl Blocker {
+    /// The reason `allocation` is in the way for anything but the system capacity.
+    fn of(allocation: &Allocation) -> Self {
+        match (allocation.kind, allocation.planned) {
+            (AllocationKind::Entry, _) => Blocker::Entry,
+            (_, false) => Blocker::UnplannedWindow,
+            (AllocationKind::Capability, true) => Blocker::CapabilityOutage,
+            (AllocationKind::Full, true) => Blocker::PlannedOutage,
+        }
+    }
+}
+
+impl Outage {
+    pub fn allocation_id(&self) -> Uuid {
+        match self {
+            Outage::Planned { allocation_id, .. }
+            | Outage::Capability { allocation_id, .. }
+            | Outage::Unplanned { allocation_id, .. } => *allocation_id,
+        }
+    }
+
+    pub fn start(&self) -> DateTime<Utc> {
+        match self {
+            Outage::Planned { start, .. }
+            | Outage::Capability { start, .. }
+            | Outage::Unplanned { start, .. } => *start,
+        }
+    }
+
+    /// [`AllocationEnd::Never`] for an unresolved unplanned outage.
+    pub fn end(&self) -> AllocationEnd {
+        match self {
+            Outage::Planned { end, .. } | Outage::Capability { end, .. } => AllocationEnd::At(*end),
+            Outage::Unplanned { resolved_at, .. } => (*resolved_at).into(),
+        }
+    }
+
+    /// The capabilities in outage, being all of them for a planned outage of the entire system.
+    pub fn capabilities(&self) -> Capabilities {
+        match self {
+            Outage::Capability { capabilities, .. } | Outage::Unplanned { capabilities, .. } => {
+                *capabilities
+            }
+            Outage::Planned { .. } => Capabilities::all(),
+        }
+    }
+}
+
+/// The occurrences of a recurring entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecurringEntry {
+    /// The recurrence group shared by all occurrences.
+    pub group_id: Uuid,
+    /// The allocation ids of the occurrences, in order.
+    pub allocation_ids: Vec<Uuid>,
+    /// The starts of the occurrences skipped for being in conflict, see
+    /// [`SystemAllocation::insert_recurring_schedule`].
+    pub skipped: Vec<DateTime<Utc>>,
+}
+
+/// The configuration of a declared system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SystemInfo {
+    /// The concurrent capacity of entries on the system.
+    pub capacity: i32,
+    /// The capabilities the system provides.
+    pub capabilities: Capabilities,
+    /// See [`SystemAllocation::set_default_sliding_window`].
+    #[cfg_attr(feature = "serde", serde(with = "interval::serde_seconds::option"))]
+    pub default_sliding_window: Option<Duration>,
+    /// See [`SystemAllocation::set_booking_policy`].
+    #[cfg_attr(feature = "serde", serde(with = "interval::serde_seconds::option"))]
+    pub min_notice: Option<Duration>,
+    /// See [`SystemAllocation::set_booking_policy`].
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, with = "interval::serde_seconds::option")
+    )]
+    pub max_horizon: Option<Duration>,
+    /// See [`SystemAllocation::declare_system_with_boundary_policy`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub boundary_policy: BoundaryPolicy,
+    /// See [`SystemAllocation::set_timezone`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub timezone: Option<Tz>,
+}
+
+/// Allocations of the systems within a database.
+///
+/// Every operation modifying allocations has a `_tx` variant, running within a transaction of
+/// the caller rather than one of its own, such that it may be composed with other writes. The
+/// transaction should be rolled back if the operation fails, as the database may have aborted it.
 pub struct SystemAllocation {
     pool: PgPool,
+    clock: Arc<dyn Fn() -> DateTime<Utc> + Send + Sync>,
 }
 
 impl SystemAllocation {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            clock: Arc::new(Utc::now),
+        }
+    }
+
+    /// Take the current time from `clock` when evaluating the booking policy of a system, see
+    /// [`SystemAllocation::set_booking_policy`], e.g. to pin it in tests.
+    ///
+    /// Everything evaluated by the database, such as the sliding windows of unplanned outages
+    /// and the expiry of holds, still follows the clock of the database.
+    pub fn with_clock(mut self, clock: impl Fn() -> DateTime<Utc> + Send + Sync + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// The current time, as told by the clock, see [`SystemAllocation::with_clock`].
+    fn now(&self) -> DateTime<Utc> {
+        (self.clock)()
     }
 }
 
 impl SystemAllocation {
+    #[tracing::instrument(skip(self))]
     pub async fn declare_system(
         &self,
         system: Uuid,
         capacity: i32,
         capabilities: Capabilities,
-    ) -> Result<(), anyhow::Error> {
-        sqlx::query!(
-            r#"
-        INSERT INTO systems(system_id, capacity, capabilities) VALUES ($1, $2, $3)
-            "#,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.declare_system_tx(&mut tx, system, capacity, capabilities)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::declare_system`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn declare_system_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        capacity: i32,
+        capabilities: Capabilities,
+    ) -> Result<(), AllocationError> {
+        self.declare_system_with_boundary_policy_tx(
+            tx,
             system,
             capacity,
-            // NOTE: postgres lacks unsigned types, so lets hope this conversion is actually legit
-            capabilities.bits() as i32,
+            capabilities,
+            BoundaryPolicy::default(),
         )
-        .execute(&self.pool)
         .await
-        .map(|_| ())
-        .map_err(anyhow::Error::from)
     }
 
-    /// Insert a single entry to occupy a timeslot on the system.
-    pub async fn insert_entry(
+    /// Declare a system as with [`SystemAllocation::declare_system`], where allocations meeting
+    /// at a boundary are treated according to `boundary_policy`.
+    ///
+    /// Under [`BoundaryPolicy::Exclusive`], an entry ending exactly as another allocation starts,
+    /// or starting exactly as one ends, is in conflict with it, as if they overlapped. The same
+    /// holds for outages, such that a gap is always left between allocations in conflict. Under
+    /// [`BoundaryPolicy::Gap`], that gap must be at least as long as given, failing with
+    /// [`AllocationError::Validation`] unless it is positive.
+    #[tracing::instrument(skip(self))]
+    pub async fn declare_system_with_boundary_policy(
         &self,
         system: Uuid,
-        start: DateTime<Utc>,
-        end: DateTime<Utc>,
+        capacity: i32,
         capabilities: Capabilities,
-    ) -> Result<(), anyhow::Error> {
-        let allocation_id = Uuid::new_v4();
-        sqlx::query!(
-            r#"
-        INSERT INTO entries(allocation_id, start_time, end_time)
-        VALUES ($1, $2, $3)
-            "#,
-            allocation_id,
-            start,
-            end
+        boundary_policy: BoundaryPolicy,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.declare_system_with_boundary_policy_tx(
+            &mut tx,
+            system,
+            capacity,
+            capabilities,
+            boundary_policy,
         )
-        .execute(&self.pool)
         .await?;
-
-        sqlx::query!(
-            r#"
-        INSERT INTO allocations(system_id, allocation_id, kind, planned, start_time, end_time, capabilities)
-        VALUES ($1, $2, $3, true, $4, $5, $6)
-            "#,
-            system, allocation_id, AllocationKind::Entry as _, start, end, capabilities.bits() as i32
-        ).execute(&self.pool).await?;
+        tx.commit().await?;
 
         Ok(())
     }
 
-    /// Inserting a planned outage for the duration (start, end).
-    ///
-    /// This outage _must_ resolve all conflicts. No partial capability downtimes allowed.
-    /// This function will fail if any items are in conflict.
-    pub async fn insert_planned_outage(
+    /// As [`SystemAllocation::declare_system_with_boundary_policy`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn declare_system_with_boundary_policy_tx(
         &self,
+        tx: &mut Transaction<'_, Postgres>,
         system: Uuid,
-        start: DateTime<Utc>,
-        end: DateTime<Utc>,
-    ) -> Result<(), anyhow::Error> {
-        let allocation_id = Uuid::new_v4();
-        let capabilities = Capabilities::all().bits() as i32;
+        capacity: i32,
+        capabilities: Capabilities,
+        boundary_policy: BoundaryPolicy,
+    ) -> Result<(), AllocationError> {
+        let (kind, gap) = boundary_policy.to_columns()?;
         sqlx::query!(
             r#"
-        INSERT INTO planned(allocation_id, system_id, start_time, end_time, capabilities)
+        INSERT INTO systems(system_id, capacity, capabilities, boundary_policy, boundary_gap)
         VALUES ($1, $2, $3, $4, $5)
             "#,
-            allocation_id,
             system,
-            start,
-            end,
-            capabilities,
+            capacity,
+            // NOTE: postgres lacks unsigned types, so lets hope this conversion is actually legit
+            capabilities.bits() as i64,
+            kind as _,
+            gap,
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
 
+        Ok(())
+    }
+
+    /// Declare a system with a separate concurrent capacity for each individual capability.
+    ///
+    /// An entry requiring several capabilities occupies a slot of each of them. The total
+    /// capacity of the system is the sum of the capabilities, and the declared capabilities
+    /// are those given a capacity.
+    #[tracing::instrument(skip(self))]
+    pub async fn declare_system_with_capacities(
+        &self,
+        system: Uuid,
+        capacities: &[(Capabilities, i32)],
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.declare_system_with_capacities_tx(&mut tx, system, capacities)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::declare_system_with_capacities`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn declare_system_with_capacities_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        capacities: &[(Capabilities, i32)],
+    ) -> Result<(), AllocationError> {
+        if let Some((capabilities, _)) = capacities.iter().find(|(c, _)| c.bits().count_ones() != 1)
+        {
+            return Err(AllocationError::Validation(format!(
+                "capacity must be given for a single capability, not {capabilities:?}"
+            )));
+        }
+
+        let capacity: i32 = capacities.iter().map(|(_, capacity)| capacity).sum();
+        let capabilities = capacities
+            .iter()
+            .fold(Capabilities::empty(), |all, (c, _)| all | *c);
+
         sqlx::query!(
             r#"
-        INSERT INTO allocations(system_id, allocation_id, kind, planned, start_time, end_time, capabilities)
-        VALUES ($1, $2, $3, true, $4, $5, $6)
+        INSERT INTO systems(system_id, capacity, capabilities) VALUES ($1, $2, $3)
             "#,
             system,
-            allocation_id,
-            AllocationKind::Full as _,
-            start,
-            end,
-            capabilities,
-        ).execute(&self.pool)
+            capacity,
+            capabilities.bits() as i64,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        for (capability, capacity) in capacities {
+            sqlx::query!(
+                r#"
+            INSERT INTO system_capacities(system_id, capability, capacity) VALUES ($1, $2, $3)
+                "#,
+                system,
+                capability.bits() as i64,
+                capacity,
+            )
+            .execute(&mut *tx)
             .await?;
+        }
 
         Ok(())
     }
 
-    /// Only those in conflict from start + sliding_window duration will be evaluated to be
-    /// enforced to re-allocate before this unplanned outage can be successfully inserted.
-    pub async fn insert_unplanned_outage(
+    /// Set the sliding window of unplanned outages on the system inserted with
+    /// [`SystemAllocation::insert_unplanned_outage_default`].
+    ///
+    /// The window is rounded up to whole minutes, as with
+    /// [`SystemAllocation::insert_unplanned_outage`].
+    #[tracing::instrument(skip(self))]
+    pub async fn set_default_sliding_window(
         &self,
         system: Uuid,
-        start: DateTime<Utc>,
         sliding_window: Duration,
-    ) -> Result<(), anyhow::Error> {
-        let allocation_id = Uuid::new_v4();
-        let capabilities = Capabilities::all().bits() as i32;
-        sqlx::query!(
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.set_default_sliding_window_tx(&mut tx, system, sliding_window)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::set_default_sliding_window`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn set_default_sliding_window_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        sliding_window: Duration,
+    ) -> Result<(), AllocationError> {
+        let interval = sliding_window_to_interval(sliding_window)?;
+        let updated = sqlx::query!(
             r#"
-        INSERT INTO unplanned (allocation_id, system_id, start_time, sliding_window, capabilities)
-        VALUES ($1, $2, $3, $4, $5)
+        UPDATE systems SET default_sliding_window = $2 WHERE system_id = $1
             "#,
-            allocation_id,
             system,
-            start,
-            PgInterval::try_from(sliding_window).unwrap(),
-            capabilities,
+            interval,
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
+        if updated.rows_affected() == 0 {
+            return Err(AllocationError::SystemNotFound(system));
+        }
 
-        sqlx::query!(
+        Ok(())
+    }
+
+    /// Set how far ahead of time entries must be inserted on the system, rejecting those
+    /// starting sooner than `min_notice` from now with [`AllocationError::TooSoon`].
+    ///
+    /// This sets the minimum notice of the booking policy alone, see
+    /// [`SystemAllocation::set_booking_policy`] for how it applies.
+    #[tracing::instrument(skip(self))]
+    pub async fn set_min_notice(
+        &self,
+        system: Uuid,
+        min_notice: Duration,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.set_min_notice_tx(&mut tx, system, min_notice).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::set_min_notice`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn set_min_notice_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        min_notice: Duration,
+    ) -> Result<(), AllocationError> {
+        let interval = PgInterval::try_from(min_notice)
+            .ok()
+            .filter(|_| min_notice >= Duration::zero())
+            .ok_or_else(|| {
+                AllocationError::Validation(format!("invalid minimum notice {min_notice}"))
+            })?;
+        let updated = sqlx::query!(
             r#"
-        INSERT INTO allocations(system_id, allocation_id, kind, planned, start_time, end_time, capabilities)
-        VALUES ($1, $2, $3, false, $4, 'infinity', $5)
+        UPDATE systems SET min_notice = $2 WHERE system_id = $1
             "#,
             system,
-            allocation_id,
-            AllocationKind::Full as _,
-            start,
-            capabilities,
-        ).execute(&self.pool)
-            .await?;
+            interval,
+        )
+        .execute(&mut *tx)
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Err(AllocationError::SystemNotFound(system));
+        }
 
         Ok(())
     }
 
-    /// Insert an outage only in a single capability. All entires overlapping with the same
-    /// capability must be cleared prior to inserting this.
+    /// Set the timezone the operators of the system think in, or clear it with `None`.
     ///
-    /// Return all entires in conflict on error.
-    pub async fn insert_planned_capability_outage(
+    /// Timestamps are stored and evaluated in UTC regardless, the timezone is only kept for
+    /// presenting them, e.g. with [`Entry::local_times`]. A system has no timezone until one is
+    /// set.
+    #[tracing::instrument(skip(self))]
+    pub async fn set_timezone(
         &self,
         system: Uuid,
-        capabilities: Capabilities,
-        start: DateTime<Utc>,
-        end: DateTime<Utc>,
-    ) -> Result<(), anyhow::Error> {
-        let allocation_id = Uuid::new_v4();
-        let capabilities = capabilities.bits() as i32;
-        sqlx::query!(
+        timezone: Option<Tz>,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.set_timezone_tx(&mut tx, system, timezone).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::set_timezone`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn set_timezone_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        timezone: Option<Tz>,
+    ) -> Result<(), AllocationError> {
+        let updated = sqlx::query!(
             r#"
-        INSERT INTO planned (allocation_id, system_id, start_time, end_time, capabilities)
-        VALUES ($1, $2, $3, $4, $5)
+        UPDATE systems SET timezone = $2 WHERE system_id = $1
             "#,
-            allocation_id,
             system,
-            start,
-            end,
-            capabilities,
+            timezone.map(|tz| tz.name()),
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
+        if updated.rows_affected() == 0 {
+            return Err(AllocationError::SystemNotFound(system));
+        }
 
-        sqlx::query!(
-            r#"
-        INSERT INTO allocations(system_id, allocation_id, kind, planned, start_time, end_time, capabilities)
-        VALUES ($1, $2, $3, true, $4, $5, $6)
-            "#,
-            system,
-            allocation_id,
-            AllocationKind::Capability as _,
-            start,
-            end,
-            capabilities,
-        ).execute(&self.pool)
+        Ok(())
+    }
+
+    /// Change the concurrent capacity of entries on a declared system.
+    ///
+    /// Raising the capacity always succeeds. Lowering it fails with
+    /// [`AllocationError::CapacityExceeded`] if more than `capacity` entries are concurrent at
+    /// any instant from now on, listing the entries over each such timespan. Entries that have
+    /// already ended are not considered.
+    ///
+    /// The capacities of individual capabilities, see
+    /// [`SystemAllocation::declare_system_with_capacities`], are left as they are.
+    #[tracing::instrument(skip(self))]
+    pub async fn update_capacity(
+        &self,
+        system: Uuid,
+        capacity: i32,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.update_capacity_tx(&mut tx, system, capacity).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::update_capacity`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn update_capacity_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        capacity: i32,
+    ) -> Result<(), AllocationError> {
+        let current = lock_capacity(&mut *tx, system, capacity).await?;
+        if capacity < current {
+            let now = Utc::now();
+            let entries = remaining_entries(&mut *tx, system, now).await?;
+            let mut conflicts = over_capacity(&entries, capacity, now);
+            describe_conflicts(&mut *tx, &mut conflicts).await?;
+            conflicts_to_error(conflicts)?;
+        }
+        set_capacity(&mut *tx, system, capacity).await?;
+
+        Ok(())
+    }
+
+    /// Change the capacity as with [`SystemAllocation::update_capacity`], even if lowering it
+    /// leaves more entries than `capacity` concurrent.
+    ///
+    /// Returns the entries which must be removed or moved for the system to be within its new
+    /// capacity, without removing them. At each instant over capacity, the entries created last
+    /// are picked first.
+    #[tracing::instrument(skip(self))]
+    pub async fn update_capacity_forced(
+        &self,
+        system: Uuid,
+        capacity: i32,
+    ) -> Result<Vec<Entry>, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let excess = self
+            .update_capacity_forced_tx(&mut tx, system, capacity)
             .await?;
+        tx.commit().await?;
+
+        Ok(excess)
+    }
+
+    /// As [`SystemAllocation::update_capacity_forced`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn update_capacity_forced_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        capacity: i32,
+    ) -> Result<Vec<Entry>, AllocationError> {
+        let current = lock_capacity(&mut *tx, system, capacity).await?;
+        let mut excess = Vec::new();
+        if capacity < current {
+            let now = Utc::now();
+            let entries = remaining_entries(&mut *tx, system, now).await?;
+            excess = capacity_excess(&entries, capacity, now);
+        }
+        set_capacity(&mut *tx, system, capacity).await?;
+        let ids: Vec<_> = excess.iter().map(|a| a.allocation_id).collect();
+        let mut params = entry_params(&mut *tx, &ids).await?;
+
+        Ok(excess
+            .into_iter()
+            .map(|a| Entry {
+                allocation_id: a.allocation_id,
+                start_time: a.start_time,
+                end_time: a.end_time,
+                capabilities: a.capabilities,
+                params: params.remove(&a.allocation_id).unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Change the capabilities provided by a declared system.
+    ///
+    /// Adding capabilities always succeeds. Removing any fails with
+    /// [`AllocationError::CapabilitiesInUse`] if entries or capability outages yet to end
+    /// require them, listing those allocations. The capacity of a removed capability, see
+    /// [`SystemAllocation::declare_system_with_capacities`], is removed along with it.
+    #[tracing::instrument(skip(self))]
+    pub async fn update_capabilities(
+        &self,
+        system: Uuid,
+        capabilities: Capabilities,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.update_capabilities_tx(&mut tx, system, capabilities)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::update_capabilities`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn update_capabilities_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        capabilities: Capabilities,
+    ) -> Result<(), AllocationError> {
+        let current = sqlx::query_scalar!(
+            r#"
+        SELECT capabilities FROM systems WHERE system_id = $1 FOR UPDATE
+            "#,
+            system,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AllocationError::SystemNotFound(system))?;
+
+        let removed = decode_capabilities(current)? - capabilities;
+        if !removed.is_empty() {
+            let rows = sqlx::query!(
+                r#"
+            SELECT allocation_id, kind AS "kind: AllocationKind", planned, start_time,
+                NULLIF(end_time, 'infinity') AS end_time, capabilities
+            FROM allocations
+            WHERE system_id = $1
+                AND kind != 'full'
+                AND capabilities & $2 != 0
+                AND end_time > now()
+                AND NOT hold_expired(allocation_id)
+                AND NOT entry_finished(allocation_id)
+            ORDER BY start_time
+                "#,
+                system,
+                removed.bits() as i64,
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+
+            let now = Utc::now();
+            let mut conflicts = rows
+                .into_iter()
+                .map(|row| {
+                    let allocation = Allocation {
+                        system_id: system,
+                        allocation_id: row.allocation_id,
+                        kind: row.kind,
+                        planned: row.planned,
+                        start_time: row.start_time,
+                        end_time: row.end_time,
+                        capabilities: decode_capabilities(row.capabilities)?,
+                    };
+                    let blocker = Blocker::of(&allocation);
+                    Ok(ConflictInfo::new(allocation, blocker, now, None, removed))
+                })
+                .collect::<Result<Vec<_>, sqlx::Error>>()?;
+            if !conflicts.is_empty() {
+                describe_conflicts(&mut *tx, &mut conflicts).await?;
+                return Err(AllocationError::CapabilitiesInUse { removed, conflicts });
+            }
+
+            sqlx::query!(
+                r#"
+            DELETE FROM system_capacities WHERE system_id = $1 AND capability & $2 != 0
+                "#,
+                system,
+                removed.bits() as i64,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query!(
+            r#"
+        UPDATE systems SET capabilities = $2 WHERE system_id = $1
+            "#,
+            system,
+            capabilities.bits() as i64,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Insert a single entry to occupy a timeslot on the system.
+    ///
+    /// Fails listing every allocation in the way, if the entry overlaps an outage sharing any of
+    /// its capabilities, or would exceed the system capacity. The error variant names the most
+    /// severe reason: [`AllocationError::WindowViolation`] for an unplanned outage, then
+    /// [`AllocationError::CapabilityOutage`] for a planned outage, then
+    /// [`AllocationError::CapacityExceeded`].
+    ///
+    /// Fails with [`AllocationError::UnsupportedCapabilities`] if the system was not declared with
+    /// all `capabilities`, and [`AllocationError::Validation`] if there are none.
+    ///
+    /// Returns the allocation id of the entry, by which it may later be modified or removed.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_entry(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<Uuid, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let allocation_id = self
+            .insert_entry_tx(&mut tx, system, start, end, capabilities)
+            .await?;
+        tx.commit().await?;
+
+        Ok(allocation_id)
+    }
+
+    /// As [`SystemAllocation::insert_entry`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_entry_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<Uuid, AllocationError> {
+        self.insert_entry_with_params_tx(tx, system, start, end, capabilities, EntryParams::new())
+            .await
+    }
+
+    /// Insert a single entry as with [`SystemAllocation::insert_entry`], along with whom it
+    /// belongs to and what it is for.
+    ///
+    /// The details are returned along with the entry when listed, and when it is in conflict or
+    /// removed, e.g. such that its owner may be notified when evicted by a sweep.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_entry_with_params(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+        params: EntryParams,
+    ) -> Result<Uuid, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let allocation_id = self
+            .insert_entry_with_params_tx(&mut tx, system, start, end, capabilities, params)
+            .await?;
+        tx.commit().await?;
+
+        Ok(allocation_id)
+    }
+
+    /// As [`SystemAllocation::insert_entry_with_params`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_entry_with_params_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+        params: EntryParams,
+    ) -> Result<Uuid, AllocationError> {
+        booking_policy(&mut *tx, system)
+            .await?
+            .check(self.now(), start)?;
+        let allocation_id = Uuid::new_v4();
+        insert_entry(
+            &mut *tx,
+            system,
+            allocation_id,
+            start,
+            Some(end),
+            capabilities,
+            &params,
+        )
+        .await?;
+
+        Ok(allocation_id)
+    }
+
+    /// Insert a single entry as with [`SystemAllocation::insert_entry`], occupying the system
+    /// from `start` until further notice, e.g. for a long-running soak test.
+    ///
+    /// Having no end, the entry conflicts with everything after its start. It is stored as
+    /// ending at `'infinity'`, as is an unresolved unplanned outage, and listed without an end
+    /// until given one with [`SystemAllocation::close_entry`].
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_open_entry(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<Uuid, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let allocation_id = self
+            .insert_open_entry_tx(&mut tx, system, start, capabilities)
+            .await?;
+        tx.commit().await?;
+
+        Ok(allocation_id)
+    }
+
+    /// As [`SystemAllocation::insert_open_entry`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_open_entry_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        start: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<Uuid, AllocationError> {
+        booking_policy(&mut *tx, system)
+            .await?
+            .check(self.now(), start)?;
+        let allocation_id = Uuid::new_v4();
+        insert_entry(
+            &mut *tx,
+            system,
+            allocation_id,
+            start,
+            None,
+            capabilities,
+            &EntryParams::new(),
+        )
+        .await?;
+
+        Ok(allocation_id)
+    }
+
+    /// Insert a single entry as with [`SystemAllocation::insert_entry`], identified by the
+    /// caller-supplied `allocation_id`.
+    ///
+    /// The insert is idempotent, such that it may be retried when it is not known whether an
+    /// earlier attempt succeeded. If an entry with `allocation_id` already exists with the same
+    /// system, timespan and capabilities, nothing is inserted and this succeeds. If any
+    /// allocation with `allocation_id` exists otherwise, this fails with
+    /// [`AllocationError::Conflict`] listing it.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_entry_with_id(
+        &self,
+        system: Uuid,
+        allocation_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.insert_entry_with_id_tx(&mut tx, system, allocation_id, start, end, capabilities)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::insert_entry_with_id`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_entry_with_id_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        allocation_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<(), AllocationError> {
+        // A retry succeeds regardless of the booking policy, once the entry has been inserted.
+        if allocation(&mut *tx, allocation_id).await?.is_none() {
+            booking_policy(&mut *tx, system)
+                .await?
+                .check(self.now(), start)?;
+        }
+        insert_entry(
+            &mut *tx,
+            system,
+            allocation_id,
+            start,
+            Some(end),
+            capabilities,
+            &EntryParams::new(),
+        )
+        .await
+    }
+
+    /// Insert a single entry as with [`SystemAllocation::insert_entry`], unless one was already
+    /// inserted with `idempotency_key`, returning the allocation id of the entry either way.
+    ///
+    /// A request retried with the same key thus succeeds without inserting again, e.g. when it is
+    /// not known whether an earlier attempt timed out before or after committing. If the entry
+    /// of the key no longer matches the system, timespan and capabilities requested, this fails
+    /// with [`AllocationError::Conflict`] listing it. A key is reused once its entry is removed.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_entry_idempotent(
+        &self,
+        system: Uuid,
+        idempotency_key: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<Uuid, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let allocation_id = self
+            .insert_entry_idempotent_tx(&mut tx, system, idempotency_key, start, end, capabilities)
+            .await?;
+        tx.commit().await?;
+
+        Ok(allocation_id)
+    }
+
+    /// As [`SystemAllocation::insert_entry_idempotent`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_entry_idempotent_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        idempotency_key: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<Uuid, AllocationError> {
+        check_range(start, end)?;
+        // Serializes concurrent requests with the same key, such that only the first inserts.
+        lock_system(&mut *tx, system).await?;
+        let existing = sqlx::query_scalar!(
+            r#"
+        SELECT k.allocation_id FROM idempotency_keys k
+        JOIN entries e USING (allocation_id)
+        WHERE k.idempotency_key = $1
+            "#,
+            idempotency_key,
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some(allocation_id) = existing {
+            let existing = allocation(&mut *tx, allocation_id)
+                .await?
+                .ok_or(AllocationError::NotFound(allocation_id))?;
+            check_same_entry(existing, system, start, Some(end), capabilities)?;
+            return Ok(allocation_id);
+        }
+
+        let allocation_id = self
+            .insert_entry_tx(&mut *tx, system, start, end, capabilities)
+            .await?;
+        sqlx::query!(
+            r#"
+        DELETE FROM idempotency_keys WHERE idempotency_key = $1
+            "#,
+            idempotency_key,
+        )
+        .execute(&mut *tx)
+        .await?;
+        // Only a concurrent request with the same key for another system may have inserted it.
+        sqlx::query!(
+            r#"
+        INSERT INTO idempotency_keys(idempotency_key, allocation_id) VALUES ($1, $2)
+            "#,
+            idempotency_key,
+            allocation_id,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| AllocationError::from_insert(e, system, Some((start, end))))?;
+
+        Ok(allocation_id)
+    }
+
+    /// Insert a batch of entries as `(start, end, capabilities)`, returning their allocation ids
+    /// in the same order.
+    ///
+    /// Each entry is evaluated as with [`SystemAllocation::insert_entry`], against the existing
+    /// allocations as well as the entries preceding it in the batch. Either all entries are
+    /// inserted, or none are, failing with [`AllocationError::Batch`] naming the first entry that
+    /// could not be.
+    #[tracing::instrument(skip(self, entries), fields(entries = entries.len()))]
+    pub async fn insert_entries(
+        &self,
+        system: Uuid,
+        entries: &[(DateTime<Utc>, DateTime<Utc>, Capabilities)],
+    ) -> Result<Vec<Uuid>, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let result = self.insert_entries_tx(&mut tx, system, entries).await?;
+        tx.commit().await?;
+
+        Ok(result)
+    }
+
+    /// As [`SystemAllocation::insert_entries`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx, entries), fields(entries = entries.len()))]
+    pub async fn insert_entries_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        entries: &[(DateTime<Utc>, DateTime<Utc>, Capabilities)],
+    ) -> Result<Vec<Uuid>, AllocationError> {
+        for (index, (start, end, capabilities)) in entries.iter().enumerate() {
+            check_range(*start, *end)
+                .and_then(|_| check_entry_capabilities(*capabilities))
+                .map_err(|e| e.in_batch(index))?;
+        }
+        let (Some(first), Some(last)) = (
+            entries.iter().map(|(start, _, _)| *start).min(),
+            entries.iter().map(|(_, end, _)| *end).max(),
+        ) else {
+            return Ok(Vec::new());
+        };
+        let (policy, now) = (booking_policy(&mut *tx, system).await?, self.now());
+        for (index, (start, _, _)) in entries.iter().enumerate() {
+            policy.check(now, *start).map_err(|e| e.in_batch(index))?;
+        }
+
+        lock_system(&mut *tx, system).await?;
+        let mut limits = HashMap::new();
+        for (index, (_, _, capabilities)) in entries.iter().enumerate() {
+            if !limits.contains_key(capabilities) {
+                let loaded = CapacityLimits::load(&mut *tx, system, *capabilities)
+                    .await
+                    .map_err(|e| e.in_batch(index))?;
+                limits.insert(*capabilities, loaded);
+            }
+        }
+
+        let boundary = boundary_policy(&mut *tx, system).await?;
+        let mut allocations =
+            overlapping_allocations(&mut *tx, system, first, last, boundary).await?;
+        let mut ids = Vec::with_capacity(entries.len());
+        for (index, (start, end, capabilities)) in entries.iter().enumerate() {
+            let overlapping = allocations
+                .iter()
+                .filter(|a| boundary.overlaps((a.start_time, a.end_time), (*start, Some(*end))))
+                .cloned();
+            let entry_limits = &limits[capabilities];
+            let conflicts = classify_conflicts(
+                overlapping,
+                entry_limits,
+                (*start, Some(*end)),
+                *capabilities,
+                None,
+                &[],
+            );
+            entry_limits
+                .with_occupancy(conflicts_to_error(conflicts), (*start, Some(*end)))
+                .map_err(|e| e.in_batch(index))?;
+
+            // Later entries of the batch are evaluated against this one as well.
+            let allocation_id = Uuid::new_v4();
+            ids.push(allocation_id);
+            allocations.push(Allocation {
+                system_id: system,
+                allocation_id,
+                kind: AllocationKind::Entry,
+                planned: true,
+                start_time: *start,
+                end_time: Some(*end),
+                capabilities: *capabilities,
+            });
+        }
+
+        let starts: Vec<_> = entries.iter().map(|(start, _, _)| *start).collect();
+        let ends: Vec<_> = entries.iter().map(|(_, end, _)| *end).collect();
+        let capabilities: Vec<_> = entries.iter().map(|(_, _, c)| c.bits() as i64).collect();
+
+        sqlx::query!(
+            r#"
+        INSERT INTO entries(allocation_id, start_time, end_time)
+        SELECT * FROM UNNEST($1::uuid[], $2::timestamptz[], $3::timestamptz[])
+            "#,
+            &ids,
+            &starts,
+            &ends,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+        INSERT INTO allocations(system_id, allocation_id, kind, planned, start_time, end_time, capabilities)
+        SELECT $1, allocation_id, 'entry', true, start_time, end_time, capabilities
+        FROM UNNEST($2::uuid[], $3::timestamptz[], $4::timestamptz[], $5::bigint[])
+            AS batch(allocation_id, start_time, end_time, capabilities)
+            "#,
+            system,
+            &ids,
+            &starts,
+            &ends,
+            &capabilities,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| AllocationError::from_insert(e, system, None))?;
+
+        Ok(ids)
+    }
+
+    /// Insert `count` occurrences of an entry of `duration`, the first starting at `first_start`
+    /// and each following one `period` after the previous.
+    ///
+    /// The occurrences are inserted as a batch with [`SystemAllocation::insert_entries`], such
+    /// that the series fails as a whole if any occurrence is in conflict, naming the first such
+    /// occurrence by its index. They share a recurrence group, identifying the series.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_recurring_entry(
+        &self,
+        system: Uuid,
+        first_start: DateTime<Utc>,
+        duration: Duration,
+        period: Duration,
+        count: u32,
+        capabilities: Capabilities,
+    ) -> Result<RecurringEntry, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let series = self
+            .insert_recurring_entry_tx(
+                &mut tx,
+                system,
+                first_start,
+                duration,
+                period,
+                count,
+                capabilities,
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(series)
+    }
+
+    /// As [`SystemAllocation::insert_recurring_entry`], within the transaction `tx`.
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_recurring_entry_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        first_start: DateTime<Utc>,
+        duration: Duration,
+        period: Duration,
+        count: u32,
+        capabilities: Capabilities,
+    ) -> Result<RecurringEntry, AllocationError> {
+        if period <= Duration::zero() {
+            return Err(AllocationError::Validation(format!(
+                "recurrence period must be positive, got {period}"
+            )));
+        }
+        if count == 0 {
+            return Err(AllocationError::Validation(
+                "a recurring entry must occur at least once".to_string(),
+            ));
+        }
+
+        let occurrences =
+            std::iter::successors(Some(first_start), |start| start.checked_add_signed(period))
+                .take(count as usize)
+                .map(|start| Some((start, start.checked_add_signed(duration)?, capabilities)))
+                .collect::<Option<Vec<_>>>()
+                .filter(|occurrences| occurrences.len() == count as usize)
+                .ok_or_else(|| {
+                    AllocationError::Validation(format!(
+                        "{count} occurrences every {period} are out of range"
+                    ))
+                })?;
+        let allocation_ids = self
+            .insert_entries_tx(&mut *tx, system, &occurrences)
+            .await?;
+
+        let group_id = Uuid::new_v4();
+        sqlx::query!(
+            r#"
+        UPDATE entries SET recurrence_group = $1 WHERE allocation_id = ANY($2)
+            "#,
+            group_id,
+            &allocation_ids,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(RecurringEntry {
+            group_id,
+            allocation_ids,
+            skipped: Vec::new(),
+        })
+    }
+
+    /// Check whether [`SystemAllocation::insert_entry`] would succeed, without inserting anything.
+    ///
+    /// The insert is attempted within a transaction that is always rolled back, returning
+    /// `Ok(false)` if it was rejected for being in conflict with any existing allocations. An
+    /// entry the booking policy of the system does not allow fails as it would on insert.
+    #[tracing::instrument(skip(self))]
+    pub async fn can_insert_entry(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<bool, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        booking_policy(&mut tx, system)
+            .await?
+            .check(self.now(), start)?;
+        let result = insert_entry(
+            &mut tx,
+            system,
+            Uuid::new_v4(),
+            start,
+            Some(end),
+            capabilities,
+            &EntryParams::new(),
+        )
+        .await;
+        tx.rollback().await?;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(err) if err.is_conflict() => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Evaluate whether an entry would fit, without inserting anything.
+    ///
+    /// This runs the same evaluation as [`SystemAllocation::insert_entry`], reporting every
+    /// allocation in the way along with why it is. An entry the booking policy of the system does
+    /// not allow fails as it would on insert.
+    #[tracing::instrument(skip(self))]
+    pub async fn check_entry_fits(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<FitResult, AllocationError> {
+        check_range(start, end)?;
+
+        let mut tx = self.pool.begin().await?;
+        booking_policy(&mut tx, system)
+            .await?
+            .check(self.now(), start)?;
+        let conflicts =
+            entry_conflicts(&mut tx, system, start, Some(end), capabilities, None).await?;
+        tx.rollback().await?;
+
+        if conflicts.is_empty() {
+            Ok(FitResult::Fits)
+        } else {
+            Ok(FitResult::Conflicts(conflicts))
+        }
+    }
+
+    /// The first outage, by start, in the way of an entry over `[start, end)` for
+    /// `capabilities`, or `None` if there is none, regardless of whether the entry would fit the
+    /// capacity.
+    ///
+    /// This tells a timespan blocked by e.g. maintenance apart from one merely full, which
+    /// [`SystemAllocation::check_entry_fits`] reports alike.
+    #[tracing::instrument(skip(self))]
+    pub async fn overlaps_outage(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<Option<Outage>, AllocationError> {
+        check_range(start, end)?;
+
+        let mut tx = self.pool.begin().await?;
+        let conflicts =
+            entry_conflicts(&mut tx, system, start, Some(end), capabilities, None).await?;
+        let blocking = conflicts
+            .iter()
+            .find(|c| !matches!(c.blocker, Blocker::Capacity | Blocker::Entry));
+        let outage = match blocking {
+            Some(conflict) => overlapping_outages(&mut tx, system, start, end)
+                .await?
+                .into_iter()
+                .find(|o| o.allocation_id() == conflict.allocation.allocation_id),
+            None => None,
+        };
+        tx.rollback().await?;
+
+        Ok(outage)
+    }
+
+    /// Find the earliest start, no earlier than `after`, at which an entry of `duration` for
+    /// `capabilities` would be accepted by [`SystemAllocation::insert_entry`].
+    ///
+    /// Returns `None` if there is no such start, e.g. due to an unresolved unplanned outage.
+    #[tracing::instrument(skip(self))]
+    pub async fn find_next_free_slot(
+        &self,
+        system: Uuid,
+        after: DateTime<Utc>,
+        duration: Duration,
+        capabilities: Capabilities,
+    ) -> Result<Option<DateTime<Utc>>, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let slot = next_free_slot(
+            &mut tx,
+            system,
+            after,
+            DateTime::<Utc>::MAX_UTC,
+            duration,
+            capabilities,
+        )
+        .await?;
+        tx.rollback().await?;
+
+        Ok(slot)
+    }
+
+    /// Find the earliest slot as with [`SystemAllocation::find_next_free_slot`], only scanning
+    /// `horizon` ahead of `after`.
+    ///
+    /// Returns `None` if no slot of `duration` fits before the end of the horizon.
+    #[tracing::instrument(skip(self))]
+    pub async fn find_next_available_slot(
+        &self,
+        system: Uuid,
+        after: DateTime<Utc>,
+        duration: Duration,
+        capabilities: Capabilities,
+        horizon: Duration,
+    ) -> Result<Option<DateTime<Utc>>, AllocationError> {
+        let until = after.checked_add_signed(horizon).ok_or_else(|| {
+            AllocationError::Validation(format!("horizon {horizon} is out of range"))
+        })?;
+        let mut tx = self.pool.begin().await?;
+        let slot = next_free_slot(&mut tx, system, after, until, duration, capabilities).await?;
+        tx.rollback().await?;
+
+        Ok(slot)
+    }
+
+    /// Remove a single entry, freeing up its timeslot on the system.
+    ///
+    /// Requests on the waitlist for the freed timeslot are promoted into entries, see
+    /// [`SystemAllocation::join_waitlist`].
+    ///
+    /// Fails with [`AllocationError::NotFound`] if nothing exists with the given id, or
+    /// [`AllocationError::WrongKind`] if it refers to an outage.
+    #[tracing::instrument(skip(self))]
+    pub async fn remove_entry(&self, allocation_id: Uuid) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.remove_entry_tx(&mut tx, allocation_id).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::remove_entry`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn remove_entry_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        allocation_id: Uuid,
+    ) -> Result<(), AllocationError> {
+        let removed = sqlx::query!(
+            r#"
+        DELETE FROM allocations WHERE allocation_id = $1 AND kind = 'entry'
+        RETURNING system_id, start_time, NULLIF(end_time, 'infinity') AS end_time
+            "#,
+            allocation_id,
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(removed) = removed else {
+            return Err(not_of_kind(&mut *tx, allocation_id).await?);
+        };
+
+        sqlx::query!(
+            r#"
+        DELETE FROM entries WHERE allocation_id = $1
+            "#,
+            allocation_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        promote_waitlist(tx, removed.system_id, removed.start_time, removed.end_time).await
+    }
+
+    /// Pin or unpin an entry. A pinned entry is never forcefully removed.
+    ///
+    /// [`SystemAllocation::insert_unplanned_outage_forced`] fails with
+    /// [`AllocationError::Conflict`] listing the pinned entries within its sliding window, and
+    /// sweeps leave them in place, reporting them for manual intervention. Entries may also be
+    /// pinned as they are inserted, see [`EntryParams::pinned`].
+    ///
+    /// Fails with [`AllocationError::NotFound`] if nothing exists with the given id, or
+    /// [`AllocationError::WrongKind`] if it refers to an outage.
+    #[tracing::instrument(skip(self))]
+    pub async fn set_pinned(
+        &self,
+        allocation_id: Uuid,
+        pinned: bool,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.set_pinned_tx(&mut tx, allocation_id, pinned).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::set_pinned`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn set_pinned_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        allocation_id: Uuid,
+        pinned: bool,
+    ) -> Result<(), AllocationError> {
+        let updated = sqlx::query!(
+            r#"
+        UPDATE entries SET pinned = $2 WHERE allocation_id = $1
+            "#,
+            allocation_id,
+            pinned,
+        )
+        .execute(&mut *tx)
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Err(not_of_kind(&mut *tx, allocation_id).await?);
+        }
+
+        Ok(())
+    }
+
+    /// Give an open-ended entry, see [`SystemAllocation::insert_open_entry`], its end at `end`.
+    ///
+    /// Ending an entry only frees up time, and is thus never in conflict. Requests on the waitlist
+    /// for the time freed are promoted, see [`SystemAllocation::join_waitlist`]. Fails with
+    /// [`AllocationError::InvalidRange`] unless `end` is after the start of the entry, and
+    /// [`AllocationError::Validation`] if it already has an end, see
+    /// [`SystemAllocation::update_entry`] to move it instead.
+    ///
+    /// Fails with [`AllocationError::NotFound`] if nothing exists with the given id, or
+    /// [`AllocationError::WrongKind`] if it refers to an outage.
+    #[tracing::instrument(skip(self))]
+    pub async fn close_entry(
+        &self,
+        allocation_id: Uuid,
+        end: DateTime<Utc>,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.close_entry_tx(&mut tx, allocation_id, end).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::close_entry`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn close_entry_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        allocation_id: Uuid,
+        end: DateTime<Utc>,
+    ) -> Result<(), AllocationError> {
+        let open = sqlx::query!(
+            r#"
+        SELECT a.system_id, a.start_time
+        FROM allocations a
+        JOIN entries e USING (allocation_id)
+        WHERE a.allocation_id = $1 AND a.end_time = 'infinity'
+        FOR UPDATE
+            "#,
+            allocation_id,
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(open) = open else {
+            return Err(match allocation(&mut *tx, allocation_id).await? {
+                Some(Allocation {
+                    kind: AllocationKind::Entry,
+                    end_time: Some(current),
+                    ..
+                }) => AllocationError::Validation(format!(
+                    "entry {allocation_id} already ends at {current}"
+                )),
+                _ => not_of_kind(&mut *tx, allocation_id).await?,
+            });
+        };
+        check_range(open.start_time, end)?;
+
+        sqlx::query!(
+            r#"
+        UPDATE entries SET end_time = $2 WHERE allocation_id = $1
+            "#,
+            allocation_id,
+            end,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+        UPDATE allocations SET end_time = $2 WHERE allocation_id = $1
+            "#,
+            allocation_id,
+            end,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        promote_waitlist(tx, open.system_id, end, None).await
+    }
+
+    /// Remove every occurrence of a recurring entry, see
+    /// [`SystemAllocation::insert_recurring_entry`], returning the number of entries removed.
+    ///
+    /// As with [`SystemAllocation::remove_entry`], occurrences within the sliding window of an
+    /// unplanned outage may be removed as well, and requests on the waitlist for the time freed
+    /// are promoted.
+    #[tracing::instrument(skip(self))]
+    pub async fn cancel_recurring_group(&self, group_id: Uuid) -> Result<u64, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let removed = self.cancel_recurring_group_tx(&mut tx, group_id).await?;
+        tx.commit().await?;
+
+        Ok(removed)
+    }
+
+    /// As [`SystemAllocation::cancel_recurring_group`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn cancel_recurring_group_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        group_id: Uuid,
+    ) -> Result<u64, AllocationError> {
+        let removed = sqlx::query_scalar!(
+            r#"
+        DELETE FROM entries WHERE recurrence_group = $1 RETURNING allocation_id
+            "#,
+            group_id,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let freed = sqlx::query!(
+            r#"
+        DELETE FROM allocations WHERE allocation_id = ANY($1)
+        RETURNING system_id, start_time, NULLIF(end_time, 'infinity') AS end_time
+            "#,
+            &removed,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        for occurrence in freed {
+            promote_waitlist(
+                tx,
+                occurrence.system_id,
+                occurrence.start_time,
+                occurrence.end_time,
+            )
+            .await?;
+        }
+
+        Ok(removed.len() as u64)
+    }
+
+    /// Move or resize an existing entry, and replace its capabilities.
+    ///
+    /// The new placement is evaluated as if inserting the entry, apart from the entry itself not
+    /// counting against it. Contrary to inserting, an entry may be placed beyond the sliding
+    /// window of an unplanned outage, but never into it. An entry already within the window is
+    /// due to be removed, and can not be modified at all. Moving the start of an entry is
+    /// subject to the booking policy of the system, see [`SystemAllocation::set_booking_policy`].
+    #[tracing::instrument(skip(self))]
+    pub async fn update_entry(
+        &self,
+        allocation_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.update_entry_tx(&mut tx, allocation_id, start, end, capabilities)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::update_entry`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn update_entry_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        allocation_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<(), AllocationError> {
+        check_range(start, end)?;
+
+        let current = sqlx::query!(
+            r#"
+        SELECT system_id, kind AS "kind: AllocationKind", start_time,
+            NULLIF(end_time, 'infinity') AS end_time, capabilities
+        FROM allocations
+        WHERE allocation_id = $1
+        FOR UPDATE
+            "#,
+            allocation_id,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AllocationError::NotFound(allocation_id))?;
+        if current.kind != AllocationKind::Entry {
+            return Err(AllocationError::WrongKind {
+                allocation_id,
+                kind: current.kind,
+            });
+        }
+        let system = current.system_id;
+        if start != current.start_time {
+            booking_policy(&mut *tx, system)
+                .await?
+                .check(self.now(), start)?;
+        }
+
+        check_not_in_window(
+            &mut *tx,
+            system,
+            current.start_time,
+            current.end_time.unwrap_or(DateTime::<Utc>::MAX_UTC),
+            decode_capabilities(current.capabilities)?,
+        )
+        .await?;
+
+        check_entry_conflicts(
+            &mut *tx,
+            system,
+            start,
+            Some(end),
+            capabilities,
+            Some(allocation_id),
+        )
+        .await?;
+
+        sqlx::query!(
+            r#"
+        UPDATE entries SET start_time = $2, end_time = $3 WHERE allocation_id = $1
+            "#,
+            allocation_id,
+            start,
+            end,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| AllocationError::from_insert(e, system, Some((start, end))))?;
+
+        sqlx::query!(
+            r#"
+        UPDATE allocations SET start_time = $2, end_time = $3, capabilities = $4
+        WHERE allocation_id = $1
+            "#,
+            allocation_id,
+            start,
+            end,
+            capabilities.bits() as i64,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| AllocationError::from_insert(e, system, Some((start, end))))?;
+
+        Ok(())
+    }
+
+    /// Inserting a planned outage for the duration (start, end).
+    ///
+    /// This outage _must_ resolve all conflicts. No partial capability downtimes allowed.
+    /// This function will fail if any items are in conflict.
+    ///
+    /// Returns the allocation id of the outage.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_planned_outage(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Uuid, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let allocation_id = self
+            .insert_planned_outage_tx(&mut tx, system, start, end)
+            .await?;
+        tx.commit().await?;
+
+        Ok(allocation_id)
+    }
+
+    /// As [`SystemAllocation::insert_planned_outage`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_planned_outage_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Uuid, AllocationError> {
+        check_range(start, end)?;
+
+        let capabilities = Capabilities::all();
+        check_outage_conflicts(&mut *tx, system, start, Some(end), None, capabilities, None)
+            .await?;
+
+        let allocation_id = Uuid::new_v4();
+        sqlx::query!(
+            r#"
+        INSERT INTO planned(allocation_id, system_id, start_time, end_time, capabilities)
+        VALUES ($1, $2, $3, $4, $5)
+            "#,
+            allocation_id,
+            system,
+            start,
+            end,
+            capabilities.bits() as i64,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| AllocationError::from_insert(e, system, Some((start, end))))?;
+
+        NewAllocation {
+            system,
+            allocation_id,
+            kind: AllocationKind::Full,
+            planned: true,
+            start,
+            end: Some(end),
+            capabilities,
+        }
+        .insert(&mut *tx)
+        .await?;
+
+        Ok(allocation_id)
+    }
+
+    /// Only those in conflict from start + sliding_window duration will be evaluated to be
+    /// enforced to re-allocate before this unplanned outage can be successfully inserted.
+    ///
+    /// The sliding window is rounded up to whole minutes, and fails with
+    /// [`AllocationError::InvalidSlidingWindow`] unless it is positive and not too large.
+    ///
+    /// Returns the allocation id of the outage, by which it is later resolved.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_unplanned_outage(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        sliding_window: Duration,
+    ) -> Result<Uuid, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let allocation_id = self
+            .insert_unplanned_outage_tx(&mut tx, system, start, sliding_window)
+            .await?;
+        tx.commit().await?;
+
+        Ok(allocation_id)
+    }
+
+    /// As [`SystemAllocation::insert_unplanned_outage`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_unplanned_outage_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        start: DateTime<Utc>,
+        sliding_window: Duration,
+    ) -> Result<Uuid, AllocationError> {
+        let interval = sliding_window_to_interval(sliding_window)?;
+        let allocation_id =
+            insert_unplanned_outage(&mut *tx, system, Capabilities::all(), start, interval).await?;
+
+        Ok(allocation_id)
+    }
+
+    /// Insert an unplanned outage as with [`SystemAllocation::insert_unplanned_outage`], of only
+    /// a subset of the system capabilities.
+    ///
+    /// Only entries sharing any of `capabilities` are in conflict within the sliding window, and
+    /// are denied from its start. Entries of other capabilities are unaffected by the outage.
+    /// Fails with [`AllocationError::UnsupportedCapabilities`] if the system was not declared
+    /// with all `capabilities`.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_unplanned_capability_outage(
+        &self,
+        system: Uuid,
+        capabilities: Capabilities,
+        start: DateTime<Utc>,
+        sliding_window: Duration,
+    ) -> Result<Uuid, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let allocation_id = self
+            .insert_unplanned_capability_outage_tx(
+                &mut tx,
+                system,
+                capabilities,
+                start,
+                sliding_window,
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(allocation_id)
+    }
+
+    /// As [`SystemAllocation::insert_unplanned_capability_outage`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_unplanned_capability_outage_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        capabilities: Capabilities,
+        start: DateTime<Utc>,
+        sliding_window: Duration,
+    ) -> Result<Uuid, AllocationError> {
+        let interval = sliding_window_to_interval(sliding_window)?;
+        system_capacity(&mut *tx, system, capabilities).await?;
+        let allocation_id =
+            insert_unplanned_outage(&mut *tx, system, capabilities, start, interval).await?;
+
+        Ok(allocation_id)
+    }
+
+    /// Insert an unplanned outage as with [`SystemAllocation::insert_unplanned_outage`], using
+    /// the default sliding window of the system.
+    ///
+    /// Fails with [`AllocationError::Validation`] if the system has no default sliding window,
+    /// see [`SystemAllocation::set_default_sliding_window`].
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_unplanned_outage_default(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+    ) -> Result<Uuid, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let allocation_id = self
+            .insert_unplanned_outage_default_tx(&mut tx, system, start)
+            .await?;
+        tx.commit().await?;
+
+        Ok(allocation_id)
+    }
+
+    /// As [`SystemAllocation::insert_unplanned_outage_default`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_unplanned_outage_default_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        start: DateTime<Utc>,
+    ) -> Result<Uuid, AllocationError> {
+        let sliding_window = system_info(&mut *tx, system)
+            .await?
+            .ok_or(AllocationError::SystemNotFound(system))?
+            .default_sliding_window
+            .ok_or_else(|| {
+                AllocationError::Validation(format!(
+                    "system {system} has no default sliding window"
+                ))
+            })?;
+        let interval = sliding_window_to_interval(sliding_window)?;
+        let allocation_id =
+            insert_unplanned_outage(&mut *tx, system, Capabilities::all(), start, interval).await?;
+
+        Ok(allocation_id)
+    }
+
+    /// Insert an unplanned outage as with [`SystemAllocation::insert_unplanned_outage`], but
+    /// forcefully remove the entries in conflict within its sliding window instead of failing.
+    ///
+    /// The removed entries are returned, such that their owners may be notified. Nothing is
+    /// removed if the outage fails to be inserted, which it does with
+    /// [`AllocationError::Conflict`] listing any pinned entries within the window, see
+    /// [`SystemAllocation::set_pinned`].
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_unplanned_outage_forced(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        sliding_window: Duration,
+    ) -> Result<Vec<Entry>, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let result = self
+            .insert_unplanned_outage_forced_tx(&mut tx, system, start, sliding_window)
+            .await?;
+        tx.commit().await?;
+
+        Ok(result)
+    }
+
+    /// As [`SystemAllocation::insert_unplanned_outage_forced`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_unplanned_outage_forced_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        start: DateTime<Utc>,
+        sliding_window: Duration,
+    ) -> Result<Vec<Entry>, AllocationError> {
+        let interval = sliding_window_to_interval(sliding_window)?;
+
+        let rows = sqlx::query!(
+            r#"
+        DELETE FROM allocations a
+        USING systems s, entries e
+        WHERE a.system_id = $1
+            AND s.system_id = a.system_id
+            AND e.allocation_id = a.allocation_id
+            AND a.kind = 'entry'
+            AND NOT e.pinned
+            AND e.status NOT IN ('completed', 'cancelled')
+            AND ($2 < a.end_time + COALESCE(s.boundary_gap, interval '0')
+                OR (s.boundary_policy = 'exclusive' AND $2 = a.end_time))
+            AND ($2 + $3::interval) > a.start_time
+        RETURNING a.allocation_id, a.start_time, NULLIF(a.end_time, 'infinity') AS end_time,
+            a.capabilities
+            "#,
+            system,
+            start,
+            interval,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let removed: Vec<_> = rows.iter().map(|row| row.allocation_id).collect();
+        let mut params = delete_entry_rows(&mut *tx, &removed).await?;
+
+        // Any pinned entries are left in the window, failing the checks of the outage.
+        insert_unplanned_outage(&mut *tx, system, Capabilities::all(), start, interval).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                Ok(Entry {
+                    allocation_id: row.allocation_id,
+                    start_time: row.start_time,
+                    end_time: row.end_time,
+                    capabilities: decode_capabilities(row.capabilities)?,
+                    params: params.remove(&row.allocation_id).unwrap_or_default(),
+                })
+            })
+            .collect::<Result<_, sqlx::Error>>()?)
+    }
+
+    /// Resolve an unplanned outage, giving it an end at `end`.
+    ///
+    /// New entries are allowed after the outage has ended, and entries within its sliding window
+    /// are no longer forcefully removed. Fails with [`AllocationError::InvalidRange`] if `end` is
+    /// not after the start of the outage.
+    #[tracing::instrument(skip(self))]
+    pub async fn resolve_unplanned_outage(
+        &self,
+        allocation_id: Uuid,
+        end: DateTime<Utc>,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.resolve_unplanned_outage_tx(&mut tx, allocation_id, end)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::resolve_unplanned_outage`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn resolve_unplanned_outage_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        allocation_id: Uuid,
+        end: DateTime<Utc>,
+    ) -> Result<(), AllocationError> {
+        let outage = sqlx::query!(
+            r#"
+        SELECT system_id, start_time, resolved_at FROM unplanned
+        WHERE allocation_id = $1
+        FOR UPDATE
+            "#,
+            allocation_id,
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(outage) = outage else {
+            return Err(not_of_kind(&mut *tx, allocation_id).await?);
+        };
+        if outage.resolved_at.is_some() {
+            return Err(AllocationError::Validation(format!(
+                "unplanned outage {allocation_id} is already resolved"
+            )));
+        }
+        if end <= outage.start_time {
+            return Err(AllocationError::InvalidRange {
+                start: outage.start_time,
+                end,
+            });
+        }
+
+        sqlx::query!(
+            r#"
+        UPDATE unplanned SET resolved_at = $2 WHERE allocation_id = $1
+            "#,
+            allocation_id,
+            end,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+        UPDATE allocations SET end_time = $2 WHERE allocation_id = $1
+            "#,
+            allocation_id,
+            end,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            AllocationError::from_insert(e, outage.system_id, Some((outage.start_time, end)))
+        })?;
+
+        Ok(())
+    }
+
+    /// Cancel a planned outage, of either the entire system or some of its capabilities,
+    /// freeing up its timespan for entries.
+    ///
+    /// Fails with [`AllocationError::NotFound`] if nothing exists with the given id, or
+    /// [`AllocationError::WrongKind`] if it is not a planned outage.
+    #[tracing::instrument(skip(self))]
+    pub async fn cancel_planned_outage(&self, allocation_id: Uuid) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.cancel_planned_outage_tx(&mut tx, allocation_id)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::cancel_planned_outage`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn cancel_planned_outage_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        allocation_id: Uuid,
+    ) -> Result<(), AllocationError> {
+        lock_planned_outage(&mut *tx, allocation_id).await?;
+        delete_planned_outage(&mut *tx, allocation_id).await?;
+
+        Ok(())
+    }
+
+    /// Cancel what remains of a planned outage, ending it now if it is in progress.
+    ///
+    /// An outage yet to start is cancelled entirely, as with
+    /// [`SystemAllocation::cancel_planned_outage`]. Fails with [`AllocationError::Validation`]
+    /// if the outage has already ended.
+    #[tracing::instrument(skip(self))]
+    pub async fn cancel_planned_outage_remainder(
+        &self,
+        allocation_id: Uuid,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.cancel_planned_outage_remainder_tx(&mut tx, allocation_id)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::cancel_planned_outage_remainder`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn cancel_planned_outage_remainder_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        allocation_id: Uuid,
+    ) -> Result<(), AllocationError> {
+        let (start, end) = lock_planned_outage(&mut *tx, allocation_id).await?;
+
+        let now = Utc::now();
+        if end <= now {
+            return Err(AllocationError::Validation(format!(
+                "planned outage {allocation_id} has already ended"
+            )));
+        }
+        if start >= now {
+            delete_planned_outage(&mut *tx, allocation_id).await?;
+        } else {
+            sqlx::query!(
+                r#"
+            UPDATE planned SET end_time = $2 WHERE allocation_id = $1
+                "#,
+                allocation_id,
+                now,
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query!(
+                r#"
+            UPDATE allocations SET end_time = $2 WHERE allocation_id = $1
+                "#,
+                allocation_id,
+                now,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Move a planned outage, of either the entire system or some of its capabilities, to the
+    /// timespan `[start, end)`, e.g. as maintenance is rescheduled.
+    ///
+    /// The new timespan is evaluated as if inserting the outage, apart from the outage itself not
+    /// counting against it, and fails with [`AllocationError::Conflict`] listing every
+    /// allocation in conflict. Fails with [`AllocationError::NotFound`] if nothing exists with
+    /// the given id, or [`AllocationError::WrongKind`] if it is not a planned outage.
+    #[tracing::instrument(skip(self))]
+    pub async fn move_planned_outage(
+        &self,
+        allocation_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.move_planned_outage_tx(&mut tx, allocation_id, start, end)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::move_planned_outage`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn move_planned_outage_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        allocation_id: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<(), AllocationError> {
+        check_range(start, end)?;
+
+        lock_planned_outage(&mut *tx, allocation_id).await?;
+        let outage = allocation(&mut *tx, allocation_id)
+            .await?
+            .ok_or(AllocationError::NotFound(allocation_id))?;
+        let system = outage.system_id;
+        check_outage_conflicts(
+            &mut *tx,
+            system,
+            start,
+            Some(end),
+            None,
+            outage.capabilities,
+            Some(allocation_id),
+        )
+        .await?;
+
+        sqlx::query!(
+            r#"
+        UPDATE planned SET start_time = $2, end_time = $3 WHERE allocation_id = $1
+            "#,
+            allocation_id,
+            start,
+            end,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| AllocationError::from_insert(e, system, Some((start, end))))?;
+
+        sqlx::query!(
+            r#"
+        UPDATE allocations SET start_time = $2, end_time = $3 WHERE allocation_id = $1
+            "#,
+            allocation_id,
+            start,
+            end,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| AllocationError::from_insert(e, system, Some((start, end))))?;
+
+        Ok(())
+    }
+
+    /// Insert an outage only in a single capability. All entires overlapping with the same
+    /// capability must be cleared prior to inserting this.
+    ///
+    /// Return all entires in conflict on error, as [`AllocationError::Conflict`]. This includes
+    /// any existing outages sharing a capability over the same timespan. Fails with
+    /// [`AllocationError::UnsupportedCapabilities`] if the system was not declared with all
+    /// `capabilities`.
+    ///
+    /// Returns the allocation id of the outage.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_planned_capability_outage(
+        &self,
+        system: Uuid,
+        capabilities: Capabilities,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Uuid, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let allocation_id = self
+            .insert_planned_capability_outage_tx(&mut tx, system, capabilities, start, end)
+            .await?;
+        tx.commit().await?;
+
+        Ok(allocation_id)
+    }
+
+    /// As [`SystemAllocation::insert_planned_capability_outage`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_planned_capability_outage_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        capabilities: Capabilities,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Uuid, AllocationError> {
+        check_range(start, end)?;
+
+        system_capacity(&mut *tx, system, capabilities).await?;
+        check_outage_conflicts(&mut *tx, system, start, Some(end), None, capabilities, None)
+            .await?;
+
+        let allocation_id = Uuid::new_v4();
+        sqlx::query!(
+            r#"
+        INSERT INTO planned (allocation_id, system_id, start_time, end_time, capabilities)
+        VALUES ($1, $2, $3, $4, $5)
+            "#,
+            allocation_id,
+            system,
+            start,
+            end,
+            capabilities.bits() as i64,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| AllocationError::from_insert(e, system, Some((start, end))))?;
+
+        NewAllocation {
+            system,
+            allocation_id,
+            kind: AllocationKind::Capability,
+            planned: true,
+            start,
+            end: Some(end),
+            capabilities,
+        }
+        .insert(&mut *tx)
+        .await?;
+
+        Ok(allocation_id)
+    }
+}
+
+impl SystemAllocation {
+    /// Get the configuration of a system, or `None` if it has not been declared.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_system(&self, system: Uuid) -> Result<Option<SystemInfo>, AllocationError> {
+        Ok(system_info(&self.pool, system).await?)
+    }
+
+    /// Get the timezone of a system, see [`SystemAllocation::set_timezone`].
+    ///
+    /// Fails with [`AllocationError::SystemNotFound`] if the system has not been declared.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_system_timezone(&self, system: Uuid) -> Result<Option<Tz>, AllocationError> {
+        Ok(system_info(&self.pool, system)
+            .await?
+            .ok_or(AllocationError::SystemNotFound(system))?
+            .timezone)
+    }
+
+    /// List every declared system providing all of `capabilities`, ordered by id.
+    ///
+    /// Every system is listed when no capabilities are given.
+    #[tracing::instrument(skip(self))]
+    pub async fn list_systems_with_capability(
+        &self,
+        capabilities: Capabilities,
+    ) -> Result<Vec<Uuid>, AllocationError> {
+        let systems = sqlx::query_scalar!(
+            r#"
+        SELECT system_id FROM systems
+        WHERE capabilities & $1 = $1
+        ORDER BY system_id
+            "#,
+            capabilities.bits() as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(systems)
+    }
+
+    /// Get any kind of allocation by its id, or `None` if it does not exist.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_allocation(
+        &self,
+        allocation_id: Uuid,
+    ) -> Result<Option<Allocation>, AllocationError> {
+        Ok(allocation(&self.pool, allocation_id).await?)
+    }
+
+    /// List all entries on the system overlapping the timespan `[start, end)` of `range`, ordered
+    /// by start. All entries on the system are listed when no range is given.
+    ///
+    /// An entry ending exactly at `start`, or starting exactly at `end`, is not included. Holds
+    /// are not listed until they are confirmed.
+    #[tracing::instrument(skip(self))]
+    pub async fn list_entries(
+        &self,
+        system: Uuid,
+        range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> Result<Vec<Entry>, AllocationError> {
+        let (start, end) = range.unzip();
+        let rows = sqlx::query!(
+            r#"
+        SELECT a.allocation_id, a.start_time, NULLIF(a.end_time, 'infinity') AS end_time,
+            a.capabilities, e.owner, e.label, e.metadata, e.pinned, e.priority,
+            e.status AS "status: EntryStatus", e.cancel_reason
+        FROM allocations a
+        JOIN entries e USING (allocation_id)
+        WHERE a.system_id = $1
+            AND a.kind = 'entry'
+            AND a.allocation_id NOT IN (SELECT allocation_id FROM holds)
+            AND ($2::timestamptz IS NULL OR $2 < a.end_time)
+            AND ($3::timestamptz IS NULL OR $3 > a.start_time)
+        ORDER BY a.start_time
+            "#,
+            system,
+            start,
+            end,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                Ok(Entry {
+                    allocation_id: row.allocation_id,
+                    start_time: row.start_time,
+                    end_time: row.end_time,
+                    capabilities: decode_capabilities(row.capabilities)?,
+                    params: EntryParams {
+                        owner: row.owner,
+                        label: row.label,
+                        metadata: row.metadata,
+                        pinned: row.pinned,
+                        priority: row.priority,
+                        status: row.status,
+                        cancel_reason: row.cancel_reason,
+                    },
+                })
+            })
+            .collect::<Result<_, sqlx::Error>>()?)
+    }
+
+    /// List the entries of `owner` across all systems, along with the system of each, as with
+    /// [`SystemAllocation::list_entries`].
+    ///
+    /// Only entries inserted with an owner, see [`SystemAllocation::insert_entry_with_params`],
+    /// are listed.
+    #[tracing::instrument(skip(self))]
+    pub async fn list_entries_by_owner(
+        &self,
+        owner: Uuid,
+        range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> Result<Vec<(Uuid, Entry)>, AllocationError> {
+        let (start, end) = range.unzip();
+        let rows = sqlx::query!(
+            r#"
+        SELECT a.system_id, a.allocation_id, a.start_time,
+            NULLIF(a.end_time, 'infinity') AS end_time, a.capabilities,
+            e.owner, e.label, e.metadata, e.pinned, e.priority,
+            e.status AS "status: EntryStatus", e.cancel_reason
+        FROM entries e
+        JOIN allocations a USING (allocation_id)
+        WHERE e.owner = $1
+            AND a.allocation_id NOT IN (SELECT allocation_id FROM holds)
+            AND ($2::timestamptz IS NULL OR $2 < a.end_time)
+            AND ($3::timestamptz IS NULL OR $3 > a.start_time)
+        ORDER BY a.start_time
+            "#,
+            owner,
+            start,
+            end,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let entry = Entry {
+                    allocation_id: row.allocation_id,
+                    start_time: row.start_time,
+                    end_time: row.end_time,
+                    capabilities: decode_capabilities(row.capabilities)?,
+                    params: EntryParams {
+                        owner: row.owner,
+                        label: row.label,
+                        metadata: row.metadata,
+                        pinned: row.pinned,
+                        priority: row.priority,
+                        status: row.status,
+                        cancel_reason: row.cancel_reason,
+                    },
+                };
+                Ok((row.system_id, entry))
+            })
+            .collect::<Result<_, sqlx::Error>>()?)
+    }
+
+    /// List all outages on the system overlapping the timespan `[start, end)`, ordered by start.
+    ///
+    /// Unresolved unplanned outages are open-ended, and are listed for any range after they
+    /// started.
+    #[tracing::instrument(skip(self))]
+    pub async fn list_outages(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Outage>, AllocationError> {
+        Ok(overlapping_outages(&self.pool, system, start, end).await?)
+    }
+}
+
+impl SystemAllocation {
+    /// Free capacity for entries requiring `capabilities` on the system over the timespan
+    /// `[start, end)`.
+    ///
+    /// The timespan is partitioned at the boundaries of every allocation within it, reporting
+    /// the number of free slots for each sub-interval. The free slots are those of the tightest
+    /// of the capacities an entry would be limited by, see
+    /// [`SystemAllocation::declare_system_with_capacities`], with the system capacity reduced
+    /// by any capacity reductions in effect. Any outage affecting the capabilities reduces the
+    /// free capacity to zero for its duration.
+    ///
+    /// Fails with [`AllocationError::InvalidRange`] if `start` is not before `end`, and
+    /// [`AllocationError::UnsupportedCapabilities`] unless the system provides all of
+    /// `capabilities`, as no such entry could be inserted.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_availability(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<Availability, AllocationError> {
+        check_range(start, end)?;
+
+        let mut tx = self.pool.begin().await?;
+        let limits = CapacityLimits::load(&mut tx, system, capabilities).await?;
+        let allocations =
+            overlapping_allocations(&mut tx, system, start, end, BoundaryPolicy::Touching).await?;
+        tx.rollback().await?;
+
+        Ok(availability(
+            &limits,
+            &allocations,
+            start,
+            end,
+            capabilities,
+        ))
+    }
+
+    /// The number of entries on the system at the instant `at`, sharing any of `capabilities`.
+    ///
+    /// An entry is active from its start, until but not including its end.
+    #[tracing::instrument(skip(self))]
+    pub async fn count_active_entries(
+        &self,
+        system: Uuid,
+        at: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<i32, AllocationError> {
+        system_info(&self.pool, system)
+            .await?
+            .ok_or(AllocationError::SystemNotFound(system))?;
+        // The database only keeps microsecond precision.
+        let allocations = overlapping_allocations(
+            &self.pool,
+            system,
+            at,
+            at + Duration::microseconds(1),
+            BoundaryPolicy::Touching,
+        )
+        .await?;
+
+        Ok(entries_sharing(&allocations, capabilities) as i32)
+    }
+
+    /// The occupancy of the system over the timespan `[start, end)`, partitioned into segments
+    /// at the boundaries of every allocation and capacity reduction within it.
+    ///
+    /// During an outage of the entire system, be it planned or unplanned, nothing is available.
+    /// Capacity reductions in effect reduce what remains, see
+    /// [`SystemAllocation::insert_capacity_reduction`].
+    #[tracing::instrument(skip(self))]
+    pub async fn capacity_timeline(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<CapacitySegment>, AllocationError> {
+        check_range(start, end)?;
+
+        let info = system_info(&self.pool, system)
+            .await?
+            .ok_or(AllocationError::SystemNotFound(system))?;
+        let allocations =
+            overlapping_allocations(&self.pool, system, start, end, BoundaryPolicy::Touching)
+                .await?;
+        let reductions = capacity_reductions(&self.pool, &[system])
+            .await?
+            .remove(&system)
+            .unwrap_or_default();
+
+        Ok(reduced_breakpoints(&allocations, &reductions, start, end)
+            .windows(2)
+            .map(|w| {
+                let (from, to) = (w[0], w[1]);
+                let mut entries = 0;
+                let mut capabilities = info.capabilities;
+                for allocation in covering(&allocations, from, to) {
+                    if allocation.kind == AllocationKind::Entry {
+                        entries += 1;
+                    } else {
+                        capabilities -= allocation.capabilities;
+                    }
+                }
+                let remaining = if capabilities.is_empty() {
+                    0
+                } else {
+                    (info.capacity - peak_reduction(&reductions, from, to) - entries).max(0)
+                };
+                CapacitySegment {
+                    start: from,
+                    end: to,
+                    entries,
+                    remaining,
+                    capabilities,
+                }
+            })
+            .collect())
+    }
+}
+
+/// The occupancy of a system over the half-open timespan `[start, end)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CapacitySegment {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// The number of concurrent entries.
+    pub entries: i32,
+    /// The number of entries that may still be added.
+    pub remaining: i32,
+    /// The capabilities of the system not in outage.
+    pub capabilities: Capabilities,
+}
+
+/// The free capacity of each sub-interval of a timespan, as `(start, end, free)`.
+type Availability = Vec<(DateTime<Utc>, DateTime<Utc>, i32)>;
+
+/// Partition `[start, end)` at the boundaries of `allocations`, with the free capacity of each
+/// sub-interval for entries requiring `capabilities`, limited by `limits`.
+fn availability(
+    limits: &CapacityLimits,
+    allocations: &[Allocation],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    capabilities: Capabilities,
+) -> Availability {
+    reduced_breakpoints(allocations, &limits.reductions, start, end)
+        .windows(2)
+        .map(|w| {
+            let (from, to) = (w[0], w[1]);
+            let mut entries = Vec::new();
+            let mut blocked = false;
+            for allocation in covering(allocations, from, to) {
+                if allocation.kind == AllocationKind::Entry {
+                    entries.push(allocation);
+                } else if allocation.capabilities.intersects(capabilities) {
+                    blocked = true;
+                }
+            }
+            let free = if blocked {
+                0
+            } else {
+                limits.free(&entries, from, to)
+            };
+            (from, to, free)
+        })
+        .collect()
+}
+
+/// The boundaries of `allocations` within `[start, end)`, including `start` and `end`, in order.
+fn breakpoints(
+    allocations: &[Allocation],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Vec<DateTime<Utc>> {
+    let mut breakpoints: Vec<_> = allocations
+        .iter()
+        .flat_map(|a| [Some(a.start_time), a.end_time])
+        .flatten()
+        .filter(|t| start < *t && *t < end)
+        .chain([start, end])
+        .collect();
+    breakpoints.sort();
+    breakpoints.dedup();
+    breakpoints
+}
+
+/// The [`breakpoints`] of `allocations`, along with the boundaries of `reductions` within
+/// `[start, end)`.
+fn reduced_breakpoints(
+    allocations: &[Allocation],
+    reductions: &[CapacityReduction],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Vec<DateTime<Utc>> {
+    let mut breakpoints = breakpoints(allocations, start, end);
+    breakpoints.extend(
+        reductions
+            .iter()
+            .flat_map(|r| [Some(r.start), r.end])
+            .flatten()
+            .filter(|t| start < *t && *t < end),
+    );
+    breakpoints.sort();
+    breakpoints.dedup();
+    breakpoints
+}
+
+/// The allocations covering all of `[from, to)`, which must not contain any of their boundaries.
+fn covering(
+    allocations: &[Allocation],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> impl Iterator<Item = &Allocation> {
+    allocations
+        .iter()
+        .filter(move |a| intervals_overlap((a.start_time, a.end_time), (from, Some(to))))
+}
+
+/// Decode capabilities read from the database, failing on any bits not known to us rather than
+/// silently dropping them.
+fn decode_capabilities(bits: i64) -> Result<Capabilities, sqlx::Error> {
+    Capabilities::try_from_bits_strict(bits as u64).map_err(|e| sqlx::Error::Decode(e.into()))
+}
+
+/// Fail unless `start` is strictly before `end`.
+///
+/// Zero-length timespans are rejected for outages as well as entries, as neither would occupy
+/// any time on the system.
+fn check_range(start: DateTime<Utc>, end: DateTime<Utc>) -> Result<(), AllocationError> {
+    if start >= end {
+        return Err(AllocationError::InvalidRange { start, end });
+    }
+
+    Ok(())
+}
+
+/// Fail if an entry does not require any capabilities, as it would never be in conflict with
+/// any outage.
+fn check_entry_capabilities(capabilities: Capabilities) -> Result<(), AllocationError> {
+    if capabilities.is_empty() {
+        return Err(AllocationError::Validation(
+            "an entry must require at least one capability".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Insert an entry with the given allocation id within `tx`, open-ended without an `end`.
+///
+/// See [`SystemAllocation::insert_entry`] for how conflicts are reported, and
+/// [`SystemAllocation::insert_entry_with_id`] for an existing allocation with the same id.
+async fn insert_entry(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    allocation_id: Uuid,
+    start: DateTime<Utc>,
+    end: Option<DateTime<Utc>>,
+    capabilities: Capabilities,
+    params: &EntryParams,
+) -> Result<(), AllocationError> {
+    if let Some(end) = end {
+        check_range(start, end)?;
+    }
+    if let Some(existing) = allocation(&mut *tx, allocation_id).await? {
+        return check_same_entry(existing, system, start, end, capabilities);
+    }
+    check_entry_conflicts(tx, system, start, end, capabilities, None).await?;
+
+    // A concurrent insert with the same id makes us wait for it to commit, rather than both
+    // passing the check above.
+    let inserted = sqlx::query!(
+        r#"
+    INSERT INTO entries(allocation_id, start_time, end_time, owner, label, metadata, pinned,
+        priority)
+    VALUES ($1, $2, COALESCE($3, 'infinity'::timestamptz), $4, $5, $6, $7, $8)
+    ON CONFLICT (allocation_id) DO NOTHING
+        "#,
+        allocation_id,
+        start,
+        end,
+        params.owner,
+        params.label,
+        params.metadata,
+        params.pinned,
+        params.priority,
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| AllocationError::from_insert(e, system, end.map(|end| (start, end))))?
+    .rows_affected();
+    if inserted == 0 {
+        let existing =
+            allocation(&mut *tx, allocation_id)
+                .await?
+                .ok_or(AllocationError::Conflict {
+                    conflicts: Vec::new(),
+                })?;
+        return check_same_entry(existing, system, start, end, capabilities);
+    }
+
+    NewAllocation {
+        system,
+        allocation_id,
+        kind: AllocationKind::Entry,
+        planned: true,
+        start,
+        end,
+        capabilities,
+    }
+    .insert(tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Succeed if `existing` is the entry described by the remaining arguments, and otherwise fail
+/// with [`AllocationError::Conflict`] listing it.
+fn check_same_entry(
+    existing: Allocation,
+    system: Uuid,
+    start: DateTime<Utc>,
+    end: Option<DateTime<Utc>>,
+    capabilities: Capabilities,
+) -> Result<(), AllocationError> {
+    // Compare at the precision the timespan of the existing entry was stored with.
+    let stored = |t: DateTime<Utc>| t.duration_trunc(Duration::microseconds(1)).unwrap_or(t);
+    let same = existing.kind == AllocationKind::Entry
+        && existing.system_id == system
+        && existing.start_time == stored(start)
+        && existing.end_time == end.map(stored)
+        && existing.capabilities == capabilities;
+    if !same {
+        let blocker = Blocker::of(&existing);
+        let conflict = ConflictInfo::new(existing, blocker, start, end, capabilities);
+        return Err(AllocationError::Conflict {
+            conflicts: vec![conflict],
+        });
+    }
+
+    Ok(())
+}
+
+/// Insert an unplanned outage of `capabilities` within `tx`, returning its allocation id.
+///
+/// It is an outage of the entire system if `capabilities` are all of them.
+async fn insert_unplanned_outage(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    capabilities: Capabilities,
+    start: DateTime<Utc>,
+    interval: PgInterval,
+) -> Result<Uuid, AllocationError> {
+    // Evaluate the window as the database will, with the start truncated to its precision.
+    let window_start = start
+        .duration_trunc(Duration::microseconds(1))
+        .unwrap_or(start);
+    let window = interval_to_duration(interval.clone());
+    check_outage_conflicts(
+        &mut *tx,
+        system,
+        window_start,
+        None,
+        Some(window),
+        capabilities,
+        None,
+    )
+    .await?;
+
+    let allocation_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+    INSERT INTO unplanned (allocation_id, system_id, start_time, sliding_window, capabilities)
+    VALUES ($1, $2, $3, $4, $5)
+        "#,
+        allocation_id,
+        system,
+        start,
+        interval,
+        capabilities.bits() as i64,
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| AllocationError::from_insert(e, system, None))?;
+
+    NewAllocation {
+        system,
+        allocation_id,
+        kind: if capabilities == Capabilities::all() {
+            AllocationKind::Full
+        } else {
+            AllocationKind::Capability
+        },
+        planned: false,
+        start,
+        end: None,
+        capabilities,
+    }
+    .insert(tx)
+    .await?;
+
+    Ok(allocation_id)
+}
+
+/// Lock the planned outage with the given id for the remainder of `tx`, returning its timespan.
+async fn lock_planned_outage(
+    tx: &mut Transaction<'_, Postgres>,
+    allocation_id: Uuid,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), AllocationError> {
+    let outage = sqlx::query!(
+        r#"
+    SELECT start_time, end_time FROM planned
+    WHERE allocation_id = $1
+    FOR UPDATE
+        "#,
+        allocation_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    match outage {
+        Some(outage) => Ok((outage.start_time, outage.end_time)),
+        None => Err(not_of_kind(tx, allocation_id).await?),
+    }
+}
+
+/// Delete the planned outage with the given id within `tx`.
+async fn delete_planned_outage(
+    tx: &mut Transaction<'_, Postgres>,
+    allocation_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+    DELETE FROM allocations WHERE allocation_id = $1
+        "#,
+        allocation_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"
+    DELETE FROM planned WHERE allocation_id = $1
+        "#,
+        allocation_id,
+    )
+    .execute(tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Fail with every allocation an entry over `[start, end)` for `capabilities` would conflict
+/// with, as found by [`entry_conflicts`].
+///
+/// This is evaluated within the same transaction as the insert, such that what we report is what
+/// the insert would have been rejected by.
+async fn check_entry_conflicts(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    start: DateTime<Utc>,
+    end: Option<DateTime<Utc>>,
+    capabilities: Capabilities,
+    modifying: Option<Uuid>,
+) -> Result<(), AllocationError> {
+    lock_system(&mut *tx, system).await?;
+    let conflicts = entry_conflicts(tx, system, start, end, capabilities, modifying).await?;
+    entry_conflicts_to_error(tx, system, conflicts, (start, end), capabilities).await
+}
+
+/// Fail as [`conflicts_to_error`] with the conflicts of an entry over `[start, end)`, telling how
+/// much of the capacity is taken should it be exceeded.
+async fn entry_conflicts_to_error(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    conflicts: Vec<ConflictInfo>,
+    span: (DateTime<Utc>, Option<DateTime<Utc>>),
+    capabilities: Capabilities,
+) -> Result<(), AllocationError> {
+    let result = conflicts_to_error(conflicts);
+    if !matches!(result, Err(AllocationError::CapacityExceeded { .. })) {
+        return result;
+    }
+
+    let limits = CapacityLimits::load(tx, system, capabilities).await?;
+    limits.with_occupancy(result, span)
+}
+
+/// Fail with [`AllocationError::WindowViolation`] if an entry over `[start, end)` for
+/// `capabilities` is within the sliding window of an unplanned outage, as it is then due to be
+/// removed and may not be moved.
+async fn check_not_in_window(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    capabilities: Capabilities,
+) -> Result<(), AllocationError> {
+    let in_window = unplanned_windows_overlapping(&mut *tx, system, start, end).await?;
+    if in_window.is_empty() {
+        return Ok(());
+    }
+
+    let conflicts: Vec<_> =
+        overlapping_allocations(&mut *tx, system, start, end, BoundaryPolicy::Touching)
+            .await?
+            .into_iter()
+            .filter(|a| in_window.contains(&a.allocation_id))
+            .filter(|a| a.capabilities.intersects(capabilities))
+            .map(|a| ConflictInfo::new(a, Blocker::UnplannedWindow, start, Some(end), capabilities))
+            .collect();
+    if !conflicts.is_empty() {
+        return Err(AllocationError::WindowViolation { conflicts });
+    }
+
+    Ok(())
+}
+
+/// The details of the entries among `allocation_ids`, keyed by allocation id.
+async fn entry_params(
+    executor: impl PgExecutor<'_>,
+    allocation_ids: &[Uuid],
+) -> Result<HashMap<Uuid, EntryParams>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+    SELECT allocation_id, owner, label, metadata, pinned, priority,
+        status AS "status: EntryStatus", cancel_reason
+    FROM entries
+    WHERE allocation_id = ANY($1)
+        "#,
+        allocation_ids,
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let params = EntryParams {
+                owner: row.owner,
+                label: row.label,
+                metadata: row.metadata,
+                pinned: row.pinned,
+                priority: row.priority,
+                status: row.status,
+                cancel_reason: row.cancel_reason,
+            };
+            (row.allocation_id, params)
+        })
+        .collect())
+}
+
+/// Delete the rows of the entries among `allocation_ids` from the entries table, returning their
+/// details keyed by allocation id.
+async fn delete_entry_rows(
+    executor: impl PgExecutor<'_>,
+    allocation_ids: &[Uuid],
+) -> Result<HashMap<Uuid, EntryParams>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+    DELETE FROM entries WHERE allocation_id = ANY($1)
+    RETURNING allocation_id, owner, label, metadata, pinned, priority,
+        status AS "status: EntryStatus", cancel_reason
+        "#,
+        allocation_ids,
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let params = EntryParams {
+                owner: row.owner,
+                label: row.label,
+                metadata: row.metadata,
+                pinned: row.pinned,
+                priority: row.priority,
+                status: row.status,
+                cancel_reason: row.cancel_reason,
+            };
+            (row.allocation_id, params)
+        })
+        .collect())
+}
+
+/// Attach the details of the entries in the way to `conflicts`, such that their owners may be
+/// told apart. Holds are left without, as they have none until confirmed.
+async fn describe_conflicts(
+    executor: impl PgExecutor<'_>,
+    conflicts: &mut [ConflictInfo],
+) -> Result<(), sqlx::Error> {
+    let entries: Vec<_> = conflicts
+        .iter()
+        .filter(|c| c.allocation.kind == AllocationKind::Entry)
+        .map(|c| c.allocation.allocation_id)
+        .collect();
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let mut params = entry_params(executor, &entries).await?;
+    for conflict in conflicts {
+        conflict.entry = params.remove(&conflict.allocation.allocation_id);
+    }
+
+    Ok(())
+}
+
+/// Fail with the error variant naming the most severe of `conflicts`, if there are any.
+fn conflicts_to_error(conflicts: Vec<ConflictInfo>) -> Result<(), AllocationError> {
+    let blocked_by = |blockers: &[Blocker]| {
+        conflicts
+            .iter()
+            .filter(|c| blockers.contains(&c.blocker))
+            .count()
+    };
+    let unplanned = blocked_by(&[Blocker::UnplannedWindow]);
+    let outage = blocked_by(&[Blocker::PlannedOutage, Blocker::CapabilityOutage]);
+    if !conflicts.is_empty() {
+        tracing::debug!(
+            unplanned,
+            outage,
+            capacity = blocked_by(&[Blocker::Capacity]),
+            "rejected: {} overlapping allocation(s)",
+            conflicts.len(),
+        );
+    }
+
+    if unplanned > 0 {
+        Err(AllocationError::WindowViolation { conflicts })
+    } else if outage > 0 {
+        Err(AllocationError::CapabilityOutage { conflicts })
+    } else if !conflicts.is_empty() {
+        Err(AllocationError::CapacityExceeded {
+            conflicts,
+            occupancy: None,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Every allocation an entry over `[start, end)` for `capabilities` would conflict with, be it
+/// outages sharing any of its capabilities or entries exhausting the system capacity. An entry
+/// without an `end` conflicts with everything after its start.
+///
+/// When `modifying` an existing entry, it is excluded from the evaluation, and unplanned outages
+/// are only in conflict within their sliding window.
+async fn entry_conflicts(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    start: DateTime<Utc>,
+    end: Option<DateTime<Utc>>,
+    capabilities: Capabilities,
+    modifying: Option<Uuid>,
+) -> Result<Vec<ConflictInfo>, AllocationError> {
+    check_entry_capabilities(capabilities)?;
+    let until = end.unwrap_or(DateTime::<Utc>::MAX_UTC);
+    let boundary = boundary_policy(&mut *tx, system).await?;
+    let overlapping = overlapping_allocations(&mut *tx, system, start, until, boundary).await?;
+    let mut limits = CapacityLimits::load(tx, system, capabilities).await?;
+    tracing::debug!(overlapping = overlapping.len(), "evaluating entry");
+
+    // Entries may be moved beyond the sliding window of an unplanned outage, just not into it,
+    // and likewise only the capacity reductions with a window over the entry apply to it.
+    let in_window = match modifying {
+        Some(_) => unplanned_windows_overlapping(tx, system, start, until).await?,
+        None => Vec::new(),
+    };
+    if modifying.is_some() {
+        limits
+            .reductions
+            .retain(|r| r.start < until && r.window_end.is_some_and(|end| end > start));
+    }
+
+    let mut conflicts = classify_conflicts(
+        overlapping,
+        &limits,
+        (start, end),
+        capabilities,
+        modifying,
+        &in_window,
+    );
+    describe_conflicts(tx, &mut conflicts).await?;
+
+    Ok(conflicts)
+}
+
+/// The earliest start of a slot of `duration` for `capabilities` within `[after, until)`, as
+/// seen within `tx`.
+async fn next_free_slot(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    after: DateTime<Utc>,
+    until: DateTime<Utc>,
+    duration: Duration,
+    capabilities: Capabilities,
+) -> Result<Option<DateTime<Utc>>, AllocationError> {
+    if duration <= Duration::zero() {
+        return Err(AllocationError::Validation(format!(
+            "slot duration must be positive, got {duration}"
+        )));
+    }
+    check_entry_capabilities(capabilities)?;
+    if until <= after {
+        return Ok(None);
+    }
+
+    let limits = CapacityLimits::load(&mut *tx, system, capabilities).await?;
+    let boundary = boundary_policy(&mut *tx, system).await?;
+    let allocations = overlapping_allocations(tx, system, after, until, boundary).await?;
+
+    // A slot can always be moved earlier until it either starts at `after`, or right as
+    // another allocation ends, so those are the only starts worth considering.
+    let mut candidates: Vec<_> = allocations
+        .iter()
+        .filter_map(|a| a.end_time)
+        .map(|end| boundary.next_start(end))
+        .filter(|start| *start > after)
+        .chain(std::iter::once(after))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+
+    Ok(candidates.into_iter().find(|start| {
+        let Some(end) = start
+            .checked_add_signed(duration)
+            .filter(|end| *end <= until)
+        else {
+            return false;
+        };
+        let overlapping = allocations
+            .iter()
+            .filter(|a| boundary.overlaps((a.start_time, a.end_time), (*start, Some(end))))
+            .cloned();
+        classify_conflicts(
+            overlapping,
+            &limits,
+            (*start, Some(end)),
+            capabilities,
+            None,
+            &[],
+        )
+        .is_empty()
+    }))
+}
+
+/// The capacities an entry requiring some capabilities is limited by.
+struct CapacityLimits {
+    /// Shared by all entries on the system, regardless of capabilities.
+    system: i32,
+    /// Only shared by the entries requiring that capability, as `(capability, capacity)`.
+    capabilities: Vec<(Capabilities, i32)>,
+    /// Reducing the system capacity while in effect, see
+    /// [`SystemAllocation::insert_capacity_reduction`].
+    reductions: Vec<CapacityReduction>,
+}
+
+impl CapacityLimits {
+    /// The limits for an entry requiring `capabilities` on `system`, failing unless the system
+    /// has been declared with all of them.
+    async fn load(
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        capabilities: Capabilities,
+    ) -> Result<Self, AllocationError> {
+        let capacity = system_capacity(tx, system, capabilities).await?;
+        let rows = sqlx::query!(
+            r#"
+        SELECT capability, capacity FROM system_capacities
+        WHERE system_id = $1 AND capability & $2 != 0
+            "#,
+            system,
+            capabilities.bits() as i64,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let reductions = capacity_reductions(&mut *tx, &[system])
+            .await?
+            .remove(&system)
+            .unwrap_or_default();
+
+        Ok(Self {
+            system: capacity,
+            capabilities: rows
+                .into_iter()
+                .map(|r| Ok((decode_capabilities(r.capability)?, r.capacity)))
+                .collect::<Result<_, sqlx::Error>>()?,
+            reductions,
+        })
+    }
+
+    /// Whether adding another entry over `[start, end)` to `entries` would exceed any of the
+    /// limits.
+    fn exceeded_by(
+        &self,
+        entries: &[Allocation],
+        span: (DateTime<Utc>, Option<DateTime<Utc>>),
+    ) -> bool {
+        self.exceeded(entries, span).is_some()
+    }
+
+    /// The first of the limits adding another entry over `[start, end)` to `entries` would
+    /// exceed, as the capacity and its capability, `None` for the system, along with the entries
+    /// sharing it.
+    fn exceeded(
+        &self,
+        entries: &[Allocation],
+        (start, end): (DateTime<Utc>, Option<DateTime<Utc>>),
+    ) -> Option<(i32, Option<Capabilities>, Vec<Allocation>)> {
+        // The system capacity at its most reduced over the entry.
+        let until = end.unwrap_or(DateTime::<Utc>::MAX_UTC);
+        let system = (self.system - peak_reduction(&self.reductions, start, until)).max(0);
+        if entries.len() as i32 + 1 > system {
+            return Some((system, None, entries.to_vec()));
+        }
+        self.capabilities
+            .iter()
+            .find(|(capability, capacity)| {
+                entries_sharing(entries, *capability) as i32 + 1 > *capacity
+            })
+            .map(|(capability, capacity)| {
+                let sharing = entries
+                    .iter()
+                    .filter(|a| a.capabilities.intersects(*capability))
+                    .cloned()
+                    .collect();
+                (*capacity, Some(*capability), sharing)
+            })
+    }
+
+    /// How many more entries fit within the limits over `[from, to)` alongside `entries`, all
+    /// of them covering it.
+    fn free(&self, entries: &[&Allocation], from: DateTime<Utc>, to: DateTime<Utc>) -> i32 {
+        let system = self.system - peak_reduction(&self.reductions, from, to);
+        self.capabilities
+            .iter()
+            .map(|(capability, capacity)| {
+                capacity - entries_sharing(entries.iter().copied(), *capability) as i32
+            })
+            .fold(system - entries.len() as i32, i32::min)
+            .max(0)
+    }
+
+    /// Attach to a [`AllocationError::CapacityExceeded`] of an entry over `[start, end)` how
+    /// much of the limit it exceeds is taken, at its busiest within the entry.
+    fn with_occupancy(
+        &self,
+        result: Result<(), AllocationError>,
+        span: (DateTime<Utc>, Option<DateTime<Utc>>),
+    ) -> Result<(), AllocationError> {
+        let (start, end) = span;
+        let Err(AllocationError::CapacityExceeded {
+            conflicts,
+            occupancy: None,
+        }) = result
+        else {
+            return result;
+        };
+        let entries: Vec<_> = conflicts
+            .iter()
+            .filter(|c| c.blocker == Blocker::Capacity)
+            .map(|c| c.allocation.clone())
+            .collect();
+
+        let occupancy = self
+            .exceeded(&entries, span)
+            .map(|(capacity, capability, sharing)| {
+                let peak = capacity_peak(&sharing, start, end.unwrap_or(DateTime::<Utc>::MAX_UTC));
+                // The count holds until the next of the entries either starts or ends.
+                let peak_end = sharing
+                    .iter()
+                    .flat_map(|a| [Some(a.start_time), a.end_time])
+                    .flatten()
+                    .filter(|t| *t > peak.at)
+                    .chain(end)
+                    .min();
+                CapacityOccupancy {
+                    occupied: peak.concurrent as i32,
+                    capacity,
+                    capability,
+                    peak_start: peak.at,
+                    peak_end,
+                }
+            });
+
+        Err(AllocationError::CapacityExceeded {
+            conflicts,
+            occupancy,
+        })
+    }
+}
+
+/// The number of entries among `allocations` sharing any of `capabilities`.
+fn entries_sharing<'a>(
+    allocations: impl IntoIterator<Item = &'a Allocation>,
+    capabilities: Capabilities,
+) -> usize {
+    allocations
+        .into_iter()
+        .filter(|a| a.kind == AllocationKind::Entry && a.capabilities.intersects(capabilities))
+        .count()
+}
+
+/// Pick out the allocations among `overlapping` an entry for `capabilities` is in conflict with.
+///
+/// See [`entry_conflicts`] for `modifying`, with `in_window` being the unplanned outages whose
+/// sliding window overlaps the entry.
+fn classify_conflicts(
+    overlapping: impl IntoIterator<Item = Allocation>,
+    limits: &CapacityLimits,
+    (start, end): (DateTime<Utc>, Option<DateTime<Utc>>),
+    capabilities: Capabilities,
+    modifying: Option<Uuid>,
+    in_window: &[Uuid],
+) -> Vec<ConflictInfo> {
+    let (entries, outages): (Vec<_>, Vec<_>) = overlapping
+        .into_iter()
+        .filter(|a| Some(a.allocation_id) != modifying)
+        .filter(|a| a.kind == AllocationKind::Entry || a.capabilities.intersects(capabilities))
+        .filter(|a| a.planned || modifying.is_none() || in_window.contains(&a.allocation_id))
+        .partition(|a| a.kind == AllocationKind::Entry);
+    let exceeded = limits.exceeded_by(&entries, (start, end));
+
+    let mut conflicts: Vec<_> = outages
+        .into_iter()
+        .map(|allocation| {
+            let blocker = Blocker::of(&allocation);
+            ConflictInfo::new(allocation, blocker, start, end, capabilities)
+        })
+        .collect();
+    if exceeded {
+        conflicts.extend(entries.iter().map(|allocation| {
+            let mut conflict = ConflictInfo::new(
+                allocation.clone(),
+                Blocker::Capacity,
+                start,
+                end,
+                capabilities,
+            );
+            conflict.peak = Some(capacity_peak(
+                &entries,
+                conflict.overlap_start,
+                conflict
+                    .overlap_end
+                    .or(end)
+                    .unwrap_or(DateTime::<Utc>::MAX_UTC),
+            ));
+            conflict
+        }));
+        conflicts.sort_by_key(|c| c.allocation.start_time);
+    }
+
+    conflicts
+}
+
+/// The instant within `[from, to)` covered by the most of `entries`.
+fn capacity_peak(entries: &[Allocation], from: DateTime<Utc>, to: DateTime<Utc>) -> CapacityPeak {
+    let concurrent_at = |at: DateTime<Utc>| {
+        entries
+            .iter()
+            .filter(|a| a.start_time <= at && a.end_time.is_none_or(|e| e > at))
+            .count()
+    };
+
+    // The count only ever increases as an entry starts.
+    entries
+        .iter()
+        .map(|a| a.start_time)
+        .filter(|start| *start > from && *start < to)
+        .chain(std::iter::once(from))
+        .map(|at| CapacityPeak {
+            at,
+            concurrent: concurrent_at(at),
+        })
+        .max_by_key(|peak| (peak.concurrent, std::cmp::Reverse(peak.at)))
+        .expect("the start of the range is always a candidate")
+}
+
+/// Lock `system` against concurrent writes of allocations until `tx` ends, such that the checks
+/// preceding a write still hold when it commits. Without it, two transactions may both pass the
+/// checks for the last free slot, as neither sees the entry of the other.
+///
+/// Does nothing for a system that has not been declared, which the checks fail on regardless.
+async fn lock_system(tx: &mut Transaction<'_, Postgres>, system: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+    SELECT system_id FROM systems WHERE system_id = $1 FOR UPDATE
+        "#,
+        system,
+    )
+    .fetch_optional(tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Lock the system for changing its capacity to `capacity`, returning its current capacity.
+async fn lock_capacity(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    capacity: i32,
+) -> Result<i32, AllocationError> {
+    if capacity < 0 {
+        return Err(AllocationError::Validation(format!(
+            "capacity must not be negative, got {capacity}"
+        )));
+    }
+
+    sqlx::query_scalar!(
+        r#"
+    SELECT capacity FROM systems WHERE system_id = $1 FOR UPDATE
+        "#,
+        system,
+    )
+    .fetch_optional(tx)
+    .await?
+    .ok_or(AllocationError::SystemNotFound(system))
+}
+
+/// Set the capacity of `system`, locked by [`lock_capacity`].
+async fn set_capacity(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    capacity: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+    UPDATE systems SET capacity = $2 WHERE system_id = $1
+        "#,
+        system,
+        capacity,
+    )
+    .execute(tx)
+    .await?;
+
+    Ok(())
+}
+
+/// The entries on `system` yet to end at `from`, in the order they were created.
+async fn remaining_entries(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    from: DateTime<Utc>,
+) -> Result<Vec<Allocation>, sqlx::Error> {
+    let created = sqlx::query_scalar!(
+        r#"
+    SELECT allocation_id FROM allocations
+    WHERE system_id = $1 AND kind = 'entry' AND end_time > $2
+    ORDER BY created_at, allocation_id
+        "#,
+        system,
+        from,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let order: HashMap<_, _> = created
+        .into_iter()
+        .enumerate()
+        .map(|(i, id)| (id, i))
+        .collect();
+
+    let mut entries: Vec<_> = overlapping_allocations(
+        tx,
+        system,
+        from,
+        DateTime::<Utc>::MAX_UTC,
+        BoundaryPolicy::Touching,
+    )
+    .await?
+    .into_iter()
+    .filter(|a| a.kind == AllocationKind::Entry)
+    .collect();
+    entries.sort_by_key(|a| order.get(&a.allocation_id).copied());
+
+    Ok(entries)
+}
+
+/// The fewest of `entries` to remove for at most `capacity` of them to be concurrent from
+/// `from` on, picking the last of them first at each instant over capacity.
+fn capacity_excess(entries: &[Allocation], capacity: i32, from: DateTime<Utc>) -> Vec<Allocation> {
+    let until = entries
+        .iter()
+        .filter_map(|a| a.end_time)
+        .max()
+        .unwrap_or(from);
+
+    let mut excess: Vec<Allocation> = Vec::new();
+    for w in breakpoints(entries, from, until).windows(2) {
+        let mut remaining: Vec<_> = covering(entries, w[0], w[1])
+            .filter(|a| !excess.iter().any(|e| e.allocation_id == a.allocation_id))
+            .collect();
+        while remaining.len() as i32 > capacity {
+            let Some(last) = remaining.pop() else {
+                break;
+            };
+            excess.push(last.clone());
+        }
+    }
+    excess.sort_by_key(|a| a.start_time);
+
+    excess
+}
+
+/// The conflicts of `entries` over each timespan from `from` on where more than `capacity` of
+/// them are concurrent.
+fn over_capacity(entries: &[Allocation], capacity: i32, from: DateTime<Utc>) -> Vec<ConflictInfo> {
+    let until = entries
+        .iter()
+        .filter_map(|a| a.end_time)
+        .max()
+        .unwrap_or(from);
+
+    // Adjacent segments over capacity are reported as a single timespan.
+    let mut spans: Vec<(DateTime<Utc>, DateTime<Utc>)> = Vec::new();
+    for w in breakpoints(entries, from, until).windows(2) {
+        let (start, end) = (w[0], w[1]);
+        if covering(entries, start, end).count() as i32 <= capacity {
+            continue;
+        }
+        match spans.last_mut() {
+            Some((_, last_end)) if *last_end == start => *last_end = end,
+            _ => spans.push((start, end)),
+        }
+    }
+
+    spans
+        .into_iter()
+        .flat_map(|(start, end)| {
+            let within: Vec<_> = covering(entries, start, end).cloned().collect();
+            within
+                .iter()
+                .map(|allocation| {
+                    let mut conflict = ConflictInfo::new(
+                        allocation.clone(),
+                        Blocker::Capacity,
+                        start,
+                        Some(end),
+                        Capabilities::empty(),
+                    );
+                    conflict.peak = Some(capacity_peak(
+                        &within,
+                        conflict.overlap_start,
+                        conflict.overlap_end.unwrap_or(end),
+                    ));
+                    conflict
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// The capacity of `system`, failing unless it has been declared with all of `capabilities`.
+async fn system_capacity(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    capabilities: Capabilities,
+) -> Result<i32, AllocationError> {
+    let info = system_info(tx, system)
+        .await?
+        .ok_or(AllocationError::SystemNotFound(system))?;
+
+    let supported = info.capabilities;
+    if !supported.contains(capabilities) {
+        return Err(AllocationError::UnsupportedCapabilities {
+            requested: capabilities,
+            supported,
+        });
+    }
+
+    Ok(info.capacity)
+}
+
+/// The allocation with the given id, or `None` if it does not exist.
+async fn allocation(
+    executor: impl PgExecutor<'_>,
+    allocation_id: Uuid,
+) -> Result<Option<Allocation>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+    SELECT system_id, allocation_id, kind AS "kind: AllocationKind", planned, start_time,
+        NULLIF(end_time, 'infinity') AS end_time, capabilities
+    FROM allocations
+    WHERE allocation_id = $1
+        "#,
+        allocation_id,
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    Ok(Some(Allocation {
+        system_id: row.system_id,
+        allocation_id: row.allocation_id,
+        kind: row.kind,
+        planned: row.planned,
+        start_time: row.start_time,
+        end_time: row.end_time,
+        capabilities: decode_capabilities(row.capabilities)?,
+    }))
+}
+
+/// How allocations meeting at a boundary are treated on `system`, being the default for a system
+/// that has not been declared, which the checks fail on regardless.
+async fn boundary_policy(
+    executor: impl PgExecutor<'_>,
+    system: Uuid,
+) -> Result<BoundaryPolicy, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+    SELECT boundary_policy AS "boundary_policy: BoundaryKind", boundary_gap
+    FROM systems
+    WHERE system_id = $1
+        "#,
+        system,
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.map_or_else(BoundaryPolicy::default, |row| {
+        BoundaryPolicy::from_columns(row.boundary_policy, row.boundary_gap)
+    }))
+}
+
+/// The configuration of `system`, or `None` if it has not been declared.
+async fn system_info(
+    executor: impl PgExecutor<'_>,
+    system: Uuid,
+) -> Result<Option<SystemInfo>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+    SELECT capacity, capabilities, default_sliding_window, min_notice, max_horizon,
+        boundary_policy AS "boundary_policy: BoundaryKind", boundary_gap, timezone
+    FROM systems
+    WHERE system_id = $1
+        "#,
+        system
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    Ok(Some(SystemInfo {
+        capacity: row.capacity,
+        capabilities: decode_capabilities(row.capabilities)?,
+        default_sliding_window: row.default_sliding_window.map(interval_to_duration),
+        min_notice: row.min_notice.map(interval_to_duration),
+        max_horizon: row.max_horizon.map(interval_to_duration),
+        boundary_policy: BoundaryPolicy::from_columns(row.boundary_policy, row.boundary_gap),
+        timezone: row
+            .timezone
+            .map(|tz| tz.parse::<Tz>())
+            .transpose()
+            .map_err(|e| sqlx::Error::Decode(e.into()))?,
+    }))
+}
+
+/// The allocation ids of unresolved unplanned outages on `system`, whose sliding window overlaps
+/// the timespan `[start, end)`.
+///
+/// See [`SystemAllocation::sweep_unplanned_windows`] for how the window slides along with time.
+async fn unplanned_windows_overlapping(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+    SELECT allocation_id FROM unplanned
+    WHERE system_id = $1
+        AND resolved_at IS NULL
+        AND start_time < $3
+        AND (GREATEST(start_time, now()) + sliding_window) > $2
+        "#,
+        system,
+        start,
+        end,
+    )
+    .fetch_all(tx)
+    .await
+}
+
+/// The error for `allocation_id` not being of the kind an operation applies to, being
+/// [`AllocationError::WrongKind`] if it exists, or [`AllocationError::NotFound`] if it does not.
+async fn not_of_kind(
+    tx: &mut Transaction<'_, Postgres>,
+    allocation_id: Uuid,
+) -> Result<AllocationError, sqlx::Error> {
+    let kind = sqlx::query_scalar!(
+        r#"
+    SELECT kind AS "kind: AllocationKind" FROM allocations WHERE allocation_id = $1
+        "#,
+        allocation_id,
+    )
+    .fetch_optional(tx)
+    .await?;
+
+    Ok(
+        kind.map_or(AllocationError::NotFound(allocation_id), |kind| {
+            AllocationError::WrongKind {
+                allocation_id,
+                kind,
+            }
+        }),
+    )
+}
+
+/// All allocations on `system` overlapping the timespan `[start, end)`, regardless of capabilities.
+///
+/// Under [`BoundaryPolicy::Exclusive`], those touching the timespan are included as well, and
+/// under [`BoundaryPolicy::Gap`] those closer to it than the gap.
+async fn overlapping_allocations(
+    executor: impl PgExecutor<'_>,
+    system: Uuid,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    boundary: BoundaryPolicy,
+) -> Result<Vec<Allocation>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+    SELECT allocation_id, kind AS "kind: AllocationKind", planned, start_time,
+        NULLIF(end_time, 'infinity') AS end_time, capabilities
+    FROM allocations
+    WHERE system_id = $1
+        AND ($2 < end_time + $5 OR ($4 AND $2 = end_time))
+        AND ($3::timestamptz + $5 > start_time OR ($4 AND $3 = start_time))
+        AND NOT hold_expired(allocation_id)
+        AND NOT entry_finished(allocation_id)
+    ORDER BY start_time
+        "#,
+        system,
+        start,
+        end,
+        boundary == BoundaryPolicy::Exclusive,
+        boundary.gap_interval(),
+    )
+    .fetch_all(executor)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(Allocation {
+                system_id: system,
+                allocation_id: row.allocation_id,
+                kind: row.kind,
+                planned: row.planned,
+                start_time: row.start_time,
+                end_time: row.end_time,
+                capabilities: decode_capabilities(row.capabilities)?,
+            })
+        })
+        .collect()
+}
+
+/// All outages on `system` overlapping the timespan `[start, end)`, ordered by start.
+async fn overlapping_outages(
+    executor: impl PgExecutor<'_>,
+    system: Uuid,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<Vec<Outage>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+    SELECT a.allocation_id, a.kind AS "kind: AllocationKind", a.planned, a.start_time,
+        NULLIF(a.end_time, 'infinity') AS end_time, a.capabilities,
+        u.sliding_window AS "sliding_window?"
+    FROM allocations a
+    LEFT JOIN unplanned u USING (allocation_id)
+    WHERE a.system_id = $1
+        AND a.kind != 'entry'
+        AND $2 < a.end_time
+        AND $3 > a.start_time
+    ORDER BY a.start_time
+        "#,
+        system,
+        start,
+        end,
+    )
+    .fetch_all(executor)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            let outage = match (row.kind, row.sliding_window, row.end_time) {
+                (
+                    AllocationKind::Full | AllocationKind::Capability,
+                    Some(sliding_window),
+                    resolved_at,
+                ) => Outage::Unplanned {
+                    allocation_id: row.allocation_id,
+                    start: row.start_time,
+                    sliding_window: interval_to_duration(sliding_window),
+                    resolved_at,
+                    capabilities: decode_capabilities(row.capabilities)?,
+                },
+                (AllocationKind::Full, None, Some(end)) => Outage::Planned {
+                    allocation_id: row.allocation_id,
+                    start: row.start_time,
+                    end,
+                },
+                (AllocationKind::Capability, None, Some(end)) => Outage::Capability {
+                    allocation_id: row.allocation_id,
+                    start: row.start_time,
+                    end,
+                    capabilities: decode_capabilities(row.capabilities)?,
+                },
+                _ => {
+                    return Err(sqlx::Error::Decode(
+                        format!("malformed outage {}", row.allocation_id).into(),
+                    ))
+                }
+            };
+            Ok(outage)
+        })
+        .collect()
+}
+
+/// Fail with every allocation an outage over `[start, end)` for `capabilities` would conflict
+/// with, be it entries that must be cleared first or other outages covering the same capabilities.
+///
+/// An open-ended outage has no `end`, and is only in conflict with the entries ongoing at its
+/// start or starting within its sliding `window`. An outage being moved is `modifying`, and is
+/// not in conflict with itself.
+async fn check_outage_conflicts(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    start: DateTime<Utc>,
+    end: Option<DateTime<Utc>>,
+    window: Option<Duration>,
+    capabilities: Capabilities,
+    modifying: Option<Uuid>,
+) -> Result<(), AllocationError> {
+    lock_system(&mut *tx, system).await?;
+    let conflicts =
+        outage_conflicts_in(tx, system, (start, end), window, capabilities, modifying).await?;
+    outage_conflicts_to_error(conflicts)
+}
+
+/// Every allocation an outage would conflict with, as evaluated by [`check_outage_conflicts`].
+async fn outage_conflicts_in(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    (start, end): (DateTime<Utc>, Option<DateTime<Utc>>),
+    window: Option<Duration>,
+    capabilities: Capabilities,
+    modifying: Option<Uuid>,
+) -> Result<Vec<ConflictInfo>, AllocationError> {
+    let until = end.unwrap_or(DateTime::<Utc>::MAX_UTC);
+    let boundary = boundary_policy(&mut *tx, system).await?;
+    let overlapping = overlapping_allocations(&mut *tx, system, start, until, boundary).await?;
+    let mut conflicts = outage_conflicts(
+        overlapping
+            .into_iter()
+            .filter(|a| Some(a.allocation_id) != modifying),
+        (start, end),
+        window,
+        capabilities,
+    );
+    describe_conflicts(tx, &mut conflicts).await?;
+
+    Ok(conflicts)
+}
+
+/// Pick out the allocations among `overlapping` an outage over `[start, end)` for
+/// `capabilities` is in conflict with, see [`check_outage_conflicts`].
+fn outage_conflicts(
+    overlapping: impl IntoIterator<Item = Allocation>,
+    (start, end): (DateTime<Utc>, Option<DateTime<Utc>>),
+    window: Option<Duration>,
+    capabilities: Capabilities,
+) -> Vec<ConflictInfo> {
+    let until = end.unwrap_or(DateTime::<Utc>::MAX_UTC);
+    let entries_until = match window {
+        Some(window) => start.checked_add_signed(window).unwrap_or(until),
+        None => until,
+    };
+    overlapping
+        .into_iter()
+        .filter(|a| a.capabilities.intersects(capabilities))
+        .filter(|a| {
+            a.kind != AllocationKind::Entry
+                || a.start_time < start
+                || window.is_none_or(|w| in_sliding_window(start, w, a.start_time))
+        })
+        .map(|a| {
+            let blocker = Blocker::of(&a);
+            let end = match blocker {
+                Blocker::Entry => Some(entries_until),
+                _ => end,
+            };
+            ConflictInfo::new(a, blocker, start, end, capabilities)
+        })
+        .collect()
+}
+
+/// Fail with [`AllocationError::Conflict`] unless an outage is in conflict with nothing.
+fn outage_conflicts_to_error(conflicts: Vec<ConflictInfo>) -> Result<(), AllocationError> {
+    if !conflicts.is_empty() {
+        let entries = conflicts
+            .iter()
+            .filter(|c| c.blocker == Blocker::Entry)
+            .count();
+        tracing::debug!(
+            entries,
+            outages = conflicts.len() - entries,
+            "rejected: {} overlapping allocation(s)",
+            conflicts.len(),
+        );
+        return Err(AllocationError::Conflict { conflicts });
+    }
+
+    Ok(())
+}
+
+/// A row to be mirrored into the allocations table, where all overlap checks are enforced.
+///
+/// This is inserted within the same transaction as the row in the kind-specific table, such that
+/// a rejected allocation leaves nothing behind.
+struct NewAllocation {
+    system: Uuid,
+    allocation_id: Uuid,
+    kind: AllocationKind,
+    planned: bool,
+    start: DateTime<Utc>,
+    /// `None` leaves the allocation open-ended.
+    end: Option<DateTime<Utc>>,
+    capabilities: Capabilities,
+}
+
+impl NewAllocation {
+    async fn insert(&self, tx: &mut Transaction<'_, Postgres>) -> Result<(), AllocationError> {
+        sqlx::query!(
+            r#"
+        INSERT INTO allocations(system_id, allocation_id, kind, planned, start_time, end_time, capabilities)
+        VALUES ($1, $2, $3, $4, $5, COALESCE($6, 'infinity'::timestamptz), $7)
+            "#,
+            self.system,
+            self.allocation_id,
+            self.kind as _,
+            self.planned,
+            self.start,
+            self.end,
+            self.capabilities.bits() as i64,
+        )
+        .execute(tx)
+        .await
+        .map_err(|e| {
+            AllocationError::from_insert(e, self.system, self.end.map(|end| (self.start, end)))
+        })?;
 
         Ok(())
     }
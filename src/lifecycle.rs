@@ -0,0 +1,244 @@
+//! The lifecycle of an entry, from scheduled until it either completes or is cancelled.
+
+use chrono::{DateTime, Utc};
+use sqlx::postgres::Postgres;
+use sqlx::Transaction;
+use uuid::Uuid;
+
+use crate::{check_range, not_of_kind, promote_waitlist, AllocationError, SystemAllocation};
+
+/// Where an entry is in its lifecycle.
+///
+/// Completed and cancelled entries are finished, and no longer occupy their timespan. They are
+/// skipped by every check over the allocations, while still listed along with their status.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, sqlx::Type)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[sqlx(type_name = "entry_status", rename_all = "lowercase")]
+pub enum EntryStatus {
+    /// Inserted, and yet to start.
+    #[default]
+    Scheduled,
+    /// Started, see [`SystemAllocation::start_entry`].
+    Active,
+    /// Ended, see [`SystemAllocation::complete_entry`].
+    Completed,
+    /// Called off, see [`SystemAllocation::cancel_entry`].
+    Cancelled,
+}
+
+impl EntryStatus {
+    /// Whether the entry has completed or been cancelled, after which its status never changes.
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Cancelled)
+    }
+}
+
+impl SystemAllocation {
+    /// Mark a scheduled entry as active, once it has started.
+    ///
+    /// Fails with [`AllocationError::Validation`] unless the entry is scheduled, and with
+    /// [`AllocationError::NotFound`] or [`AllocationError::WrongKind`] as with
+    /// [`SystemAllocation::remove_entry`].
+    #[tracing::instrument(skip(self))]
+    pub async fn start_entry(&self, allocation_id: Uuid) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.start_entry_tx(&mut tx, allocation_id).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::start_entry`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn start_entry_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        allocation_id: Uuid,
+    ) -> Result<(), AllocationError> {
+        let current = entry_lifecycle(tx, allocation_id).await?;
+        if current.status != EntryStatus::Scheduled {
+            return Err(AllocationError::Validation(format!(
+                "entry {allocation_id} is {:?}, and can not be started",
+                current.status
+            )));
+        }
+
+        set_status(tx, allocation_id, EntryStatus::Active, None).await
+    }
+
+    /// Mark an entry as completed, having actually ended at `actual_end`, or now if `None`.
+    ///
+    /// An entry completing early is shrunk to end at `actual_end`, such that the rest of its
+    /// timespan returns to the system. Being finished, the entry no longer occupies its timespan
+    /// at all, see [`EntryStatus`], and requests on the waitlist for it are promoted, see
+    /// [`SystemAllocation::join_waitlist`].
+    ///
+    /// Fails with [`AllocationError::Validation`] if the entry is already finished, or
+    /// `actual_end` is after it ends, and with [`AllocationError::InvalidRange`] unless
+    /// `actual_end` is after it starts. Fails with [`AllocationError::NotFound`] or
+    /// [`AllocationError::WrongKind`] as with [`SystemAllocation::remove_entry`].
+    #[tracing::instrument(skip(self))]
+    pub async fn complete_entry(
+        &self,
+        allocation_id: Uuid,
+        actual_end: Option<DateTime<Utc>>,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.complete_entry_tx(&mut tx, allocation_id, actual_end)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::complete_entry`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn complete_entry_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        allocation_id: Uuid,
+        actual_end: Option<DateTime<Utc>>,
+    ) -> Result<(), AllocationError> {
+        let current = entry_lifecycle(tx, allocation_id).await?;
+        if current.status.is_finished() {
+            return Err(AllocationError::Validation(format!(
+                "entry {allocation_id} is {:?}, and can not be completed",
+                current.status
+            )));
+        }
+        let end = actual_end.unwrap_or_else(Utc::now);
+        check_range(current.start, end)?;
+        if current.end.is_some_and(|current| end > current) {
+            return Err(AllocationError::Validation(format!(
+                "entry {allocation_id} can not complete at {end}, after it ends"
+            )));
+        }
+
+        if current.end != Some(end) {
+            sqlx::query!(
+                r#"
+            UPDATE entries SET end_time = $2 WHERE allocation_id = $1
+                "#,
+                allocation_id,
+                end,
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query!(
+                r#"
+            UPDATE allocations SET end_time = $2 WHERE allocation_id = $1
+                "#,
+                allocation_id,
+                end,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        set_status(tx, allocation_id, EntryStatus::Completed, None).await?;
+        promote_waitlist(tx, current.system, current.start, current.end).await
+    }
+
+    /// Mark an entry as cancelled for `reason`, leaving it in place to be listed.
+    ///
+    /// Being finished, the entry no longer occupies its timespan, see [`EntryStatus`], and
+    /// requests on the waitlist for it are promoted, see [`SystemAllocation::join_waitlist`]. See
+    /// [`SystemAllocation::remove_entry`] to remove it entirely instead.
+    ///
+    /// Fails with [`AllocationError::Validation`] if the entry is already finished, and with
+    /// [`AllocationError::NotFound`] or [`AllocationError::WrongKind`] as with
+    /// [`SystemAllocation::remove_entry`].
+    #[tracing::instrument(skip(self))]
+    pub async fn cancel_entry(
+        &self,
+        allocation_id: Uuid,
+        reason: &str,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.cancel_entry_tx(&mut tx, allocation_id, reason).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::cancel_entry`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn cancel_entry_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        allocation_id: Uuid,
+        reason: &str,
+    ) -> Result<(), AllocationError> {
+        let current = entry_lifecycle(tx, allocation_id).await?;
+        if current.status.is_finished() {
+            return Err(AllocationError::Validation(format!(
+                "entry {allocation_id} is {:?}, and can not be cancelled",
+                current.status
+            )));
+        }
+
+        set_status(tx, allocation_id, EntryStatus::Cancelled, Some(reason)).await?;
+        promote_waitlist(tx, current.system, current.start, current.end).await
+    }
+}
+
+/// The status and timespan of an entry, as of the start of a transition.
+struct Lifecycle {
+    system: Uuid,
+    status: EntryStatus,
+    start: DateTime<Utc>,
+    /// `None` for an open-ended entry.
+    end: Option<DateTime<Utc>>,
+}
+
+/// The current status of the entry `allocation_id`, locking it until the end of `tx`.
+async fn entry_lifecycle(
+    tx: &mut Transaction<'_, Postgres>,
+    allocation_id: Uuid,
+) -> Result<Lifecycle, AllocationError> {
+    let row = sqlx::query!(
+        r#"
+    SELECT a.system_id, e.status AS "status: EntryStatus", a.start_time,
+        NULLIF(a.end_time, 'infinity') AS end_time
+    FROM entries e
+    JOIN allocations a USING (allocation_id)
+    WHERE a.allocation_id = $1 AND a.kind = 'entry'
+    FOR UPDATE
+        "#,
+        allocation_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(row) = row else {
+        return Err(not_of_kind(tx, allocation_id).await?);
+    };
+
+    Ok(Lifecycle {
+        system: row.system_id,
+        status: row.status,
+        start: row.start_time,
+        end: row.end_time,
+    })
+}
+
+/// Set the status of the entry `allocation_id`, along with why it was cancelled, if it was.
+async fn set_status(
+    tx: &mut Transaction<'_, Postgres>,
+    allocation_id: Uuid,
+    status: EntryStatus,
+    cancel_reason: Option<&str>,
+) -> Result<(), AllocationError> {
+    sqlx::query!(
+        r#"
+    UPDATE entries SET status = $2, cancel_reason = $3 WHERE allocation_id = $1
+        "#,
+        allocation_id,
+        status as _,
+        cancel_reason,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
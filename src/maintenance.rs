@@ -0,0 +1,384 @@
+//! Planned outages recurring at a local time of day, such as standing maintenance windows, kept
+//! inserted up to a rolling horizon ahead of now.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use sqlx::postgres::{types::PgInterval, Postgres};
+use sqlx::{Acquire, Transaction};
+use uuid::Uuid;
+
+use crate::interval::interval_to_duration;
+use crate::recurrence::MAX_OCCURRENCES;
+use crate::{AllocationError, ConflictInfo, SystemAllocation};
+
+/// The weekdays, indexed by their number of days from monday as stored.
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// When the occurrences of a recurring planned outage start, at a local time of day.
+///
+/// Occurrences start at the same local time across daylight saving transitions, and thus not
+/// always the same time of day in UTC. A local start skipped by the clocks moving forward is
+/// shifted forward by an hour, and one repeated by the clocks moving back is the earliest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MaintenanceSchedule {
+    /// The time of day each occurrence starts at, in `timezone`.
+    pub local_start: NaiveTime,
+    pub timezone: Tz,
+    /// Only the days on these weekdays, in `timezone`, occur, or every day if empty.
+    pub weekdays: Vec<Weekday>,
+}
+
+/// The occurrences of a recurring planned outage inserted at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecurringOutage {
+    /// The recurrence the occurrences belong to.
+    pub recurrence_id: Uuid,
+    /// The allocation ids of the inserted occurrences, in order.
+    pub allocation_ids: Vec<Uuid>,
+    /// The occurrences which were not inserted, for being in conflict.
+    pub conflicts: Vec<OccurrenceConflict>,
+}
+
+/// An occurrence of a recurring planned outage over `[start, end)`, left out for being in
+/// conflict with the listed allocations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OccurrenceConflict {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub conflicts: Vec<ConflictInfo>,
+}
+
+impl MaintenanceSchedule {
+    /// Every day at `local_start` in `timezone`.
+    pub fn daily(local_start: NaiveTime, timezone: Tz) -> Self {
+        Self {
+            local_start,
+            timezone,
+            weekdays: Vec::new(),
+        }
+    }
+
+    /// Only occur on the given weekdays, e.g.
+    /// `MaintenanceSchedule::daily(two_am, Tz::Europe__Oslo).on(&[Weekday::Sun])`.
+    pub fn on(mut self, weekdays: &[Weekday]) -> Self {
+        self.weekdays = weekdays.to_vec();
+        self
+    }
+
+    /// The start of the occurrence on the local `date`, were it to occur on it.
+    fn start_on(&self, date: NaiveDate) -> Option<DateTime<Utc>> {
+        let local = date.and_time(self.local_start);
+        self.timezone
+            .from_local_datetime(&local)
+            .earliest()
+            .or_else(|| {
+                let shifted = local.checked_add_signed(Duration::hours(1))?;
+                self.timezone.from_local_datetime(&shifted).earliest()
+            })
+            .map(|start| start.with_timezone(&Utc))
+    }
+
+    /// The starts of the occurrences after `after`, until and including `until`.
+    fn occurrences(
+        &self,
+        after: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<DateTime<Utc>>, AllocationError> {
+        let mut occurrences = Vec::new();
+        let mut date = Some(after.with_timezone(&self.timezone).date_naive());
+        while let Some(day) = date {
+            let Some(start) = self.start_on(day) else {
+                break;
+            };
+            if start > until {
+                break;
+            }
+            if start > after && (self.weekdays.is_empty() || self.weekdays.contains(&day.weekday()))
+            {
+                if occurrences.len() == MAX_OCCURRENCES {
+                    return Err(AllocationError::Validation(format!(
+                        "maintenance schedule has more than {MAX_OCCURRENCES} occurrences until \
+                         {until}"
+                    )));
+                }
+                occurrences.push(start);
+            }
+            date = day.succ_opt();
+        }
+
+        Ok(occurrences)
+    }
+}
+
+impl SystemAllocation {
+    /// Insert a planned outage of the entire system for `duration`, recurring according to
+    /// `schedule`, with every occurrence within `horizon` from now inserted right away.
+    ///
+    /// Later occurrences are inserted as time passes by [`SystemAllocation::extend_recurrences`],
+    /// keeping them inserted up to the horizon. Each occurrence is evaluated as with
+    /// [`SystemAllocation::insert_planned_outage`], and those in conflict are left out and
+    /// reported along with their conflicts, rather than failing the rest.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_recurring_planned_outage(
+        &self,
+        system: Uuid,
+        schedule: &MaintenanceSchedule,
+        duration: Duration,
+        horizon: Duration,
+    ) -> Result<RecurringOutage, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let series = self
+            .insert_recurring_planned_outage_tx(&mut tx, system, schedule, duration, horizon)
+            .await?;
+        tx.commit().await?;
+
+        Ok(series)
+    }
+
+    /// As [`SystemAllocation::insert_recurring_planned_outage`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_recurring_planned_outage_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        schedule: &MaintenanceSchedule,
+        duration: Duration,
+        horizon: Duration,
+    ) -> Result<RecurringOutage, AllocationError> {
+        let now = Utc::now();
+        let invalid = |what: &str, duration: Duration| {
+            AllocationError::Validation(format!("invalid {what} {duration}"))
+        };
+        let duration_interval = PgInterval::try_from(duration)
+            .ok()
+            .filter(|_| duration > Duration::zero())
+            .ok_or_else(|| invalid("duration", duration))?;
+        let horizon_interval = PgInterval::try_from(horizon)
+            .ok()
+            .filter(|_| horizon > Duration::zero())
+            .ok_or_else(|| invalid("horizon", horizon))?;
+        let until = now
+            .checked_add_signed(horizon)
+            .ok_or_else(|| invalid("horizon", horizon))?;
+        // Fail on too many occurrences before recording the recurrence.
+        schedule.occurrences(now, until)?;
+
+        let recurrence_id = Uuid::new_v4();
+        let weekdays: Vec<_> = schedule
+            .weekdays
+            .iter()
+            .map(|day| day.num_days_from_monday() as i16)
+            .collect();
+        sqlx::query!(
+            r#"
+        INSERT INTO outage_recurrences
+            (recurrence_id, system_id, weekdays, local_start, timezone, duration, horizon,
+             materialized_until)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+            recurrence_id,
+            system,
+            &weekdays,
+            schedule.local_start,
+            schedule.timezone.name(),
+            duration_interval,
+            horizon_interval,
+            now,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| AllocationError::from_insert(e, system, None))?;
+
+        self.materialize(tx, recurrence_id, system, schedule, duration, now, until)
+            .await
+    }
+
+    /// Insert the occurrences of every recurring planned outage which have come within its
+    /// horizon since last extended, see [`SystemAllocation::insert_recurring_planned_outage`].
+    ///
+    /// This is intended to run periodically, e.g. daily, and is a no-op when there is nothing to
+    /// insert. Returns the recurrences with any occurrences inserted or left out for being in
+    /// conflict.
+    #[tracing::instrument(skip(self))]
+    pub async fn extend_recurrences(&self) -> Result<Vec<RecurringOutage>, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let extended = self.extend_recurrences_tx(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(extended)
+    }
+
+    /// As [`SystemAllocation::extend_recurrences`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn extend_recurrences_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<Vec<RecurringOutage>, AllocationError> {
+        let now = Utc::now();
+        let rows = sqlx::query!(
+            r#"
+        SELECT recurrence_id, system_id, weekdays, local_start, timezone, duration, horizon,
+            materialized_until
+        FROM outage_recurrences
+        WHERE materialized_until < $1::timestamptz + horizon
+        ORDER BY recurrence_id
+        FOR UPDATE
+            "#,
+            now,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut extended = Vec::new();
+        for row in rows {
+            let timezone = row
+                .timezone
+                .parse::<Tz>()
+                .map_err(|e| sqlx::Error::Decode(e.into()))?;
+            let weekdays = row
+                .weekdays
+                .iter()
+                .map(|&day| {
+                    usize::try_from(day)
+                        .ok()
+                        .and_then(|day| WEEKDAYS.get(day).copied())
+                        .ok_or_else(|| sqlx::Error::Decode(format!("invalid weekday {day}").into()))
+                })
+                .collect::<Result<_, _>>()?;
+            let schedule = MaintenanceSchedule {
+                local_start: row.local_start,
+                timezone,
+                weekdays,
+            };
+            let horizon = interval_to_duration(row.horizon);
+            let until = now
+                .checked_add_signed(horizon)
+                .unwrap_or(DateTime::<Utc>::MAX_UTC);
+            let series = self
+                .materialize(
+                    &mut *tx,
+                    row.recurrence_id,
+                    row.system_id,
+                    &schedule,
+                    interval_to_duration(row.duration),
+                    row.materialized_until,
+                    until,
+                )
+                .await?;
+            if !series.allocation_ids.is_empty() || !series.conflicts.is_empty() {
+                extended.push(series);
+            }
+        }
+
+        Ok(extended)
+    }
+
+    /// Insert the occurrences of the recurrence starting after `after`, until and including
+    /// `until`, and record it as inserted until then.
+    #[allow(clippy::too_many_arguments)]
+    async fn materialize(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        recurrence_id: Uuid,
+        system: Uuid,
+        schedule: &MaintenanceSchedule,
+        duration: Duration,
+        after: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<RecurringOutage, AllocationError> {
+        let mut allocation_ids = Vec::new();
+        let mut conflicts = Vec::new();
+        for start in schedule.occurrences(after, until)? {
+            let end = start.checked_add_signed(duration).ok_or_else(|| {
+                AllocationError::Validation(format!("occurrence at {start} is out of range"))
+            })?;
+            // Each occurrence is inserted within a savepoint, as a rejected insert may abort the
+            // transaction.
+            let mut attempt = tx.begin().await?;
+            match self
+                .insert_planned_outage_tx(&mut attempt, system, start, end)
+                .await
+            {
+                Ok(allocation_id) => {
+                    attempt.commit().await?;
+                    allocation_ids.push(allocation_id);
+                }
+                Err(AllocationError::Conflict {
+                    conflicts: in_conflict,
+                }) => {
+                    tracing::debug!(%start, conflicts = in_conflict.len(), "occurrence in conflict");
+                    attempt.rollback().await?;
+                    conflicts.push(OccurrenceConflict {
+                        start,
+                        end,
+                        conflicts: in_conflict,
+                    });
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        sqlx::query!(
+            r#"
+        UPDATE planned SET recurrence_id = $1 WHERE allocation_id = ANY($2)
+            "#,
+            recurrence_id,
+            &allocation_ids,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+        UPDATE outage_recurrences SET materialized_until = $2 WHERE recurrence_id = $1
+            "#,
+            recurrence_id,
+            until,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(RecurringOutage {
+            recurrence_id,
+            allocation_ids,
+            conflicts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_start_across_daylight_saving() {
+        let two_am = NaiveTime::from_hms_opt(2, 0, 0).unwrap();
+        let schedule = MaintenanceSchedule::daily(two_am, Tz::Europe__Oslo).on(&[Weekday::Sun]);
+        let utc = |day, hour| Utc.with_ymd_and_hms(2023, 3, day, hour, 0, 0).unwrap();
+
+        // The clocks move forward at 02:00 on the last Sunday of March
+        let starts = schedule.occurrences(utc(18, 0), utc(31, 0)).unwrap();
+        assert_eq!(starts, [utc(19, 1), utc(26, 1)]);
+        assert_eq!(
+            starts[1].with_timezone(&Tz::Europe__Oslo).time(),
+            NaiveTime::from_hms_opt(3, 0, 0).unwrap()
+        );
+
+        // From then on, 02:00 local is an hour earlier in UTC
+        let starts = schedule.occurrences(utc(26, 1), utc(31, 0)).unwrap();
+        assert!(starts.is_empty());
+        let april = Utc.with_ymd_and_hms(2023, 4, 2, 0, 0, 0).unwrap();
+        let starts = schedule.occurrences(utc(27, 0), april).unwrap();
+        assert_eq!(starts, [april]);
+    }
+}
@@ -0,0 +1,173 @@
+//! Inserting entries by displacing entries of a lower priority, rather than failing.
+
+use chrono::{DateTime, Utc};
+use sqlx::postgres::Postgres;
+use sqlx::Transaction;
+use uuid::Uuid;
+
+use crate::{
+    booking_policy, boundary_policy, check_range, classify_conflicts, delete_entry_rows,
+    entry_conflicts, entry_conflicts_to_error, insert_entry, lock_system, overlapping_allocations,
+    AllocationError, AllocationKind, Blocker, Capabilities, CapacityLimits, EntryParams,
+    SystemAllocation,
+};
+
+impl SystemAllocation {
+    /// Insert a single entry of `priority` as with [`SystemAllocation::insert_entry`], removing
+    /// entries of a strictly lower priority in the way should the capacity be exceeded.
+    ///
+    /// Entries are removed lowest priority first, and the most recently inserted first among
+    /// those of the same priority, until the entry fits. Only as many as needed are removed, and
+    /// they are returned along with the allocation id of the entry, such that their owners may
+    /// be notified. Entries inserted otherwise have a priority of 0 unless given, see
+    /// [`EntryParams::priority`], and neither holds nor pinned entries are ever removed. Should
+    /// removing all of those of a lower priority not make room, or the entry be in the way of an
+    /// outage, this fails as inserting would, and nothing is removed.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_entry_preempting(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+        priority: i32,
+    ) -> Result<(Uuid, Vec<Uuid>), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let inserted = self
+            .insert_entry_preempting_tx(&mut tx, system, start, end, capabilities, priority)
+            .await?;
+        tx.commit().await?;
+
+        Ok(inserted)
+    }
+
+    /// As [`SystemAllocation::insert_entry_preempting`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_entry_preempting_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+        priority: i32,
+    ) -> Result<(Uuid, Vec<Uuid>), AllocationError> {
+        check_range(start, end)?;
+        booking_policy(&mut *tx, system)
+            .await?
+            .check(self.now(), start)?;
+
+        lock_system(&mut *tx, system).await?;
+        let conflicts =
+            entry_conflicts(&mut *tx, system, start, Some(end), capabilities, None).await?;
+        // Room may only be made within the capacity, an outage in the way is never removed.
+        let mut evicted = Vec::new();
+        if !conflicts.is_empty() && conflicts.iter().all(|c| c.blocker == Blocker::Capacity) {
+            evicted = preempted(&mut *tx, system, start, end, capabilities, priority).await?;
+        }
+        if evicted.is_empty() {
+            entry_conflicts_to_error(
+                &mut *tx,
+                system,
+                conflicts,
+                (start, Some(end)),
+                capabilities,
+            )
+            .await?;
+        }
+
+        sqlx::query!(
+            r#"
+        DELETE FROM allocations WHERE allocation_id = ANY($1)
+            "#,
+            &evicted,
+        )
+        .execute(&mut *tx)
+        .await?;
+        delete_entry_rows(&mut *tx, &evicted).await?;
+
+        let allocation_id = Uuid::new_v4();
+        insert_entry(
+            &mut *tx,
+            system,
+            allocation_id,
+            start,
+            Some(end),
+            capabilities,
+            &EntryParams::new().priority(priority),
+        )
+        .await?;
+
+        Ok((allocation_id, evicted))
+    }
+}
+
+/// The fewest entries of a lower priority than `priority` to remove for an entry over
+/// `[start, end)` for `capabilities` to fit within the capacity, see
+/// [`SystemAllocation::insert_entry_preempting`]. Empty if removing them all would not do.
+async fn preempted(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    capabilities: Capabilities,
+    priority: i32,
+) -> Result<Vec<Uuid>, AllocationError> {
+    let boundary = boundary_policy(&mut *tx, system).await?;
+    let overlapping = overlapping_allocations(&mut *tx, system, start, end, boundary).await?;
+    let limits = CapacityLimits::load(&mut *tx, system, capabilities).await?;
+    let entries: Vec<_> = overlapping
+        .iter()
+        .filter(|a| a.kind == AllocationKind::Entry)
+        .map(|a| a.allocation_id)
+        .collect();
+    let candidates = sqlx::query_scalar!(
+        r#"
+    SELECT e.allocation_id
+    FROM entries e
+    JOIN allocations a USING (allocation_id)
+    WHERE e.allocation_id = ANY($1) AND e.priority < $2 AND NOT e.pinned
+    ORDER BY e.priority, a.created_at DESC
+        "#,
+        &entries,
+        priority,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let fits = |evicted: &[Uuid]| {
+        let remaining = overlapping
+            .iter()
+            .filter(|a| !evicted.contains(&a.allocation_id))
+            .cloned();
+        classify_conflicts(
+            remaining,
+            &limits,
+            (start, Some(end)),
+            capabilities,
+            None,
+            &[],
+        )
+        .is_empty()
+    };
+    let mut evicted = Vec::new();
+    for candidate in candidates {
+        if fits(&evicted) {
+            break;
+        }
+        evicted.push(candidate);
+    }
+    if !fits(&evicted) {
+        return Ok(Vec::new());
+    }
+
+    // Spare those which turned out not to be needed, last removed first.
+    for index in (0..evicted.len()).rev() {
+        let spared = evicted.remove(index);
+        if !fits(&evicted) {
+            evicted.insert(index, spared);
+        }
+    }
+
+    Ok(evicted)
+}
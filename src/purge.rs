@@ -0,0 +1,101 @@
+//! Reclaiming the rows of allocations long past.
+
+use chrono::{DateTime, Utc};
+use sqlx::postgres::Postgres;
+use sqlx::Transaction;
+
+use crate::{delete_entry_rows, AllocationError, SystemAllocation};
+
+impl SystemAllocation {
+    /// Remove every entry and outage on any system that ended before `cutoff`, along with the
+    /// rows kept of them in every other table, returning how many were removed.
+    ///
+    /// Open-ended entries and unresolved unplanned outages have no end, and are never removed.
+    /// Neither are recorded evictions, see [`SystemAllocation::list_evictions`]. Resolved
+    /// capacity reductions and requests on the waitlist that ended before `cutoff` are removed
+    /// and counted alike. As with any other removal, subscribers are notified of each removed
+    /// allocation.
+    #[tracing::instrument(skip(self))]
+    pub async fn purge_before(&self, cutoff: DateTime<Utc>) -> Result<u64, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let purged = self.purge_before_tx(&mut tx, cutoff).await?;
+        tx.commit().await?;
+
+        Ok(purged)
+    }
+
+    /// As [`SystemAllocation::purge_before`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn purge_before_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, AllocationError> {
+        // The end of an open-ended allocation is stored as 'infinity', which is never before it.
+        let purged = sqlx::query_scalar!(
+            r#"
+        DELETE FROM allocations WHERE end_time < $1
+        RETURNING allocation_id
+            "#,
+            cutoff,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        delete_entry_rows(&mut *tx, &purged).await?;
+        sqlx::query!(
+            r#"
+        DELETE FROM planned WHERE allocation_id = ANY($1)
+            "#,
+            &purged,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+        DELETE FROM unplanned WHERE allocation_id = ANY($1)
+            "#,
+            &purged,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+        DELETE FROM holds WHERE allocation_id = ANY($1)
+            "#,
+            &purged,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+        DELETE FROM idempotency_keys WHERE allocation_id = ANY($1)
+            "#,
+            &purged,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let reductions = sqlx::query!(
+            r#"
+        DELETE FROM capacity_reductions WHERE resolved_at < $1
+            "#,
+            cutoff,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        let waitlist = sqlx::query!(
+            r#"
+        DELETE FROM waitlist WHERE end_time < $1
+            "#,
+            cutoff,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        tracing::debug!(allocations = purged.len(), reductions, waitlist, "purged");
+
+        Ok(purged.len() as u64 + reductions + waitlist)
+    }
+}
@@ -0,0 +1,232 @@
+//! Recurring entries following a schedule of days, rather than a fixed number of occurrences.
+
+use chrono::{DateTime, Datelike, Duration, Utc, Weekday};
+use sqlx::postgres::Postgres;
+use sqlx::{Acquire, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    decode_capabilities, AllocationError, Capabilities, Entry, EntryParams, EntryStatus,
+    RecurringEntry, SystemAllocation,
+};
+
+/// The most occurrences a single schedule may be materialized into at once.
+pub(crate) const MAX_OCCURRENCES: usize = 1000;
+
+/// The days on which a recurring entry occurs, at the time of day of its first occurrence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Recurrence {
+    /// The number of days from one candidate occurrence to the next.
+    pub every_days: u32,
+    /// Only the candidates on these weekdays occur, in UTC, or all of them if empty.
+    pub weekdays: Vec<Weekday>,
+}
+
+impl Recurrence {
+    /// Every day.
+    pub fn daily() -> Self {
+        Self::every_n_days(1)
+    }
+
+    /// Every week, on the weekday of the first occurrence.
+    pub fn weekly() -> Self {
+        Self::every_n_days(7)
+    }
+
+    /// Every `n` days.
+    pub fn every_n_days(n: u32) -> Self {
+        Self {
+            every_days: n,
+            weekdays: Vec::new(),
+        }
+    }
+
+    /// Only occur on the given weekdays, e.g. `Recurrence::daily().on(&[Weekday::Mon])`.
+    pub fn on(mut self, weekdays: &[Weekday]) -> Self {
+        self.weekdays = weekdays.to_vec();
+        self
+    }
+
+    /// The starts of the occurrences from `first_start` until, and including, `until`.
+    fn occurrences(
+        &self,
+        first_start: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<DateTime<Utc>>, AllocationError> {
+        if self.every_days == 0 {
+            return Err(AllocationError::Validation(
+                "recurrence must be at least every day".to_string(),
+            ));
+        }
+
+        let period = Duration::days(self.every_days.into());
+        let mut occurrences = Vec::new();
+        let candidates =
+            std::iter::successors(Some(first_start), |start| start.checked_add_signed(period));
+        for start in candidates.take_while(|start| *start <= until) {
+            if !self.weekdays.is_empty() && !self.weekdays.contains(&start.weekday()) {
+                continue;
+            }
+            if occurrences.len() == MAX_OCCURRENCES {
+                return Err(AllocationError::Validation(format!(
+                    "recurrence has more than {MAX_OCCURRENCES} occurrences until {until}"
+                )));
+            }
+            occurrences.push(start);
+        }
+
+        Ok(occurrences)
+    }
+}
+
+impl SystemAllocation {
+    /// Insert an entry of `duration` recurring according to `recurrence`, from `first_start`
+    /// until, and including, `until`.
+    ///
+    /// Each occurrence is evaluated as with [`SystemAllocation::insert_entry`], and they share a
+    /// recurrence group as with [`SystemAllocation::insert_recurring_entry`]. If any occurrences
+    /// are in conflict, this fails with [`AllocationError::RecurrenceConflict`] listing all of
+    /// them, unless `skip_conflicts` is set, in which case the rest are inserted and the skipped
+    /// starts are returned instead.
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_recurring_schedule(
+        &self,
+        system: Uuid,
+        first_start: DateTime<Utc>,
+        duration: Duration,
+        capabilities: Capabilities,
+        recurrence: &Recurrence,
+        until: DateTime<Utc>,
+        skip_conflicts: bool,
+    ) -> Result<RecurringEntry, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let series = self
+            .insert_recurring_schedule_tx(
+                &mut tx,
+                system,
+                first_start,
+                duration,
+                capabilities,
+                recurrence,
+                until,
+                skip_conflicts,
+            )
+            .await?;
+        tx.commit().await?;
+
+        Ok(series)
+    }
+
+    /// As [`SystemAllocation::insert_recurring_schedule`], within the transaction `tx`.
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_recurring_schedule_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        first_start: DateTime<Utc>,
+        duration: Duration,
+        capabilities: Capabilities,
+        recurrence: &Recurrence,
+        until: DateTime<Utc>,
+        skip_conflicts: bool,
+    ) -> Result<RecurringEntry, AllocationError> {
+        let occurrences = recurrence.occurrences(first_start, until)?;
+        if occurrences.is_empty() {
+            return Err(AllocationError::Validation(format!(
+                "recurrence has no occurrences until {until}"
+            )));
+        }
+
+        let mut allocation_ids = Vec::with_capacity(occurrences.len());
+        let mut skipped = Vec::new();
+        for start in occurrences {
+            let end = start.checked_add_signed(duration).ok_or_else(|| {
+                AllocationError::Validation(format!("occurrence at {start} is out of range"))
+            })?;
+            // Each occurrence is inserted within a savepoint, as a rejected insert may abort the
+            // transaction.
+            let mut attempt = tx.begin().await?;
+            match self
+                .insert_entry_tx(&mut attempt, system, start, end, capabilities)
+                .await
+            {
+                Ok(allocation_id) => {
+                    attempt.commit().await?;
+                    allocation_ids.push(allocation_id);
+                }
+                Err(err) if err.is_conflict() => {
+                    tracing::debug!(%start, %err, "occurrence in conflict");
+                    attempt.rollback().await?;
+                    skipped.push(start);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        if !skipped.is_empty() && !skip_conflicts {
+            return Err(AllocationError::RecurrenceConflict { starts: skipped });
+        }
+
+        let group_id = Uuid::new_v4();
+        sqlx::query!(
+            r#"
+        UPDATE entries SET recurrence_group = $1 WHERE allocation_id = ANY($2)
+            "#,
+            group_id,
+            &allocation_ids,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(RecurringEntry {
+            group_id,
+            allocation_ids,
+            skipped,
+        })
+    }
+
+    /// List the occurrences of a recurring entry which have not been removed, ordered by start.
+    #[tracing::instrument(skip(self))]
+    pub async fn list_recurring_group(
+        &self,
+        group_id: Uuid,
+    ) -> Result<Vec<Entry>, AllocationError> {
+        let rows = sqlx::query!(
+            r#"
+        SELECT a.allocation_id, a.start_time, NULLIF(a.end_time, 'infinity') AS end_time,
+            a.capabilities, e.owner, e.label, e.metadata, e.pinned, e.priority,
+            e.status AS "status: EntryStatus", e.cancel_reason
+        FROM entries e
+        JOIN allocations a USING (allocation_id)
+        WHERE e.recurrence_group = $1
+        ORDER BY a.start_time
+            "#,
+            group_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                Ok(Entry {
+                    allocation_id: row.allocation_id,
+                    start_time: row.start_time,
+                    end_time: row.end_time,
+                    capabilities: decode_capabilities(row.capabilities)?,
+                    params: EntryParams {
+                        owner: row.owner,
+                        label: row.label,
+                        metadata: row.metadata,
+                        pinned: row.pinned,
+                        priority: row.priority,
+                        status: row.status,
+                        cancel_reason: row.cancel_reason,
+                    },
+                })
+            })
+            .collect::<Result<_, sqlx::Error>>()?)
+    }
+}
@@ -0,0 +1,347 @@
+//! Unplanned reductions of the concurrent capacity of a system, rather than blocking it entirely.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::postgres::{PgExecutor, Postgres};
+use sqlx::Transaction;
+use uuid::Uuid;
+
+use crate::sweep::evict_entries;
+use crate::{
+    breakpoints, covering, entry_params, lock_system, remaining_entries,
+    sliding_window_to_interval, system_capacity, AllocationError, Capabilities, Entry, SweepReport,
+    SystemAllocation,
+};
+
+/// A reduction of the system capacity, in effect from its start until resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CapacityReduction {
+    pub(crate) start: DateTime<Utc>,
+    /// `None` until resolved.
+    pub(crate) end: Option<DateTime<Utc>>,
+    /// The end of the sliding window within which entries over the reduced capacity are
+    /// removed, `None` once resolved.
+    pub(crate) window_end: Option<DateTime<Utc>>,
+    pub(crate) reduction: i32,
+}
+
+impl SystemAllocation {
+    /// Reduce the concurrent capacity of the system by `reduction` from `start`, e.g. as one of
+    /// several identical fixtures fails, rather than blocking it entirely with an unplanned
+    /// outage.
+    ///
+    /// Entries are evaluated against the reduced capacity from `start` on, until the reduction is
+    /// resolved with [`SystemAllocation::resolve_capacity_reduction`]. Reductions in effect at
+    /// the same time sum up, and the capacity never goes below zero. As with an unplanned outage,
+    /// entries may be modified outside the sliding window, where only the declared capacity
+    /// applies to them.
+    ///
+    /// Within the sliding window, just enough entries are forcefully removed for the rest to fit
+    /// the reduced capacity, those of the lowest priority first, see
+    /// [`EntryParams::priority`](crate::EntryParams::priority), and the most recently inserted
+    /// first among those of the same priority. This is done both here and by the sweep, see
+    /// [`SystemAllocation::run_window_sweep`], as the window slides along. Pinned entries are
+    /// never removed, and are reported by the sweep instead.
+    ///
+    /// Fails with [`AllocationError::Validation`] unless `reduction` is positive, and
+    /// [`AllocationError::InvalidSlidingWindow`] as with
+    /// [`SystemAllocation::insert_unplanned_outage`].
+    ///
+    /// Returns the id of the reduction, by which it is later resolved, along with the removed
+    /// entries such that their owners may be notified.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_capacity_reduction(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        sliding_window: Duration,
+        reduction: i32,
+    ) -> Result<(Uuid, Vec<Entry>), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let inserted = self
+            .insert_capacity_reduction_tx(&mut tx, system, start, sliding_window, reduction)
+            .await?;
+        tx.commit().await?;
+
+        Ok(inserted)
+    }
+
+    /// As [`SystemAllocation::insert_capacity_reduction`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_capacity_reduction_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        start: DateTime<Utc>,
+        sliding_window: Duration,
+        reduction: i32,
+    ) -> Result<(Uuid, Vec<Entry>), AllocationError> {
+        if reduction <= 0 {
+            return Err(AllocationError::Validation(format!(
+                "invalid capacity reduction {reduction}"
+            )));
+        }
+        let interval = sliding_window_to_interval(sliding_window)?;
+        system_capacity(&mut *tx, system, Capabilities::empty()).await?;
+
+        let reduction_id = Uuid::new_v4();
+        sqlx::query!(
+            r#"
+        INSERT INTO capacity_reductions(reduction_id, system_id, start_time, sliding_window,
+            reduction)
+        VALUES ($1, $2, $3, $4, $5)
+            "#,
+            reduction_id,
+            system,
+            start,
+            interval,
+            reduction,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let report = sweep_reductions(tx, Some(system)).await?;
+
+        Ok((
+            reduction_id,
+            report.removed.into_iter().map(|(_, entry)| entry).collect(),
+        ))
+    }
+
+    /// Resolve a capacity reduction, giving it an end at `end`.
+    ///
+    /// The declared capacity applies again after the reduction has ended, and entries within its
+    /// sliding window are no longer forcefully removed. Fails with
+    /// [`AllocationError::NotFound`] if there is no reduction with the given id, and
+    /// [`AllocationError::InvalidRange`] if `end` is not after its start.
+    #[tracing::instrument(skip(self))]
+    pub async fn resolve_capacity_reduction(
+        &self,
+        reduction_id: Uuid,
+        end: DateTime<Utc>,
+    ) -> Result<(), AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        self.resolve_capacity_reduction_tx(&mut tx, reduction_id, end)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// As [`SystemAllocation::resolve_capacity_reduction`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn resolve_capacity_reduction_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        reduction_id: Uuid,
+        end: DateTime<Utc>,
+    ) -> Result<(), AllocationError> {
+        let reduction = sqlx::query!(
+            r#"
+        SELECT start_time, resolved_at FROM capacity_reductions
+        WHERE reduction_id = $1
+        FOR UPDATE
+            "#,
+            reduction_id,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AllocationError::NotFound(reduction_id))?;
+        if reduction.resolved_at.is_some() {
+            return Err(AllocationError::Validation(format!(
+                "capacity reduction {reduction_id} is already resolved"
+            )));
+        }
+        if end <= reduction.start_time {
+            return Err(AllocationError::InvalidRange {
+                start: reduction.start_time,
+                end,
+            });
+        }
+
+        sqlx::query!(
+            r#"
+        UPDATE capacity_reductions SET resolved_at = $2 WHERE reduction_id = $1
+            "#,
+            reduction_id,
+            end,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// The capacity reductions of each of `systems`, with the sliding window of those unresolved as
+/// of now.
+pub(crate) async fn capacity_reductions(
+    executor: impl PgExecutor<'_>,
+    systems: &[Uuid],
+) -> Result<HashMap<Uuid, Vec<CapacityReduction>>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+    SELECT system_id, start_time, resolved_at, reduction,
+        CASE WHEN resolved_at IS NULL THEN GREATEST(start_time, now()) + sliding_window
+        END AS window_end
+    FROM capacity_reductions
+    WHERE system_id = ANY($1)
+    ORDER BY start_time
+        "#,
+        systems,
+    )
+    .fetch_all(executor)
+    .await?;
+
+    let mut reductions: HashMap<_, Vec<_>> = HashMap::new();
+    for row in rows {
+        reductions
+            .entry(row.system_id)
+            .or_default()
+            .push(CapacityReduction {
+                start: row.start_time,
+                end: row.resolved_at,
+                window_end: row.window_end,
+                reduction: row.reduction,
+            });
+    }
+
+    Ok(reductions)
+}
+
+/// The sum of `reductions` in effect at `at`.
+fn reduced_by(reductions: &[CapacityReduction], at: DateTime<Utc>) -> i32 {
+    reductions
+        .iter()
+        .filter(|r| r.start <= at && r.end.is_none_or(|end| end > at))
+        .map(|r| r.reduction)
+        .sum()
+}
+
+/// The most the capacity is reduced by at any instant within `[start, end)`.
+pub(crate) fn peak_reduction(
+    reductions: &[CapacityReduction],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> i32 {
+    // The sum only ever increases as a reduction starts.
+    reductions
+        .iter()
+        .map(|r| r.start)
+        .filter(|at| start < *at && *at < end)
+        .chain(std::iter::once(start))
+        .map(|at| reduced_by(reductions, at))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Remove just enough entries within the sliding window of unresolved capacity reductions, on
+/// `system` or on all systems if `None`, for the rest to fit the reduced capacity. Each of them
+/// is recorded as evicted, and pinned entries are left in place.
+pub(crate) async fn sweep_reductions(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Option<Uuid>,
+) -> Result<SweepReport, sqlx::Error> {
+    let systems = sqlx::query!(
+        r#"
+    SELECT DISTINCT r.system_id, s.capacity
+    FROM capacity_reductions r
+    JOIN systems s USING (system_id)
+    WHERE ($1::uuid IS NULL OR r.system_id = $1) AND r.resolved_at IS NULL
+    ORDER BY r.system_id
+        "#,
+        system,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut report = SweepReport::default();
+    for row in systems {
+        lock_system(&mut *tx, row.system_id).await?;
+        let reductions = capacity_reductions(&mut *tx, &[row.system_id])
+            .await?
+            .remove(&row.system_id)
+            .unwrap_or_default();
+        let windows: Vec<_> = reductions
+            .iter()
+            .filter_map(|r| Some((r.start, r.window_end?)))
+            .collect();
+        let Some(from) = windows.iter().map(|(start, _)| *start).min() else {
+            continue;
+        };
+        let until = windows.iter().map(|(_, end)| *end).max().unwrap_or(from);
+
+        let entries = remaining_entries(&mut *tx, row.system_id, from).await?;
+        let ids: Vec<_> = entries.iter().map(|a| a.allocation_id).collect();
+        let removable: HashMap<_, _> = entry_params(&mut *tx, &ids)
+            .await?
+            .into_iter()
+            .filter(|(_, params)| !params.pinned)
+            .map(|(id, params)| (id, params.priority))
+            .collect();
+
+        // The capacity only changes as an entry or reduction starts or ends, or a window ends.
+        let mut points = breakpoints(&entries, from, until);
+        points.extend(
+            reductions
+                .iter()
+                .flat_map(|r| [Some(r.start), r.end, r.window_end])
+                .flatten()
+                .filter(|t| from < *t && *t < until),
+        );
+        points.sort();
+        points.dedup();
+
+        let mut excess = Vec::new();
+        let mut left = Vec::new();
+        for w in points.windows(2) {
+            let (start, end) = (w[0], w[1]);
+            if !windows
+                .iter()
+                .any(|(from, to)| *from <= start && end <= *to)
+            {
+                continue;
+            }
+            let capacity = (row.capacity - reduced_by(&reductions, start)).max(0);
+            let mut remaining: Vec<_> = covering(&entries, start, end)
+                .filter(|a| !excess.contains(&a.allocation_id))
+                .collect();
+            while remaining.len() as i32 > capacity {
+                // The lowest priority entry that may be removed, the most recently inserted
+                // among those of the same priority, if any.
+                let Some((next, _)) = remaining
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, a)| Some((i, *removable.get(&a.allocation_id)?)))
+                    .min_by_key(|&(i, priority)| (priority, Reverse(i)))
+                else {
+                    left.extend(remaining.iter().map(|a| a.allocation_id));
+                    break;
+                };
+                excess.push(remaining.remove(next).allocation_id);
+            }
+        }
+
+        report
+            .removed
+            .extend(evict_entries(&mut *tx, &excess).await?);
+        let mut params = entry_params(&mut *tx, &left).await?;
+        for allocation in entries {
+            let Some(params) = params.remove(&allocation.allocation_id) else {
+                continue;
+            };
+            let entry = Entry {
+                allocation_id: allocation.allocation_id,
+                start_time: allocation.start_time,
+                end_time: allocation.end_time,
+                capabilities: allocation.capabilities,
+                params,
+            };
+            report.pinned.push((row.system_id, entry));
+        }
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,131 @@
+//! Inserting outages by moving the entries in the way, rather than failing.
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::postgres::Postgres;
+use sqlx::Transaction;
+use uuid::Uuid;
+
+use crate::{
+    boundary_policy, check_range, delete_entry_rows, insert_entry, lock_system, next_free_slot,
+    overlapping_allocations, AllocationError, AllocationKind, Capabilities, SystemAllocation,
+};
+
+/// How entries in the way of an outage are rescheduled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReschedulePolicy {
+    /// How far past the end of the outage an entry may be moved to. An entry that does not fit
+    /// before then fails the whole operation.
+    pub horizon: Duration,
+}
+
+/// An entry moved out of the way of an outage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EntryMove {
+    pub allocation_id: Uuid,
+    pub capabilities: Capabilities,
+    /// The timespan of the entry before it was moved, as `(start, end)`.
+    pub from: (DateTime<Utc>, DateTime<Utc>),
+    /// The timespan of the entry after it was moved, as `(start, end)`.
+    pub to: (DateTime<Utc>, DateTime<Utc>),
+}
+
+impl SystemAllocation {
+    /// Insert a planned outage as with [`SystemAllocation::insert_planned_outage`], moving the
+    /// entries in the way to the earliest free slot after the outage rather than failing.
+    ///
+    /// Entries are moved in the order they started, keeping their allocation id, duration and
+    /// capabilities. Every move made is returned, such that the owners of the entries may be
+    /// notified. Fails with [`AllocationError::NotRescheduled`] if an entry does not fit within
+    /// the horizon of `policy`, or is open-ended, in which case nothing is moved.
+    #[tracing::instrument(skip(self))]
+    pub async fn insert_planned_outage_rescheduling(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        policy: ReschedulePolicy,
+    ) -> Result<Vec<EntryMove>, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let moves = self
+            .insert_planned_outage_rescheduling_tx(&mut tx, system, start, end, policy)
+            .await?;
+        tx.commit().await?;
+
+        Ok(moves)
+    }
+
+    /// As [`SystemAllocation::insert_planned_outage_rescheduling`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn insert_planned_outage_rescheduling_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        policy: ReschedulePolicy,
+    ) -> Result<Vec<EntryMove>, AllocationError> {
+        check_range(start, end)?;
+        let until = end.checked_add_signed(policy.horizon).ok_or_else(|| {
+            AllocationError::Validation(format!("horizon {} is out of range", policy.horizon))
+        })?;
+
+        lock_system(&mut *tx, system).await?;
+        let boundary = boundary_policy(&mut *tx, system).await?;
+        let displaced: Vec<_> = overlapping_allocations(&mut *tx, system, start, end, boundary)
+            .await?
+            .into_iter()
+            .filter(|a| a.kind == AllocationKind::Entry)
+            .collect();
+
+        // Remove all of them up front, such that none of them competes for a slot with what is
+        // left of the others after the outage.
+        let ids: Vec<_> = displaced.iter().map(|a| a.allocation_id).collect();
+        sqlx::query!(
+            r#"
+        DELETE FROM allocations WHERE allocation_id = ANY($1)
+            "#,
+            &ids,
+        )
+        .execute(&mut *tx)
+        .await?;
+        let mut params = delete_entry_rows(&mut *tx, &ids).await?;
+
+        let mut moves = Vec::with_capacity(displaced.len());
+        for entry in displaced {
+            // An open-ended entry remains in the way however far it is moved.
+            let Some(entry_end) = entry.end_time else {
+                return Err(AllocationError::NotRescheduled(entry.allocation_id));
+            };
+            let from = (entry.start_time, entry_end);
+            let duration = from.1 - from.0;
+            let new_start =
+                next_free_slot(&mut *tx, system, end, until, duration, entry.capabilities)
+                    .await?
+                    .ok_or(AllocationError::NotRescheduled(entry.allocation_id))?;
+            let to = (new_start, new_start + duration);
+
+            insert_entry(
+                &mut *tx,
+                system,
+                entry.allocation_id,
+                to.0,
+                Some(to.1),
+                entry.capabilities,
+                &params.remove(&entry.allocation_id).unwrap_or_default(),
+            )
+            .await?;
+            moves.push(EntryMove {
+                allocation_id: entry.allocation_id,
+                capabilities: entry.capabilities,
+                from,
+                to,
+            });
+        }
+
+        self.insert_planned_outage_tx(&mut *tx, system, start, end)
+            .await?;
+
+        Ok(moves)
+    }
+}
@@ -0,0 +1,102 @@
+//! The database schema, embedded from the migrations of the crate.
+
+use sqlx::migrate::{Migrate, Migrator};
+
+use crate::{AllocationError, SystemAllocation};
+
+/// The migrations creating the schema the allocations are stored in.
+///
+/// Tests may run against a database migrated by it with
+/// `#[sqlx::test(migrator = "allocation_poc::MIGRATOR")]`.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+impl SystemAllocation {
+    /// Apply every migration of [`MIGRATOR`] not yet applied to the database.
+    ///
+    /// This is a no-op when the schema is up to date, and is intended to run at startup.
+    #[tracing::instrument(skip(self))]
+    pub async fn migrate(&self) -> Result<(), AllocationError> {
+        MIGRATOR.run(&self.pool).await.map_err(sqlx::Error::from)?;
+
+        Ok(())
+    }
+
+    /// Fail with [`AllocationError::SchemaMismatch`] unless every migration of [`MIGRATOR`] has
+    /// been applied to the database, as is, without applying any of them.
+    #[tracing::instrument(skip(self))]
+    pub async fn ensure_schema(&self) -> Result<(), AllocationError> {
+        let expected = latest_version();
+        let mut conn = self.pool.acquire().await?;
+        if !migrations_table_exists(&mut conn).await? {
+            return Err(AllocationError::SchemaMismatch {
+                applied: None,
+                expected,
+            });
+        }
+
+        let dirty = conn
+            .dirty_version()
+            .await
+            .map_err(sqlx::Error::from)?
+            .is_some();
+        let applied = conn
+            .list_applied_migrations()
+            .await
+            .map_err(sqlx::Error::from)?;
+        let missing = MIGRATOR
+            .iter()
+            .filter(|m| !m.migration_type.is_down_migration())
+            .any(|m| {
+                !applied
+                    .iter()
+                    .any(|a| a.version == m.version && a.checksum == m.checksum)
+            });
+        if dirty || missing {
+            return Err(AllocationError::SchemaMismatch {
+                applied: applied.iter().map(|a| a.version).max(),
+                expected,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// The version of the latest migration applied to the database, if any.
+    ///
+    /// Operators may compare it against the latest version of [`MIGRATOR`] to assert the
+    /// schema is compatible at startup.
+    #[tracing::instrument(skip(self))]
+    pub async fn schema_version(&self) -> Result<Option<i64>, AllocationError> {
+        let mut conn = self.pool.acquire().await?;
+        if !migrations_table_exists(&mut conn).await? {
+            return Ok(None);
+        }
+
+        let applied = conn
+            .list_applied_migrations()
+            .await
+            .map_err(sqlx::Error::from)?;
+
+        Ok(applied.iter().map(|a| a.version).max())
+    }
+}
+
+/// The version of the latest migration of [`MIGRATOR`].
+fn latest_version() -> i64 {
+    MIGRATOR
+        .iter()
+        .map(|m| m.version)
+        .max()
+        .expect("the crate embeds migrations")
+}
+
+/// Whether any migration has been run against the database, creating the table tracking them.
+async fn migrations_table_exists(conn: &mut sqlx::PgConnection) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+    SELECT to_regclass('_sqlx_migrations') IS NOT NULL AS "exists!"
+        "#,
+    )
+    .fetch_one(conn)
+    .await
+}
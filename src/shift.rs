@@ -0,0 +1,130 @@
+//! Moving every entry within a timespan by the same offset at once.
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::postgres::Postgres;
+use sqlx::Transaction;
+use uuid::Uuid;
+
+use crate::{
+    booking_policy, check_entry_conflicts, check_not_in_window, check_range, lock_system,
+    overlapping_allocations, AllocationError, AllocationKind, BoundaryPolicy, SystemAllocation,
+};
+
+impl SystemAllocation {
+    /// Move every entry on `system` overlapping `in_range` by `by`, keeping their allocation id,
+    /// duration, capabilities and details.
+    ///
+    /// Each entry is evaluated at its new placement as with [`SystemAllocation::update_entry`],
+    /// against the other entries at theirs, and subject to the booking policy of the system. Should
+    /// any of them conflict, start when the policy does not allow, or already be within the sliding
+    /// window of an unplanned outage, this fails with the error of the earliest such entry and
+    /// nothing is moved. Holds are left where they are.
+    ///
+    /// Returns the allocation ids of the entries moved, in the order they start.
+    #[tracing::instrument(skip(self))]
+    pub async fn shift_entries(
+        &self,
+        system: Uuid,
+        in_range: (DateTime<Utc>, DateTime<Utc>),
+        by: Duration,
+    ) -> Result<Vec<Uuid>, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let shifted = self.shift_entries_tx(&mut tx, system, in_range, by).await?;
+        tx.commit().await?;
+
+        Ok(shifted)
+    }
+
+    /// As [`SystemAllocation::shift_entries`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn shift_entries_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        (start, end): (DateTime<Utc>, DateTime<Utc>),
+        by: Duration,
+    ) -> Result<Vec<Uuid>, AllocationError> {
+        check_range(start, end)?;
+
+        lock_system(&mut *tx, system).await?;
+        let (policy, now) = (booking_policy(&mut *tx, system).await?, self.now());
+        let overlapping =
+            overlapping_allocations(&mut *tx, system, start, end, BoundaryPolicy::Touching).await?;
+
+        // Move all of them up front, such that each is evaluated against where the others end up
+        // rather than where they were.
+        let mut shifted = Vec::new();
+        for entry in overlapping
+            .into_iter()
+            .filter(|a| a.kind == AllocationKind::Entry)
+        {
+            check_not_in_window(
+                &mut *tx,
+                system,
+                entry.start_time,
+                entry.end_time.unwrap_or(DateTime::<Utc>::MAX_UTC),
+                entry.capabilities,
+            )
+            .await?;
+            let shift = |t: DateTime<Utc>| {
+                t.checked_add_signed(by).ok_or_else(|| {
+                    AllocationError::Validation(format!(
+                        "shifting entry {} by {by} is out of range",
+                        entry.allocation_id
+                    ))
+                })
+            };
+            // An open-ended entry remains so, only its start is moved.
+            let to = (
+                shift(entry.start_time)?,
+                entry.end_time.map(shift).transpose()?,
+            );
+
+            // Holds have no row in entries, and are left to either be confirmed or expire.
+            let moved = sqlx::query!(
+                r#"
+            UPDATE entries SET start_time = $2, end_time = COALESCE($3, 'infinity'::timestamptz)
+            WHERE allocation_id = $1
+                "#,
+                entry.allocation_id,
+                to.0,
+                to.1,
+            )
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            if moved == 0 {
+                continue;
+            }
+            sqlx::query!(
+                r#"
+            UPDATE allocations SET start_time = $2, end_time = COALESCE($3, 'infinity'::timestamptz)
+            WHERE allocation_id = $1
+                "#,
+                entry.allocation_id,
+                to.0,
+                to.1,
+            )
+            .execute(&mut *tx)
+            .await?;
+            shifted.push((entry.allocation_id, to, entry.capabilities));
+        }
+
+        for &(allocation_id, (to_start, to_end), capabilities) in &shifted {
+            if by != Duration::zero() {
+                policy.check(now, to_start)?;
+            }
+            check_entry_conflicts(
+                &mut *tx,
+                system,
+                to_start,
+                to_end,
+                capabilities,
+                Some(allocation_id),
+            )
+            .await?;
+        }
+
+        Ok(shifted.into_iter().map(|(id, _, _)| id).collect())
+    }
+}
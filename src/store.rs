@@ -0,0 +1,180 @@
+//! The operations shared by every backend of the allocations, such that applications may be
+//! tested against an in-memory backend rather than a database.
+
+use std::future::Future;
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::{Allocation, AllocationError, Capabilities, Entry, Outage, SystemAllocation};
+
+/// Storage of the allocations of systems, upholding the capacity, outage and sliding window
+/// invariants described by the crate.
+///
+/// See the inherent methods of [`SystemAllocation`] of the same name for how each operation
+/// behaves, and fails. Every backend reports the same errors for the same requests, save for
+/// [`AllocationError::Database`], which only the database may fail with.
+pub trait AllocationStore {
+    fn declare_system(
+        &self,
+        system: Uuid,
+        capacity: i32,
+        capabilities: Capabilities,
+    ) -> impl Future<Output = Result<(), AllocationError>> + Send;
+
+    fn insert_entry(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> impl Future<Output = Result<Uuid, AllocationError>> + Send;
+
+    fn insert_planned_outage(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> impl Future<Output = Result<Uuid, AllocationError>> + Send;
+
+    fn insert_planned_capability_outage(
+        &self,
+        system: Uuid,
+        capabilities: Capabilities,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> impl Future<Output = Result<Uuid, AllocationError>> + Send;
+
+    fn insert_unplanned_outage(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        sliding_window: Duration,
+    ) -> impl Future<Output = Result<Uuid, AllocationError>> + Send;
+
+    fn insert_unplanned_capability_outage(
+        &self,
+        system: Uuid,
+        capabilities: Capabilities,
+        start: DateTime<Utc>,
+        sliding_window: Duration,
+    ) -> impl Future<Output = Result<Uuid, AllocationError>> + Send;
+
+    fn remove_entry(
+        &self,
+        allocation_id: Uuid,
+    ) -> impl Future<Output = Result<(), AllocationError>> + Send;
+
+    fn get_allocation(
+        &self,
+        allocation_id: Uuid,
+    ) -> impl Future<Output = Result<Option<Allocation>, AllocationError>> + Send;
+
+    fn list_entries(
+        &self,
+        system: Uuid,
+        range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> impl Future<Output = Result<Vec<Entry>, AllocationError>> + Send;
+
+    fn list_outages(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> impl Future<Output = Result<Vec<Outage>, AllocationError>> + Send;
+}
+
+impl AllocationStore for SystemAllocation {
+    async fn declare_system(
+        &self,
+        system: Uuid,
+        capacity: i32,
+        capabilities: Capabilities,
+    ) -> Result<(), AllocationError> {
+        SystemAllocation::declare_system(self, system, capacity, capabilities).await
+    }
+
+    async fn insert_entry(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<Uuid, AllocationError> {
+        SystemAllocation::insert_entry(self, system, start, end, capabilities).await
+    }
+
+    async fn insert_planned_outage(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Uuid, AllocationError> {
+        SystemAllocation::insert_planned_outage(self, system, start, end).await
+    }
+
+    async fn insert_planned_capability_outage(
+        &self,
+        system: Uuid,
+        capabilities: Capabilities,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Uuid, AllocationError> {
+        SystemAllocation::insert_planned_capability_outage(self, system, capabilities, start, end)
+            .await
+    }
+
+    async fn insert_unplanned_outage(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        sliding_window: Duration,
+    ) -> Result<Uuid, AllocationError> {
+        SystemAllocation::insert_unplanned_outage(self, system, start, sliding_window).await
+    }
+
+    async fn insert_unplanned_capability_outage(
+        &self,
+        system: Uuid,
+        capabilities: Capabilities,
+        start: DateTime<Utc>,
+        sliding_window: Duration,
+    ) -> Result<Uuid, AllocationError> {
+        SystemAllocation::insert_unplanned_capability_outage(
+            self,
+            system,
+            capabilities,
+            start,
+            sliding_window,
+        )
+        .await
+    }
+
+    async fn remove_entry(&self, allocation_id: Uuid) -> Result<(), AllocationError> {
+        SystemAllocation::remove_entry(self, allocation_id).await
+    }
+
+    async fn get_allocation(
+        &self,
+        allocation_id: Uuid,
+    ) -> Result<Option<Allocation>, AllocationError> {
+        SystemAllocation::get_allocation(self, allocation_id).await
+    }
+
+    async fn list_entries(
+        &self,
+        system: Uuid,
+        range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> Result<Vec<Entry>, AllocationError> {
+        SystemAllocation::list_entries(self, system, range).await
+    }
+
+    async fn list_outages(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Outage>, AllocationError> {
+        SystemAllocation::list_outages(self, system, start, end).await
+    }
+}
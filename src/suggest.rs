@@ -0,0 +1,153 @@
+//! Suggesting when a planned outage would be the least disruptive.
+
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use uuid::Uuid;
+
+use crate::{
+    boundary_policy, check_range, outage_conflicts, overlapping_allocations, system_capacity,
+    AllocationError, Blocker, BoundaryPolicy, Capabilities, SystemAllocation,
+};
+
+/// The longest search range of [`SystemAllocation::suggest_outage_window`], in days.
+const MAX_SEARCH_DAYS: i64 = 366;
+
+/// A candidate timespan `[start, end)` for a planned outage, see
+/// [`SystemAllocation::suggest_outage_window`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SuggestedWindow {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// The number of entries in conflict, to be cleared before the outage can be inserted.
+    pub conflicts: usize,
+}
+
+impl SystemAllocation {
+    /// Suggest up to `limit` timespans of `duration` within `[start, end)` for a planned outage
+    /// of `capabilities`, ranked by the number of entries in conflict, the fewest first.
+    ///
+    /// Windows overlapping another outage in the way are never suggested, as the outage could not
+    /// be inserted over them regardless. Among windows with as many conflicts, those starting at
+    /// a round hour are preferred, and then the earliest. The suggested windows do not overlap
+    /// each other, such that each is a distinct option.
+    ///
+    /// Fails with [`AllocationError::InvalidRange`] unless `duration` is positive and
+    /// `[start, end)` is a valid timespan, and with [`AllocationError::Validation`] if it spans
+    /// more than a year.
+    #[tracing::instrument(skip(self))]
+    pub async fn suggest_outage_window(
+        &self,
+        system: Uuid,
+        duration: Duration,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+        limit: usize,
+    ) -> Result<Vec<SuggestedWindow>, AllocationError> {
+        check_range(start, end)?;
+        if end - start > Duration::days(MAX_SEARCH_DAYS) {
+            return Err(AllocationError::Validation(format!(
+                "search range from {start} to {end} is longer than {MAX_SEARCH_DAYS} days"
+            )));
+        }
+        let first_end = start
+            .checked_add_signed(duration)
+            .ok_or(AllocationError::InvalidRange { start, end })?;
+        check_range(start, first_end)?;
+        if first_end > end {
+            return Ok(Vec::new());
+        }
+        let last_start = end - duration;
+
+        let mut tx = self.pool.begin().await?;
+        // An outage of the entire system covers every capability, declared or not.
+        let required = if capabilities == Capabilities::all() {
+            Capabilities::empty()
+        } else {
+            capabilities
+        };
+        system_capacity(&mut tx, system, required).await?;
+        let boundary = boundary_policy(&mut tx, system).await?;
+        let allocations = overlapping_allocations(&mut tx, system, start, end, boundary).await?;
+        tx.rollback().await?;
+
+        // The conflicts only change as an allocation ends, so every such start is considered
+        // along with the round hours.
+        let mut starts: Vec<_> = round_hours(start, last_start)
+            .chain(std::iter::once(start))
+            .chain(
+                allocations
+                    .iter()
+                    .filter_map(|a| Some(boundary.next_start(a.end_time?)))
+                    .filter(|at| start < *at && *at <= last_start),
+            )
+            .collect();
+        starts.sort();
+        starts.dedup();
+
+        let mut candidates: Vec<_> = starts
+            .into_iter()
+            .filter_map(|from| {
+                let to = from + duration;
+                let conflicts = outage_conflicts(
+                    allocations
+                        .iter()
+                        .filter(|a| boundary.overlaps((a.start_time, a.end_time), (from, Some(to))))
+                        .cloned(),
+                    (from, Some(to)),
+                    None,
+                    capabilities,
+                );
+                if conflicts.iter().any(|c| c.blocker != Blocker::Entry) {
+                    return None;
+                }
+                Some(SuggestedWindow {
+                    start: from,
+                    end: to,
+                    conflicts: conflicts.len(),
+                })
+            })
+            .collect();
+        candidates.sort_by_key(|w| (w.conflicts, !is_round_hour(w.start), w.start));
+
+        let mut suggested: Vec<SuggestedWindow> = Vec::new();
+        for candidate in candidates {
+            if suggested.len() == limit {
+                break;
+            }
+            if !suggested.iter().any(|w| {
+                BoundaryPolicy::Touching.overlaps(
+                    (w.start, Some(w.end)),
+                    (candidate.start, Some(candidate.end)),
+                )
+            }) {
+                suggested.push(candidate);
+            }
+        }
+
+        Ok(suggested)
+    }
+}
+
+/// The round hours within `[from, until]`.
+fn round_hours(from: DateTime<Utc>, until: DateTime<Utc>) -> impl Iterator<Item = DateTime<Utc>> {
+    let first = from
+        .duration_trunc(Duration::hours(1))
+        .map(|hour| {
+            if hour < from {
+                hour + Duration::hours(1)
+            } else {
+                hour
+            }
+        })
+        .unwrap_or(from);
+    std::iter::successors(Some(first), |hour| {
+        hour.checked_add_signed(Duration::hours(1))
+    })
+    .take_while(move |hour| *hour <= until)
+}
+
+/// Whether `at` is at the start of an hour.
+fn is_round_hour(at: DateTime<Utc>) -> bool {
+    at.duration_trunc(Duration::hours(1)) == Ok(at)
+}
@@ -0,0 +1,368 @@
+//! The continuous job clearing entries within the sliding window of unplanned outages.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use sqlx::postgres::Postgres;
+use sqlx::Transaction;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::reduction::sweep_reductions;
+use crate::{
+    decode_capabilities, delete_entry_rows, AllocationError, Capabilities, Entry, EntryParams,
+    EntryStatus, SystemAllocation,
+};
+
+/// The outcome of a single sweep over the unplanned outage windows.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SweepReport {
+    /// The forcefully removed entries, along with the system they were removed from.
+    pub removed: Vec<(Uuid, Entry)>,
+    /// The pinned entries within a window, along with their system. These are left in place,
+    /// requiring manual intervention, see [`SystemAllocation::set_pinned`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pinned: Vec<(Uuid, Entry)>,
+}
+
+/// An entry forcefully removed by a sweep, as recorded at the time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Eviction {
+    pub allocation_id: Uuid,
+    pub start_time: DateTime<Utc>,
+    /// `None` for an open-ended entry.
+    pub end_time: Option<DateTime<Utc>>,
+    pub capabilities: Capabilities,
+    /// The details of the entry, see [`EntryParams`].
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub params: EntryParams,
+    /// When the sweep removing the entry was run.
+    pub evicted_at: DateTime<Utc>,
+}
+
+impl SystemAllocation {
+    /// Forcefully remove all entries within the sliding window of any unresolved unplanned
+    /// outage on the system, returning the allocation ids of the removed entries.
+    ///
+    /// The window slides along with time, covering from the start of the outage until the
+    /// sliding window duration from now, or from the start if it is yet to come. Entries
+    /// further ahead are allowed to stay put, until they fall within the window. Pinned entries
+    /// are never removed, see [`SystemAllocation::run_window_sweep`] for reporting them.
+    ///
+    /// Within the sliding window of an unresolved capacity reduction, only the entries over the
+    /// reduced capacity are removed, see [`SystemAllocation::insert_capacity_reduction`].
+    ///
+    /// This is intended to run continuously, and is a no-op when there is nothing to remove.
+    #[tracing::instrument(skip(self))]
+    pub async fn sweep_unplanned_windows(
+        &self,
+        system: Uuid,
+    ) -> Result<Vec<Uuid>, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let removed = self.sweep_unplanned_windows_tx(&mut tx, system).await?;
+        tx.commit().await?;
+
+        Ok(removed)
+    }
+
+    /// As [`SystemAllocation::sweep_unplanned_windows`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn sweep_unplanned_windows_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+    ) -> Result<Vec<Uuid>, AllocationError> {
+        let report = sweep(tx, Some(system)).await?;
+
+        Ok(report
+            .removed
+            .into_iter()
+            .map(|(_, entry)| entry.allocation_id)
+            .collect())
+    }
+
+    /// Sweep the unplanned outage windows of all systems, as with
+    /// [`SystemAllocation::sweep_unplanned_windows`], reporting the removed entries such that
+    /// their owners may be notified, and the pinned entries left in place.
+    #[tracing::instrument(skip(self))]
+    pub async fn run_window_sweep(&self) -> Result<SweepReport, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let report = self.run_window_sweep_tx(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(report)
+    }
+
+    /// As [`SystemAllocation::run_window_sweep`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn run_window_sweep_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<SweepReport, AllocationError> {
+        Ok(sweep(tx, None).await?)
+    }
+
+    /// List the entries evicted from the system by sweeps since `since`, ordered by when they
+    /// were evicted.
+    ///
+    /// Every entry removed by a sweep is recorded within the same transaction, such that owners
+    /// may be notified of evictions they missed.
+    #[tracing::instrument(skip(self))]
+    pub async fn list_evictions(
+        &self,
+        system: Uuid,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Eviction>, AllocationError> {
+        let rows = sqlx::query!(
+            r#"
+        SELECT allocation_id, start_time, NULLIF(end_time, 'infinity') AS end_time, capabilities,
+            owner, label, metadata, priority, evicted_at
+        FROM evictions
+        WHERE system_id = $1 AND evicted_at >= $2
+        ORDER BY evicted_at, start_time
+            "#,
+            system,
+            since,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                Ok(Eviction {
+                    allocation_id: row.allocation_id,
+                    start_time: row.start_time,
+                    end_time: row.end_time,
+                    capabilities: decode_capabilities(row.capabilities)?,
+                    params: EntryParams {
+                        owner: row.owner,
+                        label: row.label,
+                        metadata: row.metadata,
+                        // Pinned entries are never evicted, nor are those finished.
+                        pinned: false,
+                        priority: row.priority,
+                        status: EntryStatus::Scheduled,
+                        cancel_reason: None,
+                    },
+                    evicted_at: row.evicted_at,
+                })
+            })
+            .collect::<Result<_, sqlx::Error>>()?)
+    }
+
+    /// Continuously run [`SystemAllocation::run_window_sweep`] on the given interval, until the
+    /// returned task is aborted.
+    ///
+    /// Removals and failed sweeps are logged, a failed sweep is retried on the next tick.
+    pub fn spawn_sweep_task(self: Arc<Self>, interval: std::time::Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                match self.run_window_sweep().await {
+                    Ok(report) => {
+                        for (system, entry) in report.removed {
+                            tracing::info!(
+                                %system,
+                                allocation_id = %entry.allocation_id,
+                                start = %entry.start_time,
+                                end = ?entry.end_time,
+                                "removed entry within unplanned outage window"
+                            );
+                        }
+                        for (system, entry) in report.pinned {
+                            tracing::warn!(
+                                %system,
+                                allocation_id = %entry.allocation_id,
+                                start = %entry.start_time,
+                                end = ?entry.end_time,
+                                "pinned entry within unplanned outage window, manual intervention required"
+                            );
+                        }
+                    }
+                    Err(err) => tracing::warn!(%err, "unplanned outage window sweep failed"),
+                }
+            }
+        })
+    }
+}
+
+/// Remove all entries within the sliding window of unresolved unplanned outages, on `system` or
+/// on all systems if `None`, recording each of them as evicted, followed by those over the
+/// capacity within the window of a capacity reduction. Pinned entries are left in place.
+async fn sweep(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Option<Uuid>,
+) -> Result<SweepReport, sqlx::Error> {
+    let pinned = sqlx::query!(
+        r#"
+    SELECT DISTINCT a.system_id, a.allocation_id, a.start_time,
+        NULLIF(a.end_time, 'infinity') AS end_time, a.capabilities, e.owner, e.label, e.metadata,
+        e.priority, e.status AS "status: EntryStatus"
+    FROM allocations a
+    JOIN entries e USING (allocation_id)
+    JOIN allocations o ON o.system_id = a.system_id
+    JOIN unplanned u ON u.allocation_id = o.allocation_id
+    WHERE ($1::uuid IS NULL OR a.system_id = $1)
+        AND a.kind = 'entry'
+        AND e.pinned
+        AND NOT entry_finished(a.allocation_id)
+        AND NOT o.planned
+        AND u.resolved_at IS NULL
+        AND a.capabilities & o.capabilities != 0
+        AND o.start_time < a.end_time
+        AND (GREATEST(o.start_time, now()) + u.sliding_window) > a.start_time
+    ORDER BY a.start_time
+        "#,
+        system,
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|row| {
+        let entry = Entry {
+            allocation_id: row.allocation_id,
+            start_time: row.start_time,
+            end_time: row.end_time,
+            capabilities: decode_capabilities(row.capabilities)?,
+            params: EntryParams {
+                owner: row.owner,
+                label: row.label,
+                metadata: row.metadata,
+                pinned: true,
+                priority: row.priority,
+                status: row.status,
+                cancel_reason: None,
+            },
+        };
+        Ok((row.system_id, entry))
+    })
+    .collect::<Result<_, sqlx::Error>>()?;
+
+    let rows = sqlx::query!(
+        r#"
+    DELETE FROM allocations e
+    USING allocations o
+    JOIN unplanned u USING (allocation_id)
+    WHERE ($1::uuid IS NULL OR e.system_id = $1)
+        AND e.kind = 'entry'
+        AND o.system_id = e.system_id
+        AND NOT o.planned
+        AND u.resolved_at IS NULL
+        AND e.capabilities & o.capabilities != 0
+        AND o.start_time < e.end_time
+        AND (GREATEST(o.start_time, now()) + u.sliding_window) > e.start_time
+        AND NOT EXISTS (
+            SELECT 1 FROM entries p WHERE p.allocation_id = e.allocation_id AND p.pinned
+        )
+        AND NOT entry_finished(e.allocation_id)
+    RETURNING e.system_id, e.allocation_id, e.start_time, NULLIF(e.end_time, 'infinity') AS end_time,
+        e.capabilities
+        "#,
+        system,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    // Recorded before the rows of the entries are deleted, keeping their details along.
+    let removed: Vec<_> = rows.iter().map(|row| row.allocation_id).collect();
+    let systems: Vec<_> = rows.iter().map(|row| row.system_id).collect();
+    let starts: Vec<_> = rows.iter().map(|row| row.start_time).collect();
+    let ends: Vec<_> = rows.iter().map(|row| row.end_time).collect();
+    let capabilities: Vec<_> = rows.iter().map(|row| row.capabilities).collect();
+    sqlx::query!(
+        r#"
+    INSERT INTO evictions(allocation_id, system_id, start_time, end_time, capabilities, owner,
+        label, metadata, priority)
+    SELECT r.allocation_id, r.system_id, r.start_time, COALESCE(r.end_time, 'infinity'),
+        r.capabilities, e.owner, e.label, e.metadata, COALESCE(e.priority, 0)
+    FROM UNNEST($1::uuid[], $2::uuid[], $3::timestamptz[], $4::timestamptz[], $5::bigint[])
+        AS r(allocation_id, system_id, start_time, end_time, capabilities)
+    LEFT JOIN entries e USING (allocation_id)
+        "#,
+        &removed,
+        &systems,
+        &starts,
+        &ends as &[Option<DateTime<Utc>>],
+        &capabilities,
+    )
+    .execute(&mut *tx)
+    .await?;
+    let mut params = delete_entry_rows(&mut *tx, &removed).await?;
+
+    let removed = rows
+        .into_iter()
+        .map(|row| {
+            let entry = Entry {
+                allocation_id: row.allocation_id,
+                start_time: row.start_time,
+                end_time: row.end_time,
+                capabilities: decode_capabilities(row.capabilities)?,
+                params: params.remove(&row.allocation_id).unwrap_or_default(),
+            };
+            Ok((row.system_id, entry))
+        })
+        .collect::<Result<_, sqlx::Error>>()?;
+
+    let mut report = SweepReport { removed, pinned };
+    let reduced = sweep_reductions(tx, system).await?;
+    report.removed.extend(reduced.removed);
+    report.pinned.extend(reduced.pinned);
+
+    Ok(report)
+}
+
+/// Forcefully remove the entries `allocation_ids`, recording each of them as evicted, and
+/// returning them along with the system they were removed from.
+pub(crate) async fn evict_entries(
+    tx: &mut Transaction<'_, Postgres>,
+    allocation_ids: &[Uuid],
+) -> Result<Vec<(Uuid, Entry)>, sqlx::Error> {
+    if allocation_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    sqlx::query!(
+        r#"
+    INSERT INTO evictions(allocation_id, system_id, start_time, end_time, capabilities, owner,
+        label, metadata, priority)
+    SELECT a.allocation_id, a.system_id, a.start_time, a.end_time, a.capabilities, e.owner,
+        e.label, e.metadata, COALESCE(e.priority, 0)
+    FROM allocations a
+    LEFT JOIN entries e USING (allocation_id)
+    WHERE a.allocation_id = ANY($1)
+        "#,
+        allocation_ids,
+    )
+    .execute(&mut *tx)
+    .await?;
+    let mut rows = sqlx::query!(
+        r#"
+    DELETE FROM allocations
+    WHERE allocation_id = ANY($1)
+    RETURNING system_id, allocation_id, start_time, NULLIF(end_time, 'infinity') AS end_time,
+        capabilities
+        "#,
+        allocation_ids,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    rows.sort_by_key(|row| row.start_time);
+    let mut params = delete_entry_rows(tx, allocation_ids).await?;
+
+    rows.into_iter()
+        .map(|row| {
+            let entry = Entry {
+                allocation_id: row.allocation_id,
+                start_time: row.start_time,
+                end_time: row.end_time,
+                capabilities: decode_capabilities(row.capabilities)?,
+                params: params.remove(&row.allocation_id).unwrap_or_default(),
+            };
+            Ok((row.system_id, entry))
+        })
+        .collect()
+}
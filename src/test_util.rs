@@ -0,0 +1,94 @@
+//! Fixtures for tests exercising the allocations of a system, in this crate and downstream.
+
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use uuid::Uuid;
+
+use crate::{AllocationError, AllocationStore, Capabilities};
+
+mod memory;
+
+pub use memory::MemoryStore;
+
+/// Declares a fresh system, with a capacity of one and all capabilities unless told otherwise.
+///
+/// ```ignore
+/// let system = SystemBuilder::new(&planner).capacity(6).declare().await?;
+/// system.entry_at(0, 15, Capabilities::A).await?;
+/// ```
+pub struct SystemBuilder<'a, S> {
+    store: &'a S,
+    capacity: i32,
+    capabilities: Capabilities,
+}
+
+impl<'a, S: AllocationStore> SystemBuilder<'a, S> {
+    pub fn new(store: &'a S) -> Self {
+        Self {
+            store,
+            capacity: 1,
+            capabilities: Capabilities::all(),
+        }
+    }
+
+    pub fn capacity(mut self, capacity: i32) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Declare the system under a new id, taking the current time as the reference point of the
+    /// offsets given to the returned handle.
+    pub async fn declare(self) -> Result<TestSystem<'a, S>, AllocationError> {
+        let id = Uuid::new_v4();
+        self.store
+            .declare_system(id, self.capacity, self.capabilities)
+            .await?;
+
+        Ok(TestSystem {
+            store: self.store,
+            id,
+            now: Utc::now()
+                .duration_trunc(Duration::seconds(1))
+                .expect("the current time is within range"),
+        })
+    }
+}
+
+/// A system declared by [`SystemBuilder`], placing allocations relative to when it was declared.
+pub struct TestSystem<'a, S> {
+    store: &'a S,
+    /// The id of the system.
+    pub id: Uuid,
+    /// When the system was declared, truncated to whole seconds.
+    pub now: DateTime<Utc>,
+}
+
+impl<S: AllocationStore> TestSystem<'_, S> {
+    /// The instant `offset_minutes` from when the system was declared.
+    pub fn at(&self, offset_minutes: i64) -> DateTime<Utc> {
+        self.now + Duration::minutes(offset_minutes)
+    }
+
+    /// Insert an entry starting `offset_minutes` from when the system was declared, returning
+    /// its allocation id.
+    pub async fn entry_at(
+        &self,
+        offset_minutes: i64,
+        duration_minutes: i64,
+        capabilities: Capabilities,
+    ) -> Result<Uuid, AllocationError> {
+        let start = self.at(offset_minutes);
+        self.store
+            .insert_entry(
+                self.id,
+                start,
+                start + Duration::minutes(duration_minutes),
+                capabilities,
+            )
+            .await
+    }
+}
@@ -0,0 +1,372 @@
+//! An in-memory backend of the allocations, for testing applications without a database.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use uuid::Uuid;
+
+use crate::interval::{interval_to_duration, intervals_overlap, sliding_window_to_interval};
+use crate::{
+    check_entry_capabilities, check_range, classify_conflicts, conflicts_to_error,
+    outage_conflicts, outage_conflicts_to_error, Allocation, AllocationError, AllocationKind,
+    AllocationStore, Capabilities, CapacityLimits, Entry, EntryParams, Outage,
+};
+
+/// Allocations kept in memory, evaluated by the same rules as those the database enforces.
+///
+/// Nothing is persisted, and every operation is applied at once rather than within a
+/// transaction. Declaring a system twice fails with [`AllocationError::Validation`], where the
+/// database fails with [`AllocationError::Database`].
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The capacity and capabilities of each declared system.
+    systems: HashMap<Uuid, (i32, Capabilities)>,
+    allocations: Vec<Allocation>,
+    /// The sliding window of each unplanned outage.
+    sliding_windows: HashMap<Uuid, Duration>,
+}
+
+impl MemoryStore {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl State {
+    /// The capacity of `system`, failing unless it has been declared with all `capabilities`.
+    fn capacity(&self, system: Uuid, capabilities: Capabilities) -> Result<i32, AllocationError> {
+        let (capacity, supported) = *self
+            .systems
+            .get(&system)
+            .ok_or(AllocationError::SystemNotFound(system))?;
+        if !supported.contains(capabilities) {
+            return Err(AllocationError::UnsupportedCapabilities {
+                requested: capabilities,
+                supported,
+            });
+        }
+
+        Ok(capacity)
+    }
+
+    /// All allocations on `system` overlapping the timespan `[start, end)`, ordered by start.
+    fn overlapping(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+    ) -> Vec<Allocation> {
+        let mut overlapping: Vec<_> = self
+            .allocations
+            .iter()
+            .filter(|a| a.system_id == system)
+            .filter(|a| intervals_overlap((a.start_time, a.end_time), (start, end)))
+            .cloned()
+            .collect();
+        overlapping.sort_by_key(|a| a.start_time);
+        overlapping
+    }
+
+    fn insert_outage(
+        &mut self,
+        system: Uuid,
+        kind: AllocationKind,
+        (start, end): (DateTime<Utc>, Option<DateTime<Utc>>),
+        capabilities: Capabilities,
+    ) -> Uuid {
+        let allocation_id = Uuid::new_v4();
+        self.allocations.push(Allocation {
+            system_id: system,
+            allocation_id,
+            kind,
+            planned: end.is_some(),
+            start_time: start,
+            end_time: end,
+            capabilities,
+        });
+        allocation_id
+    }
+
+    /// Insert an open-ended outage of `capabilities` from `start`, unless it is in conflict
+    /// with the entries within its sliding `window`.
+    fn insert_unplanned_outage(
+        &mut self,
+        system: Uuid,
+        capabilities: Capabilities,
+        start: DateTime<Utc>,
+        window: Duration,
+    ) -> Result<Uuid, AllocationError> {
+        let overlapping = self.overlapping(system, start, None);
+        outage_conflicts_to_error(outage_conflicts(
+            overlapping,
+            (start, None),
+            Some(window),
+            capabilities,
+        ))?;
+
+        let kind = if capabilities == Capabilities::all() {
+            AllocationKind::Full
+        } else {
+            AllocationKind::Capability
+        };
+        let allocation_id = self.insert_outage(system, kind, (start, None), capabilities);
+        self.sliding_windows.insert(allocation_id, window);
+
+        Ok(allocation_id)
+    }
+}
+
+/// Truncate to the precision timestamps are stored with in the database.
+fn stored(instant: DateTime<Utc>) -> DateTime<Utc> {
+    instant
+        .duration_trunc(Duration::microseconds(1))
+        .unwrap_or(instant)
+}
+
+impl AllocationStore for MemoryStore {
+    async fn declare_system(
+        &self,
+        system: Uuid,
+        capacity: i32,
+        capabilities: Capabilities,
+    ) -> Result<(), AllocationError> {
+        let mut state = self.state();
+        if state.systems.contains_key(&system) {
+            return Err(AllocationError::Validation(format!(
+                "system {system} is already declared"
+            )));
+        }
+        state.systems.insert(system, (capacity, capabilities));
+
+        Ok(())
+    }
+
+    async fn insert_entry(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<Uuid, AllocationError> {
+        let (start, end) = (stored(start), stored(end));
+        check_range(start, end)?;
+        check_entry_capabilities(capabilities)?;
+
+        let mut state = self.state();
+        let limits = CapacityLimits {
+            system: state.capacity(system, capabilities)?,
+            capabilities: Vec::new(),
+            reductions: Vec::new(),
+        };
+        let conflicts = classify_conflicts(
+            state.overlapping(system, start, Some(end)),
+            &limits,
+            (start, Some(end)),
+            capabilities,
+            None,
+            &[],
+        );
+        limits.with_occupancy(conflicts_to_error(conflicts), (start, Some(end)))?;
+
+        let allocation_id = Uuid::new_v4();
+        state.allocations.push(Allocation {
+            system_id: system,
+            allocation_id,
+            kind: AllocationKind::Entry,
+            planned: true,
+            start_time: start,
+            end_time: Some(end),
+            capabilities,
+        });
+
+        Ok(allocation_id)
+    }
+
+    async fn insert_planned_outage(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Uuid, AllocationError> {
+        let (start, end) = (stored(start), stored(end));
+        check_range(start, end)?;
+
+        let mut state = self.state();
+        let capabilities = Capabilities::all();
+        let overlapping = state.overlapping(system, start, Some(end));
+        outage_conflicts_to_error(outage_conflicts(
+            overlapping,
+            (start, Some(end)),
+            None,
+            capabilities,
+        ))?;
+        state.capacity(system, Capabilities::empty())?;
+
+        Ok(state.insert_outage(
+            system,
+            AllocationKind::Full,
+            (start, Some(end)),
+            capabilities,
+        ))
+    }
+
+    async fn insert_planned_capability_outage(
+        &self,
+        system: Uuid,
+        capabilities: Capabilities,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Uuid, AllocationError> {
+        let (start, end) = (stored(start), stored(end));
+        check_range(start, end)?;
+
+        let mut state = self.state();
+        state.capacity(system, capabilities)?;
+        let overlapping = state.overlapping(system, start, Some(end));
+        outage_conflicts_to_error(outage_conflicts(
+            overlapping,
+            (start, Some(end)),
+            None,
+            capabilities,
+        ))?;
+
+        Ok(state.insert_outage(
+            system,
+            AllocationKind::Capability,
+            (start, Some(end)),
+            capabilities,
+        ))
+    }
+
+    async fn insert_unplanned_outage(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        sliding_window: Duration,
+    ) -> Result<Uuid, AllocationError> {
+        let window = interval_to_duration(sliding_window_to_interval(sliding_window)?);
+
+        let mut state = self.state();
+        state.capacity(system, Capabilities::empty())?;
+        state.insert_unplanned_outage(system, Capabilities::all(), stored(start), window)
+    }
+
+    async fn insert_unplanned_capability_outage(
+        &self,
+        system: Uuid,
+        capabilities: Capabilities,
+        start: DateTime<Utc>,
+        sliding_window: Duration,
+    ) -> Result<Uuid, AllocationError> {
+        let window = interval_to_duration(sliding_window_to_interval(sliding_window)?);
+
+        let mut state = self.state();
+        state.capacity(system, capabilities)?;
+        state.insert_unplanned_outage(system, capabilities, stored(start), window)
+    }
+
+    async fn remove_entry(&self, allocation_id: Uuid) -> Result<(), AllocationError> {
+        let mut state = self.state();
+        let index = state
+            .allocations
+            .iter()
+            .position(|a| a.allocation_id == allocation_id)
+            .ok_or(AllocationError::NotFound(allocation_id))?;
+        let kind = state.allocations[index].kind;
+        if kind != AllocationKind::Entry {
+            return Err(AllocationError::WrongKind {
+                allocation_id,
+                kind,
+            });
+        }
+        state.allocations.remove(index);
+
+        Ok(())
+    }
+
+    async fn get_allocation(
+        &self,
+        allocation_id: Uuid,
+    ) -> Result<Option<Allocation>, AllocationError> {
+        Ok(self
+            .state()
+            .allocations
+            .iter()
+            .find(|a| a.allocation_id == allocation_id)
+            .cloned())
+    }
+
+    async fn list_entries(
+        &self,
+        system: Uuid,
+        range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> Result<Vec<Entry>, AllocationError> {
+        let state = self.state();
+        let allocations = match range {
+            Some((start, end)) => state.overlapping(system, start, Some(end)),
+            None => state.overlapping(system, DateTime::<Utc>::MIN_UTC, None),
+        };
+
+        Ok(allocations
+            .into_iter()
+            .filter(|a| a.kind == AllocationKind::Entry)
+            .map(|a| Entry {
+                allocation_id: a.allocation_id,
+                start_time: a.start_time,
+                end_time: a.end_time,
+                capabilities: a.capabilities,
+                params: EntryParams::default(),
+            })
+            .collect())
+    }
+
+    async fn list_outages(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Outage>, AllocationError> {
+        let state = self.state();
+
+        Ok(state
+            .overlapping(system, start, Some(end))
+            .into_iter()
+            .filter(|a| a.kind != AllocationKind::Entry)
+            .map(
+                |a| match (state.sliding_windows.get(&a.allocation_id), a.end_time) {
+                    (Some(sliding_window), resolved_at) => Outage::Unplanned {
+                        allocation_id: a.allocation_id,
+                        start: a.start_time,
+                        sliding_window: *sliding_window,
+                        resolved_at,
+                        capabilities: a.capabilities,
+                    },
+                    (None, end) => {
+                        let end = end.expect("planned outages always have an end");
+                        match a.kind {
+                            AllocationKind::Capability => Outage::Capability {
+                                allocation_id: a.allocation_id,
+                                start: a.start_time,
+                                end,
+                                capabilities: a.capabilities,
+                            },
+                            _ => Outage::Planned {
+                                allocation_id: a.allocation_id,
+                                start: a.start_time,
+                                end,
+                            },
+                        }
+                    }
+                },
+            )
+            .collect())
+    }
+}
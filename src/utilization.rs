@@ -0,0 +1,198 @@
+//! Utilization of a system over time, for capacity planning.
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::{
+    capacity_peak, check_range, overlapping_allocations, system_info, Allocation, AllocationError,
+    AllocationKind, BoundaryPolicy, CapacityPeak, SystemAllocation,
+};
+
+/// How a system was utilized over the timespan `[start, end)` of a bucket.
+///
+/// Allocations crossing the boundaries of the bucket only count for the part within it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UtilizationBucket {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// The total time booked by entries, summed over concurrent entries.
+    #[cfg_attr(feature = "serde", serde(with = "crate::interval::serde_seconds"))]
+    pub booked: Duration,
+    /// The time the system was out by planned outages of all capabilities.
+    #[cfg_attr(feature = "serde", serde(with = "crate::interval::serde_seconds"))]
+    pub planned_outage: Duration,
+    /// The time any capabilities were out by planned outages of only some capabilities.
+    #[cfg_attr(feature = "serde", serde(with = "crate::interval::serde_seconds"))]
+    pub capability_outage: Duration,
+    /// The time any capabilities were out by unplanned outages, which count until the end of the
+    /// bucket until resolved.
+    #[cfg_attr(feature = "serde", serde(with = "crate::interval::serde_seconds"))]
+    pub unplanned_outage: Duration,
+    /// The number of concurrent entries, on average over the bucket.
+    pub average_occupancy: f64,
+    /// The instant within the bucket at which the most entries are concurrent.
+    pub peak: CapacityPeak,
+    /// The concurrent capacity of entries on the system, as currently declared.
+    pub capacity: i32,
+}
+
+impl SystemAllocation {
+    /// Report how the system is utilized over the timespan `[from, to)`, partitioned into
+    /// consecutive buckets of `bucket` each, e.g. a day.
+    ///
+    /// The last bucket is cut short at `to`, should the timespan not be a whole number of
+    /// buckets. Fails with [`AllocationError::Validation`] unless `bucket` is positive.
+    #[tracing::instrument(skip(self))]
+    pub async fn utilization_report(
+        &self,
+        system: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        bucket: Duration,
+    ) -> Result<Vec<UtilizationBucket>, AllocationError> {
+        check_range(from, to)?;
+        if bucket <= Duration::zero() {
+            return Err(AllocationError::Validation(format!(
+                "invalid bucket {bucket}"
+            )));
+        }
+
+        let capacity = system_info(&self.pool, system)
+            .await?
+            .ok_or(AllocationError::SystemNotFound(system))?
+            .capacity;
+        let allocations =
+            overlapping_allocations(&self.pool, system, from, to, BoundaryPolicy::Touching).await?;
+
+        Ok(utilization(&allocations, capacity, from, to, bucket))
+    }
+}
+
+/// Partition `[from, to)` into buckets, apportioning `allocations` to each of them.
+fn utilization(
+    allocations: &[Allocation],
+    capacity: i32,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    bucket: Duration,
+) -> Vec<UtilizationBucket> {
+    let (entries, outages): (Vec<_>, Vec<_>) = allocations
+        .iter()
+        .cloned()
+        .partition(|a| a.kind == AllocationKind::Entry);
+
+    let mut buckets = Vec::new();
+    let mut start = from;
+    while start < to {
+        let end = start
+            .checked_add_signed(bucket)
+            .map_or(to, |end| end.min(to));
+        let booked = total_overlap(&entries, start, end);
+        let outage = |planned: bool, kind: Option<AllocationKind>| {
+            total_overlap(
+                outages
+                    .iter()
+                    .filter(|a| a.planned == planned && kind.is_none_or(|kind| a.kind == kind)),
+                start,
+                end,
+            )
+        };
+        let in_bucket: Vec<_> = entries
+            .iter()
+            .filter(|a| overlap(a, start, end) > Duration::zero())
+            .cloned()
+            .collect();
+
+        buckets.push(UtilizationBucket {
+            start,
+            end,
+            booked,
+            planned_outage: outage(true, Some(AllocationKind::Full)),
+            capability_outage: outage(true, Some(AllocationKind::Capability)),
+            unplanned_outage: outage(false, None),
+            average_occupancy: seconds(booked) / seconds(end - start),
+            peak: capacity_peak(&in_bucket, start, end),
+            capacity,
+        });
+        start = end;
+    }
+
+    buckets
+}
+
+/// The total time of `allocations` within `[start, end)`.
+fn total_overlap<'a>(
+    allocations: impl IntoIterator<Item = &'a Allocation>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Duration {
+    allocations
+        .into_iter()
+        .map(|a| overlap(a, start, end))
+        .fold(Duration::zero(), |total, overlap| total + overlap)
+}
+
+/// How much of the allocation is within `[start, end)`, where an allocation without an end
+/// lasts beyond it.
+fn overlap(allocation: &Allocation, start: DateTime<Utc>, end: DateTime<Utc>) -> Duration {
+    let from = allocation.start_time.max(start);
+    let to = allocation.end_time.map_or(end, |e| e.min(end));
+    (to - from).max(Duration::zero())
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration
+        .num_microseconds()
+        .map_or(duration.num_seconds() as f64, |us| us as f64 / 1e6)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Capabilities;
+
+    fn at(hour: i64) -> DateTime<Utc> {
+        DateTime::<Utc>::MIN_UTC + Duration::days(1000) + Duration::hours(hour)
+    }
+
+    fn allocation(kind: AllocationKind, planned: bool, start: i64, end: Option<i64>) -> Allocation {
+        Allocation {
+            system_id: Uuid::nil(),
+            allocation_id: Uuid::new_v4(),
+            kind,
+            planned,
+            start_time: at(start),
+            end_time: end.map(at),
+            capabilities: Capabilities::A,
+        }
+    }
+
+    #[test]
+    fn apportioned_across_buckets() {
+        let allocations = [
+            // Straddling the boundary of the first two buckets
+            allocation(AllocationKind::Entry, true, 20, Some(28)),
+            allocation(AllocationKind::Entry, true, 22, Some(23)),
+            // Unresolved, lasting until the end of the range
+            allocation(AllocationKind::Full, false, 40, None),
+        ];
+        let buckets = utilization(&allocations, 2, at(0), at(60), Duration::hours(24));
+
+        assert_eq!(buckets.len(), 3);
+        assert_eq!(buckets[0].booked, Duration::hours(5));
+        assert_eq!(buckets[0].peak.concurrent, 2);
+        assert_eq!(buckets[0].peak.at, at(22));
+        assert_eq!(buckets[1].booked, Duration::hours(4));
+        assert_eq!(buckets[1].peak.concurrent, 1);
+        assert_eq!(buckets[1].average_occupancy, 4.0 / 24.0);
+        assert_eq!(buckets[1].unplanned_outage, Duration::hours(8));
+
+        // The last bucket is cut short
+        assert_eq!((buckets[2].start, buckets[2].end), (at(48), at(60)));
+        assert_eq!(buckets[2].booked, Duration::zero());
+        assert_eq!(buckets[2].peak.concurrent, 0);
+        assert_eq!(buckets[2].unplanned_outage, Duration::hours(12));
+    }
+}
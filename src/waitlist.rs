@@ -0,0 +1,235 @@
+//! Queueing requests for a timeslot without room, promoted into entries as room is freed.
+
+use chrono::{DateTime, Utc};
+use sqlx::postgres::Postgres;
+use sqlx::{Acquire, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    booking_policy, check_entry_capabilities, check_range, decode_capabilities, insert_entry,
+    system_capacity, AllocationError, Capabilities, EntryParams, SystemAllocation,
+};
+
+/// Identifies a request on the waitlist, being the allocation id of the entry it becomes once
+/// promoted.
+///
+/// A promotion is seen by subscribers to the changes of the allocations as the entry with this
+/// id being inserted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WaitlistId(pub Uuid);
+
+/// A request on the waitlist for an entry over `[start_time, end_time)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WaitlistRequest {
+    pub waitlist_id: WaitlistId,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub capabilities: Capabilities,
+    pub joined_at: DateTime<Utc>,
+}
+
+impl SystemAllocation {
+    /// Queue a request for an entry over `[start, end)` on the system, to be inserted once there
+    /// is room for it, e.g. as the timeslot it would take is full.
+    ///
+    /// Whenever an entry is removed, cancelled or completed, the requests overlapping its
+    /// timespan are promoted into entries, the earliest joined first, as long as each is accepted
+    /// as with [`SystemAllocation::insert_entry`] at the time. The promotion is part of the
+    /// transaction freeing the room. The booking policy of the system only applies as the request
+    /// joins, not as it is promoted, see [`SystemAllocation::set_booking_policy`].
+    /// A request no longer valid for the system, e.g. as a capability it requires has since been
+    /// removed, is left queued and skipped.
+    ///
+    /// Fails as [`SystemAllocation::insert_entry`] would for an invalid request, regardless of
+    /// any allocations in the way.
+    #[tracing::instrument(skip(self))]
+    pub async fn join_waitlist(
+        &self,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<WaitlistId, AllocationError> {
+        let mut tx = self.pool.begin().await?;
+        let waitlist_id = self
+            .join_waitlist_tx(&mut tx, system, start, end, capabilities)
+            .await?;
+        tx.commit().await?;
+
+        Ok(waitlist_id)
+    }
+
+    /// As [`SystemAllocation::join_waitlist`], within the transaction `tx`.
+    #[tracing::instrument(skip(self, tx))]
+    pub async fn join_waitlist_tx(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        system: Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        capabilities: Capabilities,
+    ) -> Result<WaitlistId, AllocationError> {
+        check_range(start, end)?;
+        check_entry_capabilities(capabilities)?;
+        system_capacity(&mut *tx, system, capabilities).await?;
+        booking_policy(&mut *tx, system)
+            .await?
+            .check(self.now(), start)?;
+
+        let waitlist_id = Uuid::new_v4();
+        sqlx::query!(
+            r#"
+        INSERT INTO waitlist(waitlist_id, system_id, start_time, end_time, capabilities)
+        VALUES ($1, $2, $3, $4, $5)
+            "#,
+            waitlist_id,
+            system,
+            start,
+            end,
+            capabilities.bits() as i64,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(WaitlistId(waitlist_id))
+    }
+
+    /// Withdraw a request from the waitlist.
+    ///
+    /// Fails with [`AllocationError::NotFound`] if the request is not on the waitlist, e.g. as it
+    /// has already been promoted.
+    #[tracing::instrument(skip(self))]
+    pub async fn leave_waitlist(&self, waitlist_id: WaitlistId) -> Result<(), AllocationError> {
+        let removed = sqlx::query!(
+            r#"
+        DELETE FROM waitlist WHERE waitlist_id = $1
+            "#,
+            waitlist_id.0,
+        )
+        .execute(&self.pool)
+        .await?;
+        if removed.rows_affected() == 0 {
+            return Err(AllocationError::NotFound(waitlist_id.0));
+        }
+
+        Ok(())
+    }
+
+    /// List the requests on the waitlist of the system overlapping the timespan `[start, end)`
+    /// of `range`, in the order they are promoted. All requests are listed when no range is
+    /// given.
+    #[tracing::instrument(skip(self))]
+    pub async fn list_waitlist(
+        &self,
+        system: Uuid,
+        range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> Result<Vec<WaitlistRequest>, AllocationError> {
+        let (start, end) = range.unzip();
+        let rows = sqlx::query!(
+            r#"
+        SELECT waitlist_id, start_time, end_time, capabilities, joined_at
+        FROM waitlist
+        WHERE system_id = $1
+            AND ($2::timestamptz IS NULL OR $2 < end_time)
+            AND ($3::timestamptz IS NULL OR $3 > start_time)
+        ORDER BY joined_at, waitlist_id
+            "#,
+            system,
+            start,
+            end,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                Ok(WaitlistRequest {
+                    waitlist_id: WaitlistId(row.waitlist_id),
+                    start_time: row.start_time,
+                    end_time: row.end_time,
+                    capabilities: decode_capabilities(row.capabilities)?,
+                    joined_at: row.joined_at,
+                })
+            })
+            .collect::<Result<_, sqlx::Error>>()?)
+    }
+}
+
+/// Promote the requests on the waitlist of `system` overlapping the timespan `[start, end)`,
+/// just freed, into entries, the earliest joined first.
+///
+/// `end` is `None` for the timespan of an open-ended entry.
+pub(crate) async fn promote_waitlist(
+    tx: &mut Transaction<'_, Postgres>,
+    system: Uuid,
+    start: DateTime<Utc>,
+    end: Option<DateTime<Utc>>,
+) -> Result<(), AllocationError> {
+    let rows = sqlx::query!(
+        r#"
+    SELECT waitlist_id, start_time, end_time, capabilities
+    FROM waitlist
+    WHERE system_id = $1
+        AND $2 < end_time
+        AND COALESCE($3, 'infinity'::timestamptz) > start_time
+    ORDER BY joined_at, waitlist_id
+    FOR UPDATE
+        "#,
+        system,
+        start,
+        end,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut promoted = 0;
+    for row in rows {
+        // Each attempt is made within a savepoint, as a rejected insert may abort the
+        // transaction.
+        let mut attempt = tx.begin().await?;
+        let inserted = insert_entry(
+            &mut attempt,
+            system,
+            row.waitlist_id,
+            row.start_time,
+            Some(row.end_time),
+            decode_capabilities(row.capabilities)?,
+            &EntryParams::new(),
+        )
+        .await;
+        match inserted {
+            Ok(()) => attempt.commit().await?,
+            Err(err) if err.is_conflict() => {
+                attempt.rollback().await?;
+                continue;
+            }
+            Err(AllocationError::Database(err)) => return Err(err.into()),
+            // The request is no longer valid for the system, e.g. as a capability it requires
+            // has been removed since it joined. It is left queued rather than failing the
+            // removal which freed the room, to be withdrawn by the caller.
+            Err(err) => {
+                attempt.rollback().await?;
+                tracing::warn!(%system, waitlist_id = %row.waitlist_id, %err, "not promoted");
+                continue;
+            }
+        }
+
+        sqlx::query!(
+            r#"
+        DELETE FROM waitlist WHERE waitlist_id = $1
+            "#,
+            row.waitlist_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        promoted += 1;
+    }
+    if promoted > 0 {
+        tracing::debug!(%system, promoted, "promoted from the waitlist");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,176 @@
+//! Run capability parsing and (de)serialization tests
+
+use allocation_poc::{Capabilities, ParseCapabilitiesError};
+
+#[test]
+fn parse_and_display() -> Result<(), anyhow::Error> {
+    assert_eq!(
+        "A,c".parse::<Capabilities>()?,
+        Capabilities::A | Capabilities::C
+    );
+    assert_eq!(
+        " b , H ".parse::<Capabilities>()?,
+        Capabilities::B | Capabilities::H
+    );
+    assert_eq!("".parse::<Capabilities>()?, Capabilities::empty());
+    assert_eq!("none".parse::<Capabilities>()?, Capabilities::empty());
+    assert_eq!(
+        "A,Z".parse::<Capabilities>(),
+        Err(ParseCapabilitiesError("Z".to_string()))
+    );
+    assert!("A,".parse::<Capabilities>().is_err());
+
+    assert_eq!((Capabilities::A | Capabilities::C).to_string(), "A|C");
+    assert_eq!(Capabilities::empty().to_string(), "NONE");
+    for capabilities in [Capabilities::empty(), Capabilities::B, Capabilities::all()] {
+        assert_eq!(
+            capabilities.to_string().parse::<Capabilities>()?,
+            capabilities
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn strict_bits() {
+    assert_eq!(
+        Capabilities::try_from_bits_strict(0b101),
+        Ok(Capabilities::A | Capabilities::C)
+    );
+    assert_eq!(
+        Capabilities::try_from_bits_strict(0x301),
+        Err(ParseCapabilitiesError("0x300".to_string()))
+    );
+}
+
+#[cfg(feature = "serde")]
+mod serialization {
+    use allocation_poc::{
+        Allocation, AllocationKind, Blocker, BoundaryPolicy, Capabilities, CapacityPeak,
+        ConflictInfo, Entry, EntryParams, Outage, SystemInfo,
+    };
+
+    use chrono::{Duration, TimeZone, Utc};
+    use uuid::Uuid;
+
+    #[test]
+    fn serialize_as_names() -> Result<(), anyhow::Error> {
+        let json = serde_json::to_string(&(Capabilities::A | Capabilities::C))?;
+        assert_eq!(json, r#"["A","C"]"#);
+
+        for capabilities in [Capabilities::empty(), Capabilities::all()] {
+            let json = serde_json::to_string(&capabilities)?;
+            assert_eq!(serde_json::from_str::<Capabilities>(&json)?, capabilities);
+        }
+        assert_eq!(serde_json::to_string(&Capabilities::empty())?, "[]");
+
+        let err = serde_json::from_str::<Capabilities>(r#"["A","Z"]"#).unwrap_err();
+        assert!(err.to_string().contains("unknown capability `Z`"));
+
+        // The raw bits are accepted as well, as long as all of them are known
+        assert_eq!(
+            serde_json::from_str::<Capabilities>("5")?,
+            Capabilities::A | Capabilities::C
+        );
+        assert!(serde_json::from_str::<Capabilities>("256").is_err());
+        assert!(serde_json::from_str::<Capabilities>("-1").is_err());
+        assert!(serde_json::from_str::<Capabilities>(r#""A""#).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_conflicts() -> Result<(), anyhow::Error> {
+        let start = Utc.with_ymd_and_hms(2023, 1, 1, 12, 0, 0).unwrap();
+        let conflict = ConflictInfo {
+            allocation: Allocation {
+                system_id: Uuid::nil(),
+                allocation_id: Uuid::nil(),
+                kind: AllocationKind::Entry,
+                planned: true,
+                start_time: start,
+                end_time: None,
+                capabilities: Capabilities::A | Capabilities::B,
+            },
+            blocker: Blocker::Capacity,
+            overlap_start: start,
+            overlap_end: None,
+            capabilities: Capabilities::B,
+            peak: Some(CapacityPeak {
+                at: start,
+                concurrent: 2,
+            }),
+            entry: Some(EntryParams::new().owner(Uuid::nil())),
+        };
+
+        let json = serde_json::to_value(&conflict)?;
+        assert_eq!(json["allocation"]["kind"], "entry");
+        assert_eq!(
+            json["allocation"]["capabilities"],
+            serde_json::json!(["A", "B"])
+        );
+        assert_eq!(json["blocker"], "Capacity");
+        assert_eq!(json["overlap_start"], "2023-01-01T12:00:00Z");
+        assert_eq!(json["overlap_end"], serde_json::Value::Null);
+        assert_eq!(json["peak"]["concurrent"], 2);
+        assert_eq!(json["entry"]["owner"], Uuid::nil().to_string());
+        assert_eq!(serde_json::from_value::<ConflictInfo>(json)?, conflict);
+
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip() -> Result<(), anyhow::Error> {
+        let start = Utc.with_ymd_and_hms(2023, 1, 1, 12, 0, 0).unwrap();
+        let entry = Entry {
+            allocation_id: Uuid::new_v4(),
+            start_time: start,
+            end_time: Some(start + Duration::hours(1)),
+            capabilities: Capabilities::C,
+            params: EntryParams::new()
+                .label("calibration")
+                .metadata(serde_json::json!({ "ticket": 42 })),
+        };
+        let json = serde_json::to_value(&entry)?;
+        assert_eq!(json["label"], "calibration");
+        assert_eq!(json["metadata"]["ticket"], 42);
+        assert_eq!(json["status"], "scheduled");
+        assert_eq!(serde_json::from_value::<Entry>(json)?, entry);
+
+        let outage = Outage::Unplanned {
+            allocation_id: Uuid::new_v4(),
+            start,
+            sliding_window: Duration::minutes(30),
+            resolved_at: None,
+            capabilities: Capabilities::all(),
+        };
+        let json = serde_json::to_value(&outage)?;
+        assert_eq!(json["Unplanned"]["sliding_window"], 1800);
+        assert_eq!(serde_json::from_value::<Outage>(json)?, outage);
+
+        for (default_sliding_window, timezone) in [
+            (None, None),
+            (Some(Duration::hours(2)), Some(chrono_tz::Europe::Oslo)),
+        ] {
+            let system = SystemInfo {
+                capacity: 3,
+                capabilities: Capabilities::A | Capabilities::B,
+                default_sliding_window,
+                min_notice: None,
+                max_horizon: default_sliding_window,
+                boundary_policy: BoundaryPolicy::Exclusive,
+                timezone,
+            };
+            let json = serde_json::to_string(&system)?;
+            assert_eq!(serde_json::from_str::<SystemInfo>(&json)?, system);
+        }
+
+        let policy = BoundaryPolicy::Gap(Duration::minutes(15));
+        let json = serde_json::to_value(policy)?;
+        assert_eq!(json["gap"], 900);
+        assert_eq!(serde_json::from_value::<BoundaryPolicy>(json)?, policy);
+
+        Ok(())
+    }
+}
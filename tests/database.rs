@@ -1,8 +1,17 @@
 //! Run database tests
 
-use allocation_poc::{Capabilities, SystemAllocation};
+use allocation_poc::test_util::SystemBuilder;
+use allocation_poc::{
+    AllocationEnd, AllocationError, AllocationKind, Blocker, BookingPolicy, BoundaryPolicy,
+    Capabilities, CapacityOccupancy, CapacitySegment, ConsistencyReport, Entry, EntryMove,
+    EntryParams, EntryStatus, FitResult, Inconsistency, MaintenanceSchedule, MirroredColumns,
+    OccurrenceConflict, Outage, Recurrence, RecurringEntry, ReschedulePolicy, SourceTable,
+    SystemAllocation, SystemInfo,
+};
 
-use chrono::{Duration, Utc};
+use std::sync::Arc;
+
+use chrono::{DateTime, Datelike, Duration, DurationRound, TimeZone, Utc, Weekday};
 use rand::Rng;
 use sqlx::PgPool;
 use uuid::Uuid;
@@ -88,7 +97,10 @@ async fn unplanned_outage(pool: PgPool) -> Result<(), anyhow::Error> {
             Capabilities::A,
         )
         .await;
-    assert!(result.is_err());
+    assert!(matches!(
+        result,
+        Err(AllocationError::WindowViolation { .. })
+    ));
 
     let result = planner
         .insert_entry(
@@ -124,7 +136,9 @@ async fn planned_capability_outage(pool: PgPool) -> Result<(), anyhow::Error> {
     let result = planner
         .insert_planned_capability_outage(system, Capabilities::A, start, end)
         .await;
-    assert!(result.is_err());
+    let entries = result.unwrap_err().conflicting_entries();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].capabilities, Capabilities::A);
 
     // Adding overlapping outage for capability B should succeed
     planner
@@ -154,6 +168,54 @@ async fn planned_capability_outage(pool: PgPool) -> Result<(), anyhow::Error> {
 #[sqlx::test]
 async fn entries_single_capacity_system(pool: PgPool) -> Result<(), anyhow::Error> {
     let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).declare().await?;
+
+    system.entry_at(0, 15, Capabilities::A).await?;
+    // Exact overlap is an error
+    assert!(system.entry_at(0, 15, Capabilities::A).await.is_err());
+    // Partial overlap is an error
+    assert!(system.entry_at(10, 15, Capabilities::A).await.is_err());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn entries_multiple_capacity_system(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let capacity = 6;
+    let system = SystemBuilder::new(&planner)
+        .capacity(capacity)
+        .declare()
+        .await?;
+
+    let mut rng = rand::thread_rng();
+    for _ in 0..capacity {
+        // Pick a random offset of 14 minutes
+        system
+            .entry_at(rng.gen_range(0..15), 15, Capabilities::A)
+            .await?;
+    }
+
+    // This entire range should now be filled
+    // - it should be impossible to fit anything around the end mark
+    let end = system.at(15);
+    let result = planner
+        .insert_entry(
+            system.id,
+            end - Duration::seconds(30),
+            end + Duration::minutes(2),
+            Capabilities::A,
+        )
+        .await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn entry_conflicts_are_reported(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
 
     let system = Uuid::new_v4();
     planner
@@ -161,63 +223,4527 @@ async fn entries_single_capacity_system(pool: PgPool) -> Result<(), anyhow::Erro
         .await?;
 
     let start = Utc::now();
-    let end = start + Duration::minutes(15);
-
+    let end = start + Duration::hours(1);
     planner
         .insert_entry(system, start, end, Capabilities::A)
         .await?;
-    // Exact overlap is an error
-    assert!(planner
-        .insert_entry(system, start, end, Capabilities::A)
-        .await
-        .is_err());
-    // Partial overlap is an error
-    assert!(planner
+    planner
+        .insert_planned_capability_outage(system, Capabilities::B, end, end + Duration::hours(1))
+        .await?;
+
+    // Exceeding capacity reports the entry occupying the slot
+    let result = planner
         .insert_entry(
             system,
-            start + Duration::minutes(10),
-            end + Duration::minutes(10),
-            Capabilities::A
+            start + Duration::minutes(30),
+            end - Duration::minutes(15),
+            Capabilities::A,
         )
-        .await
-        .is_err());
+        .await;
+    match result {
+        Err(AllocationError::CapacityExceeded { conflicts, .. }) => {
+            assert_eq!(conflicts.len(), 1);
+            assert_eq!(conflicts[0].allocation.kind, AllocationKind::Entry);
+            assert_eq!(conflicts[0].allocation.capabilities, Capabilities::A);
+        }
+        other => panic!("expected conflict, got {other:?}"),
+    }
+
+    // Overlapping both reports the entry and the outage sharing capabilities
+    let result = planner
+        .insert_entry(
+            system,
+            start + Duration::minutes(30),
+            end + Duration::minutes(30),
+            Capabilities::B,
+        )
+        .await;
+    match result {
+        Err(AllocationError::CapabilityOutage { conflicts }) => {
+            let kinds: Vec<_> = conflicts.iter().map(|c| c.allocation.kind).collect();
+            assert_eq!(kinds, [AllocationKind::Entry, AllocationKind::Capability]);
+        }
+        other => panic!("expected conflict, got {other:?}"),
+    }
 
     Ok(())
 }
 
 #[sqlx::test]
-async fn entries_multiple_capacity_system(pool: PgPool) -> Result<(), anyhow::Error> {
+async fn remove_entry(pool: PgPool) -> Result<(), anyhow::Error> {
     let planner = SystemAllocation::new(pool);
 
-    let capacity = 6;
     let system = Uuid::new_v4();
     planner
-        .declare_system(system, capacity, Capabilities::all())
+        .declare_system(system, 1, Capabilities::all())
         .await?;
 
     let start = Utc::now();
     let end = start + Duration::minutes(15);
+    let allocation_id = planner
+        .insert_entry(system, start, end, Capabilities::A)
+        .await?;
 
-    let mut rng = rand::thread_rng();
-    for _ in 0..capacity {
-        // Pick a random offset of 14 minutes
-        let offset = Duration::minutes(rng.gen_range(0..15));
+    planner.remove_entry(allocation_id).await?;
+
+    // The slot is free again
+    let reinserted = planner
+        .insert_entry(system, start, end, Capabilities::A)
+        .await?;
+
+    // Removing it twice is not found
+    let result = planner.remove_entry(allocation_id).await;
+    assert!(matches!(result, Err(AllocationError::NotFound(id)) if id == allocation_id));
+
+    // Once the new entry is removed as well, a planned outage over its timespan is accepted
+    assert!(planner
+        .insert_planned_outage(system, start, end)
+        .await
+        .is_err());
+    planner.remove_entry(reinserted).await?;
+    let outage_id = planner.insert_planned_outage(system, start, end).await?;
+
+    // Outages are not removed as entries
+    let result = planner.remove_entry(outage_id).await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::WrongKind {
+            kind: AllocationKind::Full,
+            ..
+        })
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn database_errors_are_typed(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let start = Utc::now();
+    let end = start + Duration::hours(1);
+
+    // Nothing can be allocated on an undeclared system
+    let unknown = Uuid::new_v4();
+    let result = planner
+        .insert_entry(unknown, start, end, Capabilities::A)
+        .await;
+    assert!(matches!(result, Err(AllocationError::SystemNotFound(id)) if id == unknown));
+    let result = planner.insert_planned_outage(unknown, start, end).await;
+    assert!(matches!(result, Err(AllocationError::SystemNotFound(id)) if id == unknown));
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    // A reversed timespan is rejected
+    let result = planner.insert_planned_outage(system, end, start).await;
+    assert!(matches!(result, Err(AllocationError::InvalidRange { .. })));
+
+    // Trigger rejections surface as conflicts
+    planner.insert_planned_outage(system, start, end).await?;
+    let result = planner.insert_planned_outage(system, start, end).await;
+    assert!(matches!(result, Err(AllocationError::Conflict { .. })));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn capability_outage_conflicts_are_reported(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 10, Capabilities::all())
+        .await?;
+
+    // Postgres only keeps microsecond precision
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let end = start + Duration::hours(6);
+    for capabilities in [Capabilities::A, Capabilities::B, Capabilities::C] {
         planner
-            .insert_entry(system, start + offset, end + offset, Capabilities::A)
+            .insert_entry(system, start, end, capabilities)
             .await?;
     }
 
-    // This entire range should now be filled
-    // - it should be impossible to fit anything around the end mark
+    // Only the entries sharing a capability with the outage are in the way
+    let result = planner
+        .insert_planned_capability_outage(
+            system,
+            Capabilities::A | Capabilities::B,
+            start + Duration::hours(1),
+            end,
+        )
+        .await;
+    match result {
+        Err(AllocationError::Conflict { conflicts }) => {
+            let mut capabilities: Vec<_> = conflicts.iter().map(|c| c.capabilities).collect();
+            capabilities.sort();
+            assert_eq!(capabilities, [Capabilities::A, Capabilities::B]);
+            assert!(conflicts
+                .iter()
+                .all(|c| c.allocation.kind == AllocationKind::Entry
+                    && c.allocation.start_time == start
+                    && c.allocation.end_time == Some(end)));
+        }
+        other => panic!("expected conflict, got {other:?}"),
+    }
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn failed_insert_leaves_no_orphans(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool.clone());
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let start = Utc::now();
+    planner
+        .insert_entry(
+            system,
+            start,
+            start + Duration::minutes(15),
+            Capabilities::A,
+        )
+        .await?;
+    planner
+        .insert_planned_outage(
+            system,
+            start + Duration::hours(1),
+            start + Duration::hours(2),
+        )
+        .await?;
+
+    // The unplanned outage passes its own window check, but its open-ended allocation overlaps
+    // the planned outage, failing the second insert.
+    let result = planner
+        .insert_unplanned_outage(system, start + Duration::minutes(30), Duration::minutes(15))
+        .await;
+    assert!(result.is_err());
+
+    // Entries and outages rejected by the allocation checks leave nothing behind either
     let result = planner
         .insert_entry(
             system,
-            end - Duration::seconds(30),
-            end + Duration::minutes(2),
+            start,
+            start + Duration::minutes(15),
             Capabilities::A,
         )
         .await;
     assert!(result.is_err());
+    let result = planner
+        .insert_planned_outage(system, start, start + Duration::hours(3))
+        .await;
+    assert!(result.is_err());
+
+    for (table, expected) in [
+        ("entries", 1),
+        ("planned", 1),
+        ("unplanned", 0),
+        ("allocations", 2),
+    ] {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT count(*) FROM {table}"))
+            .fetch_one(&pool)
+            .await?;
+        assert_eq!(count, expected, "rows in {table}");
+    }
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn list_entries(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 2, Capabilities::all())
+        .await?;
+
+    let start = Utc::now();
+    let slot = Duration::minutes(15);
+    planner
+        .insert_entry(system, start + slot, start + slot * 2, Capabilities::B)
+        .await?;
+    planner
+        .insert_entry(system, start, start + slot, Capabilities::A)
+        .await?;
+    planner
+        .insert_entry(system, start + slot * 2, start + slot * 3, Capabilities::C)
+        .await?;
+
+    // Ordered by start time
+    let entries = planner
+        .list_entries(system, Some((start, start + slot * 3)))
+        .await?;
+    let capabilities: Vec<_> = entries.iter().map(|e| e.capabilities).collect();
+    assert_eq!(
+        capabilities,
+        [Capabilities::A, Capabilities::B, Capabilities::C]
+    );
+
+    // Entries only touching the boundaries of the range are excluded
+    let entries = planner
+        .list_entries(system, Some((start + slot, start + slot * 2)))
+        .await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].capabilities, Capabilities::B);
+
+    // Any overlap with the range is included, not just containment
+    let entries = planner
+        .list_entries(
+            system,
+            Some((start + slot / 2, start + slot * 2 + slot / 2)),
+        )
+        .await?;
+    assert_eq!(entries.len(), 3);
+
+    // Without a range, everything on the system is listed
+    let entries = planner.list_entries(system, None).await?;
+    assert_eq!(entries.len(), 3);
+    let entries = planner.list_entries(Uuid::new_v4(), None).await?;
+    assert!(entries.is_empty());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn can_insert_entry(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let start = Utc::now();
+    let end = start + Duration::minutes(15);
+
+    // Checking does not occupy the slot
+    assert!(
+        planner
+            .can_insert_entry(system, start, end, Capabilities::A)
+            .await?
+    );
+    assert!(
+        planner
+            .can_insert_entry(system, start, end, Capabilities::A)
+            .await?
+    );
+    planner
+        .insert_entry(system, start, end, Capabilities::A)
+        .await?;
+    assert!(
+        !planner
+            .can_insert_entry(system, start, end, Capabilities::A)
+            .await?
+    );
+
+    // Unplanned outages block everything from their start, also outside the window
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+    let window = Duration::hours(1);
+    planner
+        .insert_unplanned_outage(system, start, window)
+        .await?;
+    assert!(
+        !planner
+            .can_insert_entry(
+                system,
+                start + window * 2,
+                end + window * 2,
+                Capabilities::A
+            )
+            .await?
+    );
+
+    // Anything but conflicts is still an error
+    assert!(planner
+        .can_insert_entry(Uuid::new_v4(), start, end, Capabilities::A)
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn list_outages(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let hour = Duration::hours(1);
+    planner
+        .insert_planned_capability_outage(system, Capabilities::C, start + hour, start + hour * 2)
+        .await?;
+    planner
+        .insert_planned_outage(system, start, start + hour)
+        .await?;
+    planner
+        .insert_unplanned_outage(system, start + hour * 3, hour)
+        .await?;
+
+    let outages = planner
+        .list_outages(system, start, start + hour * 4)
+        .await?;
+    assert_eq!(outages.len(), 3);
+    assert!(
+        matches!(outages[0], Outage::Planned { start: s, end, .. } if s == start && end == start + hour)
+    );
+    assert!(matches!(
+        outages[1],
+        Outage::Capability {
+            capabilities: Capabilities::C,
+            ..
+        }
+    ));
+    assert!(matches!(
+        outages[2],
+        Outage::Unplanned { sliding_window, .. } if sliding_window == hour
+    ));
+    assert_eq!(
+        outages.iter().map(|o| o.capabilities()).collect::<Vec<_>>(),
+        vec![Capabilities::all(), Capabilities::C, Capabilities::all()]
+    );
+    assert_eq!(outages[1].end(), AllocationEnd::At(start + hour * 2));
+    assert_eq!(outages[2].start(), start + hour * 3);
+    assert_eq!(outages[2].end(), AllocationEnd::Never);
+
+    // The unplanned outage is still reported long after it started
+    let outages = planner
+        .list_outages(system, start + hour * 24, start + hour * 48)
+        .await?;
+    assert_eq!(outages.len(), 1);
+    assert!(matches!(outages[0], Outage::Unplanned { .. }));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn sweep_unplanned_windows(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let now = Utc::now();
+    let window = Duration::hours(1);
+    let slot = Duration::minutes(15);
+
+    // Entries before the outage, soon to be within the window, and far ahead of it.
+    planner
+        .insert_entry(
+            system,
+            now - window * 3,
+            now - window * 3 + slot,
+            Capabilities::A,
+        )
+        .await?;
+    planner
+        .insert_entry(system, now, now + slot, Capabilities::A)
+        .await?;
+    planner
+        .insert_entry(
+            system,
+            now + window * 3,
+            now + window * 3 + slot,
+            Capabilities::A,
+        )
+        .await?;
+
+    // The outage started two hours ago, so its window has since slid over the second entry.
+    planner
+        .insert_unplanned_outage(system, now - window * 2, window)
+        .await?;
+
+    let removed = planner.sweep_unplanned_windows(system).await?;
+    assert_eq!(removed.len(), 1);
+
+    let entries = planner.list_entries(system, None).await?;
+    assert_eq!(entries.len(), 2);
+    assert!(entries.iter().all(|e| !removed.contains(&e.allocation_id)));
+
+    // Nothing left to clear
+    assert!(planner.sweep_unplanned_windows(system).await?.is_empty());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn list_evictions(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).declare().await?;
+    let since = Utc::now();
+
+    let entry = system.entry_at(60, 15, Capabilities::A).await?;
+    planner
+        .insert_unplanned_outage(system.id, system.at(-60), Duration::hours(1))
+        .await?;
+    assert!(planner.list_evictions(system.id, since).await?.is_empty());
+
+    // The window has slid over the entry since the outage started
+    let removed = planner.sweep_unplanned_windows(system.id).await?;
+    assert_eq!(removed, [entry]);
+
+    let evictions = planner.list_evictions(system.id, since).await?;
+    assert_eq!(evictions.len(), 1);
+    assert_eq!(evictions[0].allocation_id, entry);
+    assert_eq!(evictions[0].start_time, system.at(60));
+    assert_eq!(evictions[0].end_time, Some(system.at(75)));
+    assert_eq!(evictions[0].capabilities, Capabilities::A);
+    assert!(evictions[0].evicted_at >= since);
+
+    assert!(planner
+        .list_evictions(system.id, Utc::now() + Duration::minutes(1))
+        .await?
+        .is_empty());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn entry_params(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).declare().await?;
+    let other = SystemBuilder::new(&planner).declare().await?;
+
+    let owner = Uuid::new_v4();
+    let params = EntryParams::new()
+        .owner(owner)
+        .label("calibration")
+        .metadata(serde_json::json!({ "ticket": 42 }));
+    let entry = planner
+        .insert_entry_with_params(
+            system.id,
+            system.at(60),
+            system.at(75),
+            Capabilities::A,
+            params.clone(),
+        )
+        .await?;
+    let elsewhere = planner
+        .insert_entry_with_params(
+            other.id,
+            other.at(120),
+            other.at(180),
+            Capabilities::B,
+            EntryParams::new().owner(owner),
+        )
+        .await?;
+    other.entry_at(0, 60, Capabilities::A).await?;
+
+    let entries = planner.list_entries(system.id, None).await?;
+    assert_eq!(entries[0].params, params);
+    let entries = planner.list_entries(other.id, None).await?;
+    assert_eq!(entries[0].params, EntryParams::default());
+
+    // Across systems, filtered by the range
+    let owned: Vec<_> = planner
+        .list_entries_by_owner(owner, None)
+        .await?
+        .into_iter()
+        .map(|(system, entry)| (system, entry.allocation_id))
+        .collect();
+    assert_eq!(owned, [(system.id, entry), (other.id, elsewhere)]);
+    let owned = planner
+        .list_entries_by_owner(owner, Some((system.at(90), system.at(150))))
+        .await?;
+    assert_eq!(owned.len(), 1);
+    assert_eq!(owned[0].1.allocation_id, elsewhere);
+    assert!(planner
+        .list_entries_by_owner(Uuid::new_v4(), None)
+        .await?
+        .is_empty());
+
+    // Whom an entry in the way belongs to is reported
+    let result = planner
+        .insert_entry(system.id, system.at(60), system.at(75), Capabilities::B)
+        .await;
+    let err = result.unwrap_err();
+    assert_eq!(err.conflicting_entries()[0].params, params);
+
+    // As is whom an evicted entry belonged to
+    planner
+        .insert_unplanned_outage(system.id, system.at(-60), Duration::hours(1))
+        .await?;
+    planner.sweep_unplanned_windows(system.id).await?;
+    let evictions = planner.list_evictions(system.id, system.now).await?;
+    assert_eq!(evictions[0].allocation_id, entry);
+    assert_eq!(evictions[0].params, params);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn get_availability(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 2, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let slot = Duration::minutes(15);
+    planner
+        .insert_entry(system, start, start + slot * 2, Capabilities::A)
+        .await?;
+    planner
+        .insert_entry(system, start + slot, start + slot * 3, Capabilities::A)
+        .await?;
+    planner
+        .insert_planned_capability_outage(
+            system,
+            Capabilities::B,
+            start + slot * 4,
+            start + slot * 5,
+        )
+        .await?;
+
+    let range = (start - slot, start + slot * 6);
+    let availability = planner
+        .get_availability(system, range.0, range.1, Capabilities::A)
+        .await?;
+    let free: Vec<_> = availability.iter().map(|(_, _, free)| *free).collect();
+    assert_eq!(free, [2, 1, 0, 1, 2, 2, 2]);
+    assert_eq!(availability[0].0, range.0);
+    assert_eq!(availability[6].1, range.1);
+    assert!(availability.windows(2).all(|w| w[0].1 == w[1].0));
+
+    // The capability outage only affects entries requiring it
+    let availability = planner
+        .get_availability(system, range.0, range.1, Capabilities::B)
+        .await?;
+    let free: Vec<_> = availability.iter().map(|(_, _, free)| *free).collect();
+    assert_eq!(free, [2, 1, 0, 1, 2, 0, 2]);
+
+    // The tightest of the capacities an entry is limited by applies
+    let system = Uuid::new_v4();
+    planner
+        .declare_system_with_capacities(system, &[(Capabilities::A, 6), (Capabilities::B, 1)])
+        .await?;
+    planner
+        .insert_entry(system, start, start + slot, Capabilities::B)
+        .await?;
+    for (capabilities, free) in [
+        (Capabilities::B, 0),
+        (Capabilities::A, 6),
+        (Capabilities::A | Capabilities::B, 0),
+    ] {
+        let availability = planner
+            .get_availability(system, start, start + slot, capabilities)
+            .await?;
+        assert_eq!(availability, [(start, start + slot, free)]);
+    }
+    let result = planner
+        .get_availability(system, start, start + slot, Capabilities::H)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::UnsupportedCapabilities { .. })
+    ));
+
+    // An empty or reversed timespan is rejected
+    for end in [start, start - slot] {
+        let result = planner
+            .get_availability(system, start, end, Capabilities::A)
+            .await;
+        assert!(matches!(result, Err(AllocationError::InvalidRange { .. })));
+    }
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn update_entry(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let slot = Duration::minutes(15);
+    let hour = Duration::hours(1);
+    let allocation_id = planner
+        .insert_entry(system, start, start + slot, Capabilities::A)
+        .await?;
+    planner
+        .insert_entry(system, start + hour, start + hour + slot, Capabilities::A)
+        .await?;
+
+    // Shifting within its own footprint does not conflict with itself
+    planner
+        .update_entry(
+            allocation_id,
+            start + Duration::minutes(5),
+            start + slot + Duration::minutes(5),
+            Capabilities::A | Capabilities::B,
+        )
+        .await?;
+    let entries = planner.list_entries(system, None).await?;
+    assert_eq!(entries[0].allocation_id, allocation_id);
+    assert_eq!(entries[0].start_time, start + Duration::minutes(5));
+    assert_eq!(entries[0].capabilities, Capabilities::A | Capabilities::B);
+
+    // Moving onto the other entry exceeds capacity
+    let result = planner
+        .update_entry(
+            allocation_id,
+            start + hour,
+            start + hour + slot,
+            Capabilities::A,
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::CapacityExceeded { .. })
+    ));
+
+    // Moving into the window of an unplanned outage is rejected, beyond it is allowed
+    planner
+        .insert_unplanned_outage(system, start + hour * 2, hour)
+        .await?;
+    let result = planner
+        .update_entry(
+            allocation_id,
+            start + hour * 2 + slot,
+            start + hour * 2 + slot * 2,
+            Capabilities::A,
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::WindowViolation { .. })
+    ));
+    planner
+        .update_entry(
+            allocation_id,
+            start + hour * 4,
+            start + hour * 4 + slot,
+            Capabilities::A,
+        )
+        .await?;
+
+    // Once within the window, the entry is due to be removed and can no longer be modified,
+    // not even to move it back out.
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+    planner
+        .insert_entry(system, start, start + slot, Capabilities::A)
+        .await?;
+    let allocation_id = planner.list_entries(system, None).await?[0].allocation_id;
+    planner
+        .insert_unplanned_outage(system, start - hour * 2, hour)
+        .await?;
+    let result = planner
+        .update_entry(
+            allocation_id,
+            start + hour * 4,
+            start + hour * 4 + slot,
+            Capabilities::A,
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::WindowViolation { .. })
+    ));
+
+    // Only entries can be updated
+    let result = planner
+        .update_entry(Uuid::new_v4(), start, start + slot, Capabilities::A)
+        .await;
+    assert!(matches!(result, Err(AllocationError::NotFound(_))));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn capability_capacities(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system_with_capacities(system, &[(Capabilities::A, 6), (Capabilities::B, 1)])
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let end = start + Duration::minutes(15);
+
+    // Only a single entry of capability B at a time
+    planner
+        .insert_entry(system, start, end, Capabilities::B)
+        .await?;
+    let result = planner
+        .insert_entry(system, start, end, Capabilities::B)
+        .await;
+    match result {
+        Err(AllocationError::CapacityExceeded { occupancy, .. }) => {
+            let expected = CapacityOccupancy {
+                occupied: 1,
+                capacity: 1,
+                capability: Some(Capabilities::B),
+                peak_start: start,
+                peak_end: Some(end),
+            };
+            assert_eq!(occupancy, Some(expected));
+        }
+        other => panic!("expected capacity exceeded, got {other:?}"),
+    }
+
+    // An entry of several capabilities needs room in each of them, naming the one without
+    let result = planner
+        .insert_entry(system, start, end, Capabilities::A | Capabilities::B)
+        .await;
+    match result {
+        Err(AllocationError::CapacityExceeded {
+            occupancy: Some(occupancy),
+            ..
+        }) => assert_eq!(occupancy.capability, Some(Capabilities::B)),
+        other => panic!("expected capacity exceeded, got {other:?}"),
+    }
+    assert_eq!(
+        planner
+            .list_entries(system, Some((start, end)))
+            .await?
+            .len(),
+        1
+    );
+
+    // While there is room for six of capability A alongside it
+    for _ in 0..6 {
+        planner
+            .insert_entry(system, start, end, Capabilities::A)
+            .await?;
+    }
+    let result = planner
+        .insert_entry(system, start, end, Capabilities::A)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::CapacityExceeded { .. })
+    ));
+
+    // Capacities are given per capability
+    let result = planner
+        .declare_system_with_capacities(Uuid::new_v4(), &[(Capabilities::A | Capabilities::B, 2)])
+        .await;
+    assert!(matches!(result, Err(AllocationError::Validation(_))));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn run_window_sweep(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = Arc::new(SystemAllocation::new(pool));
+
+    let now = Utc::now();
+    let window = Duration::hours(1);
+    let slot = Duration::minutes(15);
+
+    let mut systems = Vec::new();
+    for _ in 0..2 {
+        let system = Uuid::new_v4();
+        planner
+            .declare_system(system, 1, Capabilities::all())
+            .await?;
+        // One entry that will be within the window, and one far ahead of it
+        planner
+            .insert_entry(system, now, now + slot, Capabilities::A)
+            .await?;
+        planner
+            .insert_entry(
+                system,
+                now + window * 3,
+                now + window * 3 + slot,
+                Capabilities::A,
+            )
+            .await?;
+        // Started before the window covered the first entry, which it has since slid over
+        planner
+            .insert_unplanned_outage(system, now - window * 2, window)
+            .await?;
+        systems.push(system);
+    }
+
+    let report = planner.run_window_sweep().await?;
+    assert_eq!(report.removed.len(), 2);
+    for system in &systems {
+        let (_, removed) = report
+            .removed
+            .iter()
+            .find(|(s, _)| s == system)
+            .expect("removed entry for each system");
+        assert_eq!(removed.start_time.timestamp(), now.timestamp());
+
+        let entries = planner.list_entries(*system, None).await?;
+        assert_eq!(entries.len(), 1);
+    }
+
+    // The spawned task sweeps continuously
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+    planner
+        .insert_entry(system, now, now + slot, Capabilities::A)
+        .await?;
+    planner
+        .insert_unplanned_outage(system, now - window * 2, window)
+        .await?;
+
+    let task = planner
+        .clone()
+        .spawn_sweep_task(std::time::Duration::from_millis(10));
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    task.abort();
+    assert!(planner.list_entries(system, None).await?.is_empty());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn resolve_unplanned_outage(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let window = Duration::hours(24);
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let slot = Duration::minutes(15);
+
+    let outage = planner
+        .insert_unplanned_outage(system, start, window)
+        .await?;
+
+    // Entries are denied until the outage has been resolved.
+    let result = planner
+        .insert_entry(
+            system,
+            start + window * 2,
+            start + window * 2 + slot,
+            Capabilities::A,
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::WindowViolation { .. })
+    ));
+
+    // The outage cannot end before it started.
+    let result = planner.resolve_unplanned_outage(outage, start - slot).await;
+    assert!(matches!(result, Err(AllocationError::InvalidRange { .. })));
+
+    let end = start + Duration::hours(2);
+    planner.resolve_unplanned_outage(outage, end).await?;
+
+    // Entries after the outage has ended are allowed, entries during it are not.
+    planner
+        .insert_entry(system, end, end + slot, Capabilities::A)
+        .await?;
+    let result = planner
+        .insert_entry(system, end - slot, end, Capabilities::B)
+        .await;
+    assert!(result.is_err());
+
+    let outages = planner.list_outages(system, start, end).await?;
+    assert_eq!(outages.len(), 1);
+    assert!(matches!(
+        outages[0],
+        Outage::Unplanned { resolved_at: Some(resolved_at), .. } if resolved_at == end
+    ));
+
+    // Already resolved outages, and allocations of another kind, are rejected.
+    let result = planner.resolve_unplanned_outage(outage, end + slot).await;
+    assert!(matches!(result, Err(AllocationError::Validation(_))));
+    let result = planner.resolve_unplanned_outage(Uuid::new_v4(), end).await;
+    assert!(matches!(result, Err(AllocationError::NotFound(_))));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn check_entry_fits(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let hour = Duration::hours(1);
+    planner
+        .insert_entry(system, start, start + hour, Capabilities::A)
+        .await?;
+    planner
+        .insert_planned_capability_outage(system, Capabilities::B, start + hour, start + hour * 2)
+        .await?;
+    planner
+        .insert_planned_outage(system, start + hour * 2, start + hour * 3)
+        .await?;
+    planner
+        .insert_unplanned_outage(system, start + hour * 4, hour)
+        .await?;
+
+    let blockers = |result| match result {
+        FitResult::Fits => Vec::new(),
+        FitResult::Conflicts(conflicts) => conflicts.into_iter().map(|c| c.blocker).collect(),
+    };
+
+    let result = planner
+        .check_entry_fits(system, start, start + hour, Capabilities::C)
+        .await?;
+    assert_eq!(blockers(result), vec![Blocker::Capacity]);
+    let result = planner
+        .check_entry_fits(system, start + hour, start + hour * 2, Capabilities::B)
+        .await?;
+    assert_eq!(blockers(result), vec![Blocker::CapabilityOutage]);
+    let result = planner
+        .check_entry_fits(system, start + hour, start + hour * 2, Capabilities::A)
+        .await?;
+    assert_eq!(result, FitResult::Fits);
+    let result = planner
+        .check_entry_fits(system, start + hour * 2, start + hour * 3, Capabilities::A)
+        .await?;
+    assert_eq!(blockers(result), vec![Blocker::PlannedOutage]);
+    let result = planner
+        .check_entry_fits(system, start + hour * 5, start + hour * 6, Capabilities::A)
+        .await?;
+    assert_eq!(blockers(result), vec![Blocker::UnplannedWindow]);
+
+    // Nothing is written by checking.
+    assert_eq!(planner.list_entries(system, None).await?.len(), 1);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn overlaps_outage(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).declare().await?;
+
+    let hour = Duration::hours(1);
+    system.entry_at(0, 60, Capabilities::A).await?;
+    let capability_outage = planner
+        .insert_planned_capability_outage(system.id, Capabilities::B, system.at(60), system.at(120))
+        .await?;
+    let unplanned = planner
+        .insert_unplanned_outage(system.id, system.at(180), hour)
+        .await?;
+
+    // A full system is not blocked by an outage
+    let outage = planner
+        .overlaps_outage(system.id, system.at(0), system.at(60), Capabilities::C)
+        .await?;
+    assert_eq!(outage, None);
+
+    // Only outages sharing the capabilities are in the way
+    let outage = planner
+        .overlaps_outage(system.id, system.at(60), system.at(120), Capabilities::A)
+        .await?;
+    assert_eq!(outage, None);
+    let outage = planner
+        .overlaps_outage(system.id, system.at(0), system.at(120), Capabilities::B)
+        .await?;
+    assert_eq!(outage.map(|o| o.allocation_id()), Some(capability_outage));
+
+    // The first of several outages in the way
+    let outage = planner
+        .overlaps_outage(
+            system.id,
+            system.at(90),
+            system.at(240),
+            Capabilities::all(),
+        )
+        .await?;
+    assert_eq!(outage.map(|o| o.allocation_id()), Some(capability_outage));
+    let outage = planner
+        .overlaps_outage(system.id, system.at(300), system.at(360), Capabilities::A)
+        .await?;
+    assert!(
+        matches!(outage, Some(Outage::Unplanned { allocation_id, .. }) if allocation_id == unplanned)
+    );
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn undeclared_capabilities_are_rejected(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner.declare_system(system, 1, Capabilities::A).await?;
+
+    let start = Utc::now();
+    let end = start + Duration::hours(1);
+    let is_unsupported = |result, requested| {
+        matches!(
+            result,
+            Err(AllocationError::UnsupportedCapabilities {
+                requested: r,
+                supported: Capabilities::A,
+            }) if r == requested
+        )
+    };
+
+    for requested in [Capabilities::B, Capabilities::A | Capabilities::B] {
+        let result = planner.insert_entry(system, start, end, requested).await;
+        assert!(is_unsupported(result, requested));
+        let result = planner
+            .insert_planned_capability_outage(system, requested, start, end)
+            .await;
+        assert!(is_unsupported(result, requested));
+    }
+    let result = planner
+        .insert_planned_capability_outage(Uuid::new_v4(), Capabilities::C, start, end)
+        .await;
+    assert!(matches!(result, Err(AllocationError::SystemNotFound(_))));
+
+    // Entries must require some capability
+    let result = planner
+        .insert_entry(system, start, end, Capabilities::empty())
+        .await;
+    assert!(matches!(result, Err(AllocationError::Validation(_))));
+
+    planner
+        .insert_entry(system, start, end, Capabilities::A)
+        .await?;
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn find_next_free_slot(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let day = Utc::now().duration_trunc(Duration::days(1))? + Duration::days(1);
+    let hour = Duration::hours(1);
+
+    // A fully booked day, with a gap of exactly two hours.
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+    planner
+        .insert_entry(system, day, day + hour * 4, Capabilities::A)
+        .await?;
+    planner
+        .insert_entry(system, day + hour * 6, day + hour * 24, Capabilities::A)
+        .await?;
+
+    let slot = planner
+        .find_next_free_slot(system, day, hour * 2, Capabilities::B)
+        .await?;
+    assert_eq!(slot, Some(day + hour * 4));
+    let slot = planner
+        .find_next_free_slot(system, day, hour * 3, Capabilities::B)
+        .await?;
+    assert_eq!(slot, Some(day + hour * 24));
+
+    // The gap is only free for capabilities not in an outage.
+    planner
+        .insert_planned_capability_outage(system, Capabilities::B, day + hour * 4, day + hour * 5)
+        .await?;
+    let slot = planner
+        .find_next_free_slot(system, day, hour * 2, Capabilities::B)
+        .await?;
+    assert_eq!(slot, Some(day + hour * 24));
+    let slot = planner
+        .find_next_free_slot(system, day, hour * 2, Capabilities::A)
+        .await?;
+    assert_eq!(slot, Some(day + hour * 4));
+
+    // A slot is free as long as there is capacity left.
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 2, Capabilities::all())
+        .await?;
+    for _ in 0..2 {
+        planner
+            .insert_entry(system, day, day + hour * 4, Capabilities::A)
+            .await?;
+    }
+    planner
+        .insert_entry(system, day + hour * 4, day + hour * 24, Capabilities::A)
+        .await?;
+    let slot = planner
+        .find_next_free_slot(system, day, hour * 2, Capabilities::A)
+        .await?;
+    assert_eq!(slot, Some(day + hour * 4));
+
+    // Nothing is free after an unresolved unplanned outage.
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+    planner
+        .insert_unplanned_outage(system, day + hour, hour)
+        .await?;
+    let slot = planner
+        .find_next_free_slot(system, day, hour, Capabilities::A)
+        .await?;
+    assert_eq!(slot, Some(day));
+    let slot = planner
+        .find_next_free_slot(system, day, hour * 2, Capabilities::A)
+        .await?;
+    assert_eq!(slot, None);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn invalid_ranges_are_rejected(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let start = Utc::now();
+    let end = start + Duration::hours(1);
+    fn is_invalid_range<T>(
+        result: Result<T, AllocationError>,
+        s: DateTime<Utc>,
+        e: DateTime<Utc>,
+    ) -> bool {
+        matches!(result, Err(AllocationError::InvalidRange { start, end }) if start == s && end == e)
+    }
+
+    // Reversed and zero-length timespans are both rejected
+    for (s, e) in [(end, start), (start, start)] {
+        let result = planner.insert_entry(system, s, e, Capabilities::A).await;
+        assert!(is_invalid_range(result, s, e));
+        let result = planner.insert_planned_outage(system, s, e).await;
+        assert!(is_invalid_range(result, s, e));
+        let result = planner
+            .insert_planned_capability_outage(system, Capabilities::A, s, e)
+            .await;
+        assert!(is_invalid_range(result, s, e));
+        let result = planner
+            .check_entry_fits(system, s, e, Capabilities::A)
+            .await;
+        assert!(is_invalid_range(result, s, e));
+    }
+
+    let entry = planner
+        .insert_entry(system, start, end, Capabilities::A)
+        .await?;
+    let result = planner
+        .update_entry(entry, end, start, Capabilities::A)
+        .await;
+    assert!(is_invalid_range(result, end, start));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn invalid_sliding_windows_are_rejected(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    for window in [-Duration::hours(1), Duration::zero(), Duration::max_value()] {
+        let result = planner.insert_unplanned_outage(system, start, window).await;
+        assert!(matches!(
+            result,
+            Err(AllocationError::InvalidSlidingWindow(w)) if w == window
+        ));
+    }
+
+    // Windows are rounded up to whole minutes, even for nanosecond precision
+    let window = Duration::hours(1) + Duration::nanoseconds(1500);
+    planner
+        .insert_unplanned_outage(system, start, window)
+        .await?;
+    let outages = planner
+        .list_outages(system, start, start + Duration::hours(1))
+        .await?;
+    assert!(matches!(
+        outages[..],
+        [Outage::Unplanned { sliding_window, .. }]
+            if sliding_window == Duration::minutes(61)
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn insert_unplanned_outage_forced(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let hour = Duration::hours(1);
+    let slot = Duration::minutes(15);
+
+    // Entries before the outage, within its window, and beyond it.
+    planner
+        .insert_entry(system, start - slot, start, Capabilities::A)
+        .await?;
+    planner
+        .insert_entry(system, start + slot, start + slot * 2, Capabilities::B)
+        .await?;
+    planner
+        .insert_entry(
+            system,
+            start + hour * 2,
+            start + hour * 2 + slot,
+            Capabilities::A,
+        )
+        .await?;
+    planner
+        .insert_planned_outage(system, start + hour * 3, start + hour * 4)
+        .await?;
+
+    // A failed insert removes nothing.
+    let result = planner
+        .insert_unplanned_outage_forced(system, start, hour)
+        .await;
+    assert!(matches!(result, Err(AllocationError::Conflict { .. })));
+    assert_eq!(planner.list_entries(system, None).await?.len(), 3);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+    for (s, e) in [
+        (start - slot, start),
+        (start + slot, start + slot * 2),
+        (start + hour * 2, start + hour * 2 + slot),
+    ] {
+        planner.insert_entry(system, s, e, Capabilities::A).await?;
+    }
+    let removed = planner
+        .insert_unplanned_outage_forced(system, start, hour)
+        .await?;
+    assert_eq!(removed.len(), 1);
+    assert_eq!(removed[0].start_time, start + slot);
+
+    let remaining = planner.list_entries(system, None).await?;
+    assert_eq!(remaining.len(), 2);
+    assert!(remaining
+        .iter()
+        .all(|e| e.allocation_id != removed[0].allocation_id));
+    assert_eq!(
+        planner
+            .list_outages(system, start, start + hour)
+            .await?
+            .len(),
+        1
+    );
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn pinned_entries(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).capacity(2).declare().await?;
+
+    let pinned = planner
+        .insert_entry_with_params(
+            system.id,
+            system.at(10),
+            system.at(25),
+            Capabilities::A,
+            EntryParams::new().pinned(true),
+        )
+        .await?;
+    let unpinned = system.entry_at(10, 15, Capabilities::B).await?;
+
+    // A forced outage fails on the pinned entry, removing neither
+    let result = planner
+        .insert_unplanned_outage_forced(system.id, system.at(0), Duration::hours(1))
+        .await;
+    match result {
+        Err(AllocationError::Conflict { conflicts }) => {
+            let ids: Vec<_> = conflicts
+                .iter()
+                .map(|c| c.allocation.allocation_id)
+                .collect();
+            assert_eq!(ids, [pinned]);
+            assert!(conflicts[0].entry.as_ref().is_some_and(|e| e.pinned));
+        }
+        other => panic!("expected conflict, got {other:?}"),
+    }
+    assert_eq!(planner.list_entries(system.id, None).await?.len(), 2);
+
+    // The window has since slid over both entries, only the unpinned one is removed
+    let outage = planner
+        .insert_unplanned_outage(system.id, system.at(-120), Duration::hours(1))
+        .await?;
+    assert_eq!(
+        planner.sweep_unplanned_windows(system.id).await?,
+        [unpinned]
+    );
+    let report = planner.run_window_sweep().await?;
+    assert!(report.removed.is_empty());
+    assert_eq!(report.pinned.len(), 1);
+    assert_eq!(report.pinned[0].0, system.id);
+    assert_eq!(report.pinned[0].1.allocation_id, pinned);
+
+    let result = planner.set_pinned(outage, true).await;
+    assert!(matches!(result, Err(AllocationError::WrongKind { .. })));
+    let result = planner.set_pinned(Uuid::new_v4(), true).await;
+    assert!(matches!(result, Err(AllocationError::NotFound(_))));
+
+    // Once unpinned, it is removed as any other
+    planner.set_pinned(pinned, false).await?;
+    assert_eq!(planner.sweep_unplanned_windows(system.id).await?, [pinned]);
+    assert!(planner.run_window_sweep().await?.pinned.is_empty());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn find_next_available_slot(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let day = Utc::now().duration_trunc(Duration::days(1))? + Duration::days(1);
+    let hour = Duration::hours(1);
+    let half_hour = Duration::minutes(30);
+    planner
+        .insert_entry(system, day, day + hour, Capabilities::A)
+        .await?;
+    planner
+        .insert_planned_capability_outage(system, Capabilities::A, day + hour, day + hour * 3)
+        .await?;
+
+    // Skipping over both the booked hour and the outage of the capability
+    let slot = planner
+        .find_next_available_slot(system, day, half_hour, Capabilities::A, Duration::days(1))
+        .await?;
+    assert_eq!(slot, Some(day + hour * 3));
+    let slot = planner
+        .find_next_available_slot(system, day, half_hour, Capabilities::B, Duration::days(1))
+        .await?;
+    assert_eq!(slot, Some(day + hour));
+
+    // The slot must fit entirely within the horizon
+    let slot = planner
+        .find_next_available_slot(system, day, half_hour, Capabilities::A, hour * 3)
+        .await?;
+    assert_eq!(slot, None);
+    let slot = planner
+        .find_next_available_slot(
+            system,
+            day,
+            half_hour,
+            Capabilities::A,
+            hour * 3 + half_hour,
+        )
+        .await?;
+    assert_eq!(slot, Some(day + hour * 3));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn insert_entries(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let slot = Duration::minutes(15);
+    let batch: Vec<_> = (0..50)
+        .map(|i| (start + slot * i, start + slot * (i + 1), Capabilities::A))
+        .collect();
+    let ids = planner.insert_entries(system, &batch).await?;
+    assert_eq!(ids.len(), 50);
+    let entries = planner.list_entries(system, None).await?;
+    assert_eq!(
+        entries.iter().map(|e| e.allocation_id).collect::<Vec<_>>(),
+        ids
+    );
+
+    // Entries of the batch are evaluated against each other
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+    let batch = [
+        (start, start + slot, Capabilities::A),
+        (start + slot, start + slot * 2, Capabilities::A),
+        (start, start + slot, Capabilities::B),
+    ];
+    let result = planner.insert_entries(system, &batch).await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::Batch { index: 2, ref error })
+            if matches!(**error, AllocationError::CapacityExceeded { .. })
+    ));
+    assert!(result.unwrap_err().is_conflict());
+
+    // As well as the existing allocations, leaving nothing behind on failure
+    planner
+        .insert_planned_capability_outage(system, Capabilities::B, start + slot, start + slot * 2)
+        .await?;
+    let batch = [
+        (start, start + slot, Capabilities::B),
+        (start + slot, start + slot * 2, Capabilities::B),
+    ];
+    let result = planner.insert_entries(system, &batch).await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::Batch { index: 1, ref error })
+            if matches!(**error, AllocationError::CapabilityOutage { .. })
+    ));
+    let result = planner
+        .insert_entries(system, &[batch[0], (start, start, Capabilities::A)])
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::Batch { index: 1, ref error })
+            if matches!(**error, AllocationError::InvalidRange { .. })
+    ));
+    assert!(planner.list_entries(system, None).await?.is_empty());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn wide_capabilities(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system_with_capacities(system, &[(Capabilities::A, 1), (Capabilities::H, 1)])
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let end = start + Duration::hours(1);
+    planner
+        .insert_entry(system, start, end, Capabilities::H)
+        .await?;
+    let entries = planner.list_entries(system, None).await?;
+    assert_eq!(entries[0].capabilities, Capabilities::H);
+
+    // The highest capability has a capacity of its own, and may be in an outage of its own
+    let result = planner
+        .insert_entry(system, start, end, Capabilities::H)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::CapacityExceeded { .. })
+    ));
+    planner
+        .insert_planned_capability_outage(system, Capabilities::H, end, end + Duration::hours(1))
+        .await?;
+    let result = planner
+        .insert_entry(system, end, end + Duration::hours(1), Capabilities::H)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::CapabilityOutage { .. })
+    ));
+    planner
+        .insert_entry(system, end, end + Duration::hours(1), Capabilities::A)
+        .await?;
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn cancel_planned_outage(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let now = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let hour = Duration::hours(1);
+    let start = now + hour;
+    let end = start + hour;
+    planner.insert_planned_outage(system, start, end).await?;
+    let outage = planner.list_outages(system, start, end).await?[0].clone();
+    let Outage::Planned { allocation_id, .. } = outage else {
+        panic!("expected a planned outage, got {outage:?}");
+    };
+
+    let result = planner
+        .insert_entry(system, start, end, Capabilities::A)
+        .await;
+    assert!(result.is_err());
+    planner.cancel_planned_outage(allocation_id).await?;
+    planner
+        .insert_entry(system, start, end, Capabilities::A)
+        .await?;
+
+    // Only planned outages may be cancelled
+    let entry = planner.list_entries(system, None).await?[0].allocation_id;
+    let result = planner.cancel_planned_outage(entry).await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::WrongKind {
+            kind: AllocationKind::Entry,
+            ..
+        })
+    ));
+    let result = planner.cancel_planned_outage(allocation_id).await;
+    assert!(matches!(result, Err(AllocationError::NotFound(_))));
+
+    // An outage in progress is only cancelled from now on
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+    let outage = planner
+        .insert_planned_capability_outage(system, Capabilities::B, now - hour, now + hour)
+        .await?;
+    planner.cancel_planned_outage_remainder(outage).await?;
+    let outages = planner.list_outages(system, now - hour, now + hour).await?;
+    assert!(matches!(
+        outages[..],
+        [Outage::Capability { start, end, .. }] if start == now - hour && end > now && end < now + hour
+    ));
+    planner
+        .insert_entry(
+            system,
+            now + Duration::minutes(1),
+            now + hour,
+            Capabilities::B,
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn move_planned_outage(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let now = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let hour = Duration::hours(1);
+    let outage = planner
+        .insert_planned_outage(system, now + hour, now + hour * 2)
+        .await?;
+    let entry = planner
+        .insert_entry(system, now + hour * 3, now + hour * 4, Capabilities::A)
+        .await?;
+
+    // Overlapping its own timespan is no conflict
+    planner
+        .move_planned_outage(outage, now + hour + Duration::minutes(30), now + hour * 3)
+        .await?;
+    let outages = planner.list_outages(system, now, now + hour * 4).await?;
+    assert!(matches!(
+        outages[..],
+        [Outage::Planned { start, end, .. }]
+            if start == now + hour + Duration::minutes(30) && end == now + hour * 3
+    ));
+    planner
+        .insert_entry(
+            system,
+            now + hour,
+            now + hour + Duration::minutes(30),
+            Capabilities::A,
+        )
+        .await?;
+
+    // The entry must be cleared before the outage may be moved over it
+    let result = planner
+        .move_planned_outage(outage, now + hour * 3, now + hour * 5)
+        .await;
+    assert!(
+        matches!(&result, Err(AllocationError::Conflict { conflicts })
+            if conflicts.len() == 1 && conflicts[0].allocation.allocation_id == entry),
+        "{result:?}"
+    );
+    let outages = planner.list_outages(system, now, now + hour * 5).await?;
+    assert_eq!(outages[0].allocation_id(), outage);
+    assert!(matches!(outages[..], [Outage::Planned { end, .. }] if end == now + hour * 3));
+
+    let result = planner.move_planned_outage(entry, now, now + hour).await;
+    assert!(matches!(result, Err(AllocationError::WrongKind { .. })));
+    let result = planner
+        .move_planned_outage(outage, now + hour, now + hour)
+        .await;
+    assert!(matches!(result, Err(AllocationError::InvalidRange { .. })));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn insert_recurring_planned_outage(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool.clone());
+    let system = SystemBuilder::new(&planner).declare().await?;
+
+    // Daily, two hours from now in a timezone without daylight saving
+    let first = system.at(120).duration_trunc(Duration::minutes(1))?;
+    let tokyo = chrono_tz::Asia::Tokyo;
+    let schedule = MaintenanceSchedule::daily(first.with_timezone(&tokyo).time(), tokyo);
+    let hour = Duration::hours(1);
+    let entry = planner
+        .insert_entry(
+            system.id,
+            first + Duration::days(1),
+            first + Duration::days(1) + hour,
+            Capabilities::A,
+        )
+        .await?;
+
+    // The occurrence in conflict is reported, while the rest are inserted
+    let series = planner
+        .insert_recurring_planned_outage(system.id, &schedule, hour, Duration::days(3))
+        .await?;
+    assert_eq!(series.allocation_ids.len(), 2);
+    assert!(matches!(
+        &series.conflicts[..],
+        [OccurrenceConflict { start, conflicts, .. }]
+            if *start == first + Duration::days(1)
+                && conflicts.len() == 1
+                && conflicts[0].allocation.allocation_id == entry
+    ));
+    let starts: Vec<_> = planner
+        .list_outages(system.id, first, first + Duration::days(3))
+        .await?
+        .iter()
+        .map(|o| o.start())
+        .collect();
+    assert_eq!(starts, [first, first + Duration::days(2)]);
+
+    // Nothing has come within the horizon yet
+    assert!(planner.extend_recurrences().await?.is_empty());
+
+    // As if a day has passed since, the occurrence a day further ahead is inserted
+    planner
+        .cancel_planned_outage(series.allocation_ids[1])
+        .await?;
+    sqlx::query(
+        "UPDATE outage_recurrences SET materialized_until = materialized_until - interval '1 day'",
+    )
+    .execute(&pool)
+    .await?;
+    let extended = planner.extend_recurrences().await?;
+    assert_eq!(extended.len(), 1);
+    assert_eq!(extended[0].recurrence_id, series.recurrence_id);
+    assert_eq!(extended[0].allocation_ids.len(), 1);
+    assert!(extended[0].conflicts.is_empty());
+
+    let result = planner
+        .insert_recurring_planned_outage(system.id, &schedule, Duration::zero(), Duration::days(3))
+        .await;
+    assert!(matches!(result, Err(AllocationError::Validation(_))));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn get_allocation(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool.clone());
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let end = start + Duration::hours(1);
+    planner
+        .insert_entry(system, start, end, Capabilities::A | Capabilities::C)
+        .await?;
+    planner
+        .insert_unplanned_outage(system, end, Duration::hours(1))
+        .await?;
+
+    let entry = planner.list_entries(system, None).await?[0].allocation_id;
+    let allocation = planner.get_allocation(entry).await?.unwrap();
+    assert_eq!(allocation.system_id, system);
+    assert_eq!(allocation.kind, AllocationKind::Entry);
+    assert!(allocation.planned);
+    assert_eq!(allocation.start_time, start);
+    assert_eq!(allocation.end_time, Some(end));
+    assert_eq!(allocation.end(), AllocationEnd::At(end));
+    assert_eq!(allocation.capabilities, Capabilities::A | Capabilities::C);
+
+    let outage = planner
+        .list_outages(system, end, end + Duration::hours(1))
+        .await?[0]
+        .allocation_id();
+    let allocation = planner.get_allocation(outage).await?.unwrap();
+    assert_eq!(allocation.kind, AllocationKind::Full);
+    assert!(!allocation.planned);
+    assert_eq!(allocation.end_time, None);
+    assert_eq!(allocation.end(), AllocationEnd::Never);
+
+    assert_eq!(planner.get_allocation(Uuid::new_v4()).await?, None);
+
+    // Unknown capability bits are not silently dropped
+    sqlx::query("UPDATE allocations SET capabilities = 256 WHERE allocation_id = $1")
+        .bind(entry)
+        .execute(&pool)
+        .await?;
+    let result = planner.get_allocation(entry).await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::Database(sqlx::Error::Decode(_)))
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn capacity_timeline(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 3, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let hour = Duration::hours(1);
+    for i in 0..3 {
+        planner
+            .insert_entry(
+                system,
+                start + hour * i,
+                start + hour * (i + 2),
+                Capabilities::A,
+            )
+            .await?;
+    }
+    planner
+        .insert_planned_capability_outage(system, Capabilities::B, start + hour, start + hour * 2)
+        .await?;
+    planner
+        .insert_unplanned_outage(system, start + hour * 5, hour)
+        .await?;
+
+    let segment = |from: i32, entries, remaining, capabilities| CapacitySegment {
+        start: start + hour * from,
+        end: start + hour * (from + 1),
+        entries,
+        remaining,
+        capabilities,
+    };
+    let timeline = planner
+        .capacity_timeline(system, start, start + hour * 6)
+        .await?;
+    assert_eq!(
+        timeline,
+        vec![
+            segment(0, 1, 2, Capabilities::all()),
+            segment(1, 2, 1, Capabilities::all() - Capabilities::B),
+            segment(2, 2, 1, Capabilities::all()),
+            segment(3, 1, 2, Capabilities::all()),
+            segment(4, 0, 3, Capabilities::all()),
+            segment(5, 0, 0, Capabilities::empty()),
+        ]
+    );
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn default_sliding_window(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let result = planner.insert_unplanned_outage_default(system, start).await;
+    assert!(matches!(result, Err(AllocationError::Validation(_))));
+    let result = planner
+        .set_default_sliding_window(Uuid::new_v4(), Duration::hours(24))
+        .await;
+    assert!(matches!(result, Err(AllocationError::SystemNotFound(_))));
+
+    // The default window applies, and must be clear of entries
+    planner
+        .insert_entry(
+            system,
+            start + Duration::hours(48),
+            start + Duration::hours(49),
+            Capabilities::A,
+        )
+        .await?;
+    planner
+        .set_default_sliding_window(system, Duration::hours(72))
+        .await?;
+    let result = planner.insert_unplanned_outage_default(system, start).await;
+    assert!(result.is_err());
+    planner
+        .set_default_sliding_window(system, Duration::hours(24))
+        .await?;
+    planner
+        .insert_unplanned_outage_default(system, start)
+        .await?;
+
+    let outages = planner
+        .list_outages(system, start, start + Duration::hours(1))
+        .await?;
+    assert!(matches!(
+        outages[..],
+        [Outage::Unplanned { sliding_window, .. }] if sliding_window == Duration::hours(24)
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn overlapping_outages_are_excluded(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool.clone());
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let hour = Duration::hours(1);
+    planner
+        .insert_planned_outage(system, start, start + hour)
+        .await?;
+    planner
+        .insert_planned_capability_outage(system, Capabilities::A, start + hour, start + hour * 2)
+        .await?;
+    planner
+        .insert_planned_capability_outage(
+            system,
+            Capabilities::B,
+            start + hour * 2,
+            start + hour * 3,
+        )
+        .await?;
+
+    // Updates bypass the insert triggers, but not the exclusion constraint
+    let extend = |from: chrono::DateTime<Utc>, to| {
+        sqlx::query("UPDATE allocations SET end_time = $2 WHERE system_id = $1 AND start_time = $3")
+            .bind(system)
+            .bind(to)
+            .bind(from)
+            .execute(&pool)
+    };
+    let err = extend(start, start + hour * 2).await.unwrap_err();
+    let code = err.as_database_error().and_then(|e| e.code());
+    assert_eq!(code.as_deref(), Some("23P01"));
+
+    // Outages of separate capabilities may overlap
+    extend(start + hour, start + hour * 3).await?;
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn callers_transaction(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool.clone());
+
+    let system = Uuid::new_v4();
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let end = start + Duration::hours(1);
+
+    // Nothing is persisted unless the caller commits
+    let mut tx = pool.begin().await?;
+    planner
+        .declare_system_tx(&mut tx, system, 1, Capabilities::all())
+        .await?;
+    planner
+        .insert_entry_tx(&mut tx, system, start, end, Capabilities::A)
+        .await?;
+    tx.rollback().await?;
+    let result = planner
+        .insert_entry(system, start, end, Capabilities::A)
+        .await;
+    assert!(matches!(result, Err(AllocationError::SystemNotFound(_))));
+
+    let mut tx = pool.begin().await?;
+    planner
+        .declare_system_tx(&mut tx, system, 1, Capabilities::all())
+        .await?;
+    planner
+        .insert_entry_tx(&mut tx, system, start, end, Capabilities::A)
+        .await?;
+    // Operations within the same transaction see the uncommitted allocations
+    let result = planner
+        .insert_planned_outage_tx(&mut tx, system, start, end)
+        .await;
+    assert!(matches!(result, Err(AllocationError::Conflict { .. })));
+    tx.commit().await?;
+    assert_eq!(planner.list_entries(system, None).await?.len(), 1);
+
+    // As are the removals of a sweep
+    planner
+        .insert_unplanned_outage(system, start - Duration::hours(2), Duration::hours(1))
+        .await?;
+    let mut tx = pool.begin().await?;
+    assert_eq!(
+        planner
+            .sweep_unplanned_windows_tx(&mut tx, system)
+            .await?
+            .len(),
+        1
+    );
+    tx.rollback().await?;
+    assert_eq!(planner.list_entries(system, None).await?.len(), 1);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn list_systems_with_capability(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let a = SystemBuilder::new(&planner)
+        .capabilities(Capabilities::A)
+        .declare()
+        .await?;
+    let ac = SystemBuilder::new(&planner)
+        .capabilities(Capabilities::A | Capabilities::C)
+        .declare()
+        .await?;
+    let all = SystemBuilder::new(&planner).declare().await?;
+
+    let mut expected = vec![ac.id, all.id];
+    expected.sort();
+    assert_eq!(
+        planner
+            .list_systems_with_capability(Capabilities::C)
+            .await?,
+        expected
+    );
+    assert_eq!(
+        planner
+            .list_systems_with_capability(Capabilities::A | Capabilities::C)
+            .await?,
+        expected
+    );
+    assert_eq!(
+        planner
+            .list_systems_with_capability(Capabilities::H)
+            .await?,
+        [all.id]
+    );
+    assert_eq!(
+        planner
+            .list_systems_with_capability(Capabilities::empty())
+            .await?
+            .len(),
+        3
+    );
+    assert!(planner
+        .list_systems_with_capability(Capabilities::A)
+        .await?
+        .contains(&a.id));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn boundary_policy(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let start = Utc::now().duration_trunc(Duration::seconds(1))? + Duration::hours(1);
+    let hour = Duration::hours(1);
+
+    // Under each policy, along with the gap it leaves between allocations in conflict
+    for (policy, gap) in [
+        (BoundaryPolicy::Touching, Duration::zero()),
+        (BoundaryPolicy::Exclusive, Duration::microseconds(1)),
+        (
+            BoundaryPolicy::Gap(Duration::minutes(30)),
+            Duration::minutes(30),
+        ),
+    ] {
+        let system = Uuid::new_v4();
+        planner
+            .declare_system_with_boundary_policy(system, 1, Capabilities::all(), policy)
+            .await?;
+        let info = planner.get_system(system).await?.unwrap();
+        assert_eq!(info.boundary_policy, policy);
+        planner
+            .insert_entry(system, start, start + hour, Capabilities::A)
+            .await?;
+        let touching_allowed = policy == BoundaryPolicy::Touching;
+
+        // Closer than the gap at either boundary is in conflict, as is a batch with itself
+        for (from, to) in [
+            (start + hour, start + hour * 2),
+            (start - hour, start),
+            (start + hour + gap, start + hour * 2),
+            (start - hour, start - gap),
+        ] {
+            let fits = planner
+                .can_insert_entry(system, from, to, Capabilities::A)
+                .await?;
+            let apart = from >= start + hour + gap || to <= start - gap;
+            assert_eq!(fits, apart, "{policy:?} from {from} to {to}");
+        }
+        let result = planner
+            .insert_entries(
+                system,
+                &[
+                    (start + hour * 4, start + hour * 5, Capabilities::A),
+                    (start + hour * 5, start + hour * 6, Capabilities::A),
+                ],
+            )
+            .await;
+        assert_eq!(result.is_ok(), touching_allowed, "{policy:?}");
+
+        // The earliest free slot leaves the gap after the entry
+        let slot = planner
+            .find_next_free_slot(system, start, hour, Capabilities::A)
+            .await?;
+        assert_eq!(slot, Some(start + hour + gap));
+        planner
+            .insert_entry(system, slot.unwrap(), slot.unwrap() + hour, Capabilities::A)
+            .await?;
+
+        // Outages closer than the gap are in conflict with an entry, and the other way around
+        let result = planner
+            .insert_planned_outage(system, start - hour, start)
+            .await;
+        assert_eq!(result.is_ok(), touching_allowed, "{policy:?}");
+        planner
+            .insert_planned_outage(system, start - hour * 4, start - hour * 3)
+            .await?;
+        for from in [start - hour * 3, start - hour * 3 + gap] {
+            let fits = planner
+                .can_insert_entry(system, from, start - hour * 2, Capabilities::A)
+                .await?;
+            assert_eq!(fits, from >= start - hour * 3 + gap, "{policy:?}");
+        }
+    }
+
+    // The gap must be positive
+    for gap in [Duration::zero(), Duration::minutes(-30)] {
+        let result = planner
+            .declare_system_with_boundary_policy(
+                Uuid::new_v4(),
+                1,
+                Capabilities::all(),
+                BoundaryPolicy::Gap(gap),
+            )
+            .await;
+        assert!(matches!(result, Err(AllocationError::Validation(_))));
+    }
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn get_system(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    assert_eq!(planner.get_system(system).await?, None);
+
+    planner
+        .declare_system(system, 4, Capabilities::A | Capabilities::H)
+        .await?;
+    assert_eq!(
+        planner.get_system(system).await?,
+        Some(SystemInfo {
+            capacity: 4,
+            capabilities: Capabilities::A | Capabilities::H,
+            default_sliding_window: None,
+            min_notice: None,
+            max_horizon: None,
+            boundary_policy: BoundaryPolicy::Touching,
+            timezone: None,
+        })
+    );
+
+    planner
+        .set_default_sliding_window(system, Duration::hours(24))
+        .await?;
+    let info = planner.get_system(system).await?.unwrap();
+    assert_eq!(info.default_sliding_window, Some(Duration::hours(24)));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn insert_entry_with_id(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 2, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let end = start + Duration::hours(1);
+    let entry = Uuid::new_v4();
+    planner
+        .insert_entry_with_id(system, entry, start, end, Capabilities::A)
+        .await?;
+
+    // Retrying after the first attempt succeeded must not book twice
+    planner
+        .insert_entry_with_id(system, entry, start, end, Capabilities::A)
+        .await?;
+    let entries = planner.list_entries(system, None).await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].allocation_id, entry);
+
+    // Retrying with changed times is in conflict with the earlier attempt
+    let result = planner
+        .insert_entry_with_id(
+            system,
+            entry,
+            start,
+            end + Duration::hours(1),
+            Capabilities::A,
+        )
+        .await;
+    match result {
+        Err(AllocationError::Conflict { conflicts }) => {
+            assert_eq!(conflicts.len(), 1);
+            assert_eq!(conflicts[0].allocation.allocation_id, entry);
+            assert_eq!(conflicts[0].allocation.end_time, Some(end));
+        }
+        other => panic!("expected a conflict, got {other:?}"),
+    }
+
+    // As is reusing the id of an outage
+    planner
+        .insert_planned_outage(system, end, end + Duration::hours(1))
+        .await?;
+    let outage = planner
+        .list_outages(system, start, end + Duration::hours(1))
+        .await?[0]
+        .allocation_id();
+    let result = planner
+        .insert_entry_with_id(system, outage, start, end, Capabilities::A)
+        .await;
+    assert!(matches!(result, Err(AllocationError::Conflict { .. })));
+    assert_eq!(planner.list_entries(system, None).await?.len(), 1);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn insert_entry_idempotent(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).capacity(2).declare().await?;
+    let key = Uuid::new_v4();
+    // Retried with the same instants, including their sub-microsecond precision
+    let start = Utc::now() + Duration::hours(1);
+    let end = start + Duration::hours(1);
+
+    let entry = planner
+        .insert_entry_idempotent(system.id, key, start, end, Capabilities::A)
+        .await?;
+    let retried = planner
+        .insert_entry_idempotent(system.id, key, start, end, Capabilities::A)
+        .await?;
+    assert_eq!(retried, entry);
+    assert_eq!(planner.list_entries(system.id, None).await?.len(), 1);
+
+    // Another request with the key is in conflict with its entry
+    let result = planner
+        .insert_entry_idempotent(system.id, key, start, end, Capabilities::B)
+        .await;
+    assert!(
+        matches!(&result, Err(AllocationError::Conflict { conflicts })
+            if conflicts.len() == 1 && conflicts[0].allocation.allocation_id == entry),
+        "{result:?}"
+    );
+
+    // Another key is subject to the usual checks
+    let other = planner
+        .insert_entry_idempotent(system.id, Uuid::new_v4(), start, end, Capabilities::B)
+        .await?;
+    assert_ne!(other, entry);
+    let result = planner
+        .insert_entry_idempotent(system.id, Uuid::new_v4(), start, end, Capabilities::C)
+        .await;
+    assert!(
+        matches!(result, Err(AllocationError::CapacityExceeded { .. })),
+        "{result:?}"
+    );
+
+    // The key is reused once its entry is removed
+    planner.remove_entry(entry).await?;
+    let reinserted = planner
+        .insert_entry_idempotent(system.id, key, start, end, Capabilities::A)
+        .await?;
+    assert_ne!(reinserted, entry);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn conflict_details(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 2, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let hour = Duration::hours(1);
+    planner
+        .insert_entry(system, start, start + hour * 2, Capabilities::A)
+        .await?;
+    planner
+        .insert_entry(system, start + hour, start + hour * 3, Capabilities::B)
+        .await?;
+
+    // Both entries are concurrent from the start of the second one
+    let result = planner
+        .insert_entry(
+            system,
+            start,
+            start + hour * 4,
+            Capabilities::A | Capabilities::C,
+        )
+        .await;
+    match result {
+        Err(AllocationError::CapacityExceeded { conflicts, .. }) => {
+            assert_eq!(conflicts.len(), 2);
+            assert!(conflicts.iter().all(|c| c.blocker == Blocker::Capacity));
+            assert_eq!(conflicts[0].overlap_start, start);
+            assert_eq!(conflicts[0].overlap_end, Some(start + hour * 2));
+            assert_eq!(conflicts[0].capabilities, Capabilities::A);
+            assert_eq!(conflicts[1].capabilities, Capabilities::empty());
+            for conflict in &conflicts {
+                let peak = conflict.peak.unwrap();
+                assert_eq!(peak.at, start + hour);
+                assert_eq!(peak.concurrent, 2);
+            }
+        }
+        other => panic!("expected capacity conflict, got {other:?}"),
+    }
+
+    // An unplanned outage is only in conflict with the part of entries within its sliding window
+    let result = planner
+        .insert_unplanned_outage(system, start + hour, Duration::minutes(30))
+        .await;
+    match result {
+        Err(AllocationError::Conflict { conflicts }) => {
+            assert_eq!(conflicts.len(), 2);
+            assert!(conflicts.iter().all(|c| c.blocker == Blocker::Entry
+                && c.overlap_start == start + hour
+                && c.overlap_end == Some(start + hour + Duration::minutes(30))
+                && c.peak.is_none()));
+        }
+        other => panic!("expected conflict, got {other:?}"),
+    }
+
+    // An outage in the way is reported by its kind
+    planner
+        .insert_planned_capability_outage(
+            system,
+            Capabilities::C | Capabilities::D,
+            start + hour * 5,
+            start + hour * 7,
+        )
+        .await?;
+    let result = planner
+        .insert_planned_outage(system, start + hour * 6, start + hour * 8)
+        .await;
+    match result {
+        Err(AllocationError::Conflict { conflicts }) => {
+            assert_eq!(conflicts.len(), 1);
+            assert_eq!(conflicts[0].blocker, Blocker::CapabilityOutage);
+            assert_eq!(conflicts[0].overlap_start, start + hour * 6);
+            assert_eq!(conflicts[0].overlap_end, Some(start + hour * 7));
+            assert_eq!(conflicts[0].capabilities, Capabilities::C | Capabilities::D);
+        }
+        other => panic!("expected conflict, got {other:?}"),
+    }
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn update_capacity(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    // Growing always succeeds
+    planner.update_capacity(system, 4).await?;
+    assert_eq!(planner.get_system(system).await?.unwrap().capacity, 4);
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))? + Duration::hours(1);
+    let hour = Duration::hours(1);
+    planner
+        .insert_entry(system, start, start + hour * 3, Capabilities::A)
+        .await?;
+    planner
+        .insert_entry(system, start + hour, start + hour * 2, Capabilities::B)
+        .await?;
+    planner
+        .insert_entry(system, start + hour * 4, start + hour * 5, Capabilities::C)
+        .await?;
+
+    // Shrinking below the concurrent entries reports the timespan they overlap
+    let result = planner.update_capacity(system, 1).await;
+    match result {
+        Err(AllocationError::CapacityExceeded { conflicts, .. }) => {
+            assert_eq!(conflicts.len(), 2);
+            assert!(conflicts.iter().all(|c| c.overlap_start == start + hour
+                && c.overlap_end == Some(start + hour * 2)
+                && c.peak.unwrap().concurrent == 2));
+        }
+        other => panic!("expected capacity conflict, got {other:?}"),
+    }
+    assert_eq!(planner.get_system(system).await?.unwrap().capacity, 4);
+
+    planner.update_capacity(system, 2).await?;
+    assert_eq!(planner.get_system(system).await?.unwrap().capacity, 2);
+
+    let result = planner.update_capacity(Uuid::new_v4(), 2).await;
+    assert!(matches!(result, Err(AllocationError::SystemNotFound(_))));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn unplanned_capability_outage(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 2, Capabilities::all())
+        .await?;
+
+    let window = Duration::hours(24);
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+
+    // Add fixture entries, of which only the one of the failed capability is in the way
+    planner
+        .insert_entry(
+            system,
+            start + Duration::hours(1),
+            start + Duration::hours(2),
+            Capabilities::A,
+        )
+        .await?;
+    planner
+        .insert_entry(
+            system,
+            start + window,
+            start + window + Duration::minutes(15),
+            Capabilities::B,
+        )
+        .await?;
+
+    let result = planner
+        .insert_unplanned_capability_outage(
+            system,
+            Capabilities::B,
+            start,
+            window + Duration::minutes(5),
+        )
+        .await;
+    match result {
+        Err(AllocationError::Conflict { conflicts }) => {
+            assert_eq!(conflicts.len(), 1);
+            assert_eq!(conflicts[0].allocation.capabilities, Capabilities::B);
+        }
+        other => panic!("expected conflict, got {other:?}"),
+    }
+
+    // Inserting the unplanned outage with the window prior to, or at the boundary, is ok.
+    planner
+        .insert_unplanned_capability_outage(system, Capabilities::B, start, window)
+        .await?;
+    let outages = planner.list_outages(system, start, start + window).await?;
+    assert!(matches!(
+        outages[..],
+        [Outage::Unplanned { capabilities, .. }] if capabilities == Capabilities::B
+    ));
+
+    // Entries of the failed capability are denied, regardless of the window.
+    let result = planner
+        .insert_entry(
+            system,
+            start + window * 2,
+            start + window * 2 + Duration::minutes(15),
+            Capabilities::B | Capabilities::C,
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::WindowViolation { .. })
+    ));
+
+    // While entries of other capabilities may still be inserted, and are left by the sweep,
+    // which only removes the entry of the failed capability as the window slides past it.
+    planner
+        .insert_entry(
+            system,
+            start + Duration::hours(3),
+            start + Duration::hours(4),
+            Capabilities::A | Capabilities::C,
+        )
+        .await?;
+    assert_eq!(planner.sweep_unplanned_windows(system).await?.len(), 1);
+    let entries = planner.list_entries(system, None).await?;
+    assert_eq!(entries.len(), 2);
+    assert!(entries
+        .iter()
+        .all(|e| !e.capabilities.intersects(Capabilities::B)));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn count_active_entries(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 3, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let hour = Duration::hours(1);
+    planner
+        .insert_entry(system, start, start + hour * 2, Capabilities::A)
+        .await?;
+    planner
+        .insert_entry(
+            system,
+            start + hour,
+            start + hour * 3,
+            Capabilities::A | Capabilities::B,
+        )
+        .await?;
+    planner
+        .insert_planned_outage(system, start + hour * 3, start + hour * 4)
+        .await?;
+
+    let count = |at, capabilities| planner.count_active_entries(system, at, capabilities);
+    assert_eq!(count(start, Capabilities::A).await?, 1);
+    assert_eq!(count(start + hour, Capabilities::A).await?, 2);
+    assert_eq!(count(start + hour, Capabilities::B).await?, 1);
+    assert_eq!(count(start + hour, Capabilities::C).await?, 0);
+    // Entries no longer count at their end, and outages never do
+    assert_eq!(count(start + hour * 2, Capabilities::all()).await?, 1);
+    assert_eq!(count(start + hour * 3, Capabilities::all()).await?, 0);
+
+    let result = planner
+        .count_active_entries(Uuid::new_v4(), start, Capabilities::A)
+        .await;
+    assert!(matches!(result, Err(AllocationError::SystemNotFound(_))));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn insert_planned_outage_rescheduling(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let hour = Duration::hours(1);
+    planner
+        .insert_entry(system, start, start + hour, Capabilities::A)
+        .await?;
+    planner
+        .insert_entry(system, start + hour, start + hour * 3, Capabilities::B)
+        .await?;
+    planner
+        .insert_entry(system, start + hour * 4, start + hour * 5, Capabilities::C)
+        .await?;
+    let entries = planner.list_entries(system, None).await?;
+
+    // Not enough room within the horizon leaves everything as it was
+    let policy = ReschedulePolicy { horizon: hour * 2 };
+    let result = planner
+        .insert_planned_outage_rescheduling(system, start, start + hour * 2, policy)
+        .await;
+    assert!(
+        matches!(result, Err(AllocationError::NotRescheduled(id)) if id == entries[1].allocation_id)
+    );
+    assert_eq!(planner.list_entries(system, None).await?, entries);
+    assert!(planner
+        .list_outages(system, start, start + hour * 2)
+        .await?
+        .is_empty());
+
+    // The displaced entries are moved in order into the first gaps after the outage
+    let policy = ReschedulePolicy { horizon: hour * 24 };
+    let moves = planner
+        .insert_planned_outage_rescheduling(system, start, start + hour * 2, policy)
+        .await?;
+    assert_eq!(
+        moves,
+        vec![
+            EntryMove {
+                allocation_id: entries[0].allocation_id,
+                capabilities: Capabilities::A,
+                from: (start, start + hour),
+                to: (start + hour * 2, start + hour * 3),
+            },
+            EntryMove {
+                allocation_id: entries[1].allocation_id,
+                capabilities: Capabilities::B,
+                from: (start + hour, start + hour * 3),
+                to: (start + hour * 5, start + hour * 7),
+            },
+        ]
+    );
+    assert_eq!(planner.list_entries(system, None).await?.len(), 3);
+    assert_eq!(
+        planner
+            .list_outages(system, start, start + hour * 2)
+            .await?
+            .len(),
+        1
+    );
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn update_capacity_forced(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 3, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))? + Duration::hours(1);
+    let hour = Duration::hours(1);
+    for capabilities in [Capabilities::A, Capabilities::B, Capabilities::C] {
+        planner
+            .insert_entry(system, start, start + hour, capabilities)
+            .await?;
+    }
+
+    let result = planner.update_capacity(system, 2).await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::CapacityExceeded { conflicts, .. }) if conflicts.len() == 3
+    ));
+    assert_eq!(planner.get_system(system).await?.unwrap().capacity, 3);
+
+    // The entry created last is the one to go, and is left for the caller to remove
+    let excess = planner.update_capacity_forced(system, 2).await?;
+    assert_eq!(excess.len(), 1);
+    assert_eq!(excess[0].capabilities, Capabilities::C);
+    assert_eq!(planner.get_system(system).await?.unwrap().capacity, 2);
+    assert_eq!(planner.list_entries(system, None).await?.len(), 3);
+
+    planner.remove_entry(excess[0].allocation_id).await?;
+    planner.update_capacity(system, 2).await?;
+
+    // Growing never has anything in excess
+    assert_eq!(planner.update_capacity_forced(system, 5).await?, Vec::new());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn insert_recurring_entry(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool.clone());
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let hour = Duration::hours(1);
+    let week = Duration::weeks(1);
+    planner
+        .insert_entry(
+            system,
+            start + week * 2,
+            start + week * 2 + hour,
+            Capabilities::B,
+        )
+        .await?;
+
+    // A single occurrence in conflict fails the whole series
+    let result = planner
+        .insert_recurring_entry(system, start, hour, week, 4, Capabilities::A)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::Batch { index: 2, .. })
+    ));
+    assert_eq!(planner.list_entries(system, None).await?.len(), 1);
+
+    let RecurringEntry {
+        group_id,
+        allocation_ids,
+        ..
+    } = planner
+        .insert_recurring_entry(system, start + hour, hour, week, 4, Capabilities::A)
+        .await?;
+    assert_eq!(allocation_ids.len(), 4);
+    let entries = planner.list_entries(system, None).await?;
+    let occurrences: Vec<_> = entries
+        .iter()
+        .filter(|e| e.capabilities == Capabilities::A)
+        .map(|e| (e.allocation_id, e.start_time))
+        .collect();
+    assert_eq!(
+        occurrences,
+        (0..4)
+            .map(|i| (allocation_ids[i as usize], start + hour + week * i))
+            .collect::<Vec<_>>()
+    );
+    let grouped: i64 =
+        sqlx::query_scalar("SELECT count(*) FROM entries WHERE recurrence_group = $1")
+            .bind(group_id)
+            .fetch_one(&pool)
+            .await?;
+    assert_eq!(grouped, 4);
+
+    let result = planner
+        .insert_recurring_entry(system, start, hour, week, 0, Capabilities::A)
+        .await;
+    assert!(matches!(result, Err(AllocationError::Validation(_))));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn update_capabilities(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 2, Capabilities::A | Capabilities::B)
+        .await?;
+
+    // Adding a capability just works
+    planner
+        .update_capabilities(system, Capabilities::A | Capabilities::B | Capabilities::C)
+        .await?;
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let hour = Duration::hours(1);
+    planner
+        .insert_entry(
+            system,
+            start,
+            start + hour,
+            Capabilities::A | Capabilities::C,
+        )
+        .await?;
+    planner
+        .insert_planned_capability_outage(system, Capabilities::B, start, start + hour)
+        .await?;
+    planner
+        .insert_entry(system, start - hour * 2, start - hour, Capabilities::C)
+        .await?;
+
+    // Removing capabilities still in use reports what is using them
+    let result = planner.update_capabilities(system, Capabilities::A).await;
+    match result {
+        Err(AllocationError::CapabilitiesInUse { removed, conflicts }) => {
+            assert_eq!(removed, Capabilities::B | Capabilities::C);
+            let blockers: Vec<_> = conflicts
+                .iter()
+                .map(|c| (c.blocker, c.capabilities))
+                .collect();
+            assert_eq!(
+                blockers,
+                [
+                    (Blocker::Entry, Capabilities::C),
+                    (Blocker::CapabilityOutage, Capabilities::B)
+                ]
+            );
+        }
+        other => panic!("expected capabilities in use, got {other:?}"),
+    }
+
+    // Entries that have already ended do not prevent removal
+    let entry = planner
+        .list_entries(system, Some((start, start + hour)))
+        .await?[0]
+        .allocation_id;
+    planner.remove_entry(entry).await?;
+    planner
+        .update_capabilities(system, Capabilities::A | Capabilities::B)
+        .await?;
+    assert_eq!(
+        planner.get_system(system).await?.unwrap().capabilities,
+        Capabilities::A | Capabilities::B
+    );
+    let result = planner
+        .insert_entry(system, start + hour, start + hour * 2, Capabilities::C)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::UnsupportedCapabilities { .. })
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn cancel_recurring_group(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 2, Capabilities::all())
+        .await?;
+
+    let start = Utc::now().duration_trunc(Duration::seconds(1))?;
+    let hour = Duration::hours(1);
+    let day = Duration::days(1);
+    let series = planner
+        .insert_recurring_entry(system, start, hour, day, 3, Capabilities::A)
+        .await?;
+    planner
+        .insert_recurring_entry(system, start, hour, day, 2, Capabilities::B)
+        .await?;
+
+    // The window of an outage that started a day ago has since slid over the first occurrences,
+    // which may still be removed
+    planner
+        .insert_unplanned_outage(system, start - day, hour)
+        .await?;
+
+    assert_eq!(planner.cancel_recurring_group(series.group_id).await?, 3);
+    let entries = planner.list_entries(system, None).await?;
+    assert_eq!(entries.len(), 2);
+    assert!(entries.iter().all(|e| e.capabilities == Capabilities::B));
+
+    assert_eq!(planner.cancel_recurring_group(series.group_id).await?, 0);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn insert_recurring_schedule(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).declare().await?;
+    let hour = Duration::hours(1);
+    // The next Monday, at least a day ahead
+    let mut monday = system.at(24 * 60);
+    while monday.weekday() != Weekday::Mon {
+        monday += Duration::days(1);
+    }
+    let until = monday + Duration::weeks(2);
+    let lab_days = Recurrence::daily().on(&[Weekday::Mon, Weekday::Wed]);
+
+    // Wednesday of the second week is already taken
+    let taken = system
+        .entry_at(
+            (monday - system.now).num_minutes() + 9 * 24 * 60,
+            30,
+            Capabilities::B,
+        )
+        .await?;
+
+    let result = planner
+        .insert_recurring_schedule(
+            system.id,
+            monday,
+            hour,
+            Capabilities::A,
+            &lab_days,
+            until,
+            false,
+        )
+        .await;
+    assert!(
+        matches!(&result, Err(AllocationError::RecurrenceConflict { starts })
+            if starts == &[monday + Duration::days(9)]),
+        "{result:?}"
+    );
+    assert_eq!(planner.list_entries(system.id, None).await?.len(), 1);
+
+    // Skipping the conflict inserts the rest, until and including the third Monday
+    let series = planner
+        .insert_recurring_schedule(
+            system.id,
+            monday,
+            hour,
+            Capabilities::A,
+            &lab_days,
+            until,
+            true,
+        )
+        .await?;
+    assert_eq!(series.skipped, [monday + Duration::days(9)]);
+    let starts: Vec<_> = planner
+        .list_recurring_group(series.group_id)
+        .await?
+        .iter()
+        .map(|e| e.start_time)
+        .collect();
+    assert_eq!(
+        starts,
+        [0, 2, 7, 14].map(|days| monday + Duration::days(days))
+    );
+    assert!(!series.allocation_ids.contains(&taken));
+
+    assert_eq!(planner.cancel_recurring_group(series.group_id).await?, 4);
+    assert!(planner
+        .list_recurring_group(series.group_id)
+        .await?
+        .is_empty());
+
+    let result = planner
+        .insert_recurring_schedule(
+            system.id,
+            monday,
+            hour,
+            Capabilities::A,
+            &Recurrence::every_n_days(0),
+            until,
+            false,
+        )
+        .await;
+    assert!(matches!(result, Err(AllocationError::Validation(_))));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn hold_entry(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool.clone());
+
+    let system = Uuid::new_v4();
+    planner
+        .declare_system(system, 1, Capabilities::all())
+        .await?;
+
+    let start = Utc::now();
+    let end = start + Duration::hours(1);
+    let ttl = Duration::minutes(15);
+    let hold = planner
+        .hold_entry(system, start, end, Capabilities::A, ttl)
+        .await?;
+
+    // The hold counts against the capacity, without being listed as an entry
+    let result = planner
+        .insert_entry(system, start, end, Capabilities::B)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::CapacityExceeded { .. })
+    ));
+    assert!(planner.list_entries(system, None).await?.is_empty());
+
+    // Once expired, it no longer does
+    sqlx::query("UPDATE holds SET expires_at = now() WHERE allocation_id = $1")
+        .bind(hold.0)
+        .execute(&pool)
+        .await?;
+    assert!(matches!(
+        planner.confirm_hold(hold).await,
+        Err(AllocationError::NotFound(id)) if id == hold.0
+    ));
+    planner
+        .insert_entry(system, start, end, Capabilities::B)
+        .await?;
+    assert_eq!(planner.expire_holds().await?, 1);
+    assert!(planner.get_allocation(hold.0).await?.is_none());
+
+    // A confirmed hold becomes an entry, and a released one frees its timeslot
+    let later = end + Duration::hours(1);
+    let hold = planner
+        .hold_entry(system, end, later, Capabilities::A, ttl)
+        .await?;
+    assert_eq!(planner.confirm_hold(hold).await?, hold.0);
+    assert_eq!(planner.list_entries(system, None).await?.len(), 2);
+    assert!(planner.release_hold(hold).await.is_err());
+
+    let hold = planner
+        .hold_entry(system, later, later + ttl, Capabilities::A, ttl)
+        .await?;
+    planner.release_hold(hold).await?;
+    planner
+        .insert_entry(system, later, later + ttl, Capabilities::A)
+        .await?;
+
+    let result = planner
+        .hold_entry(system, later + ttl, later + ttl * 2, Capabilities::A, -ttl)
+        .await;
+    assert!(matches!(result, Err(AllocationError::Validation(_))));
+    assert_eq!(planner.expire_holds().await?, 0);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn insert_entry_preempting(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).capacity(2).declare().await?;
+
+    let first = system.entry_at(0, 60, Capabilities::A).await?;
+    let second = system.entry_at(0, 60, Capabilities::A).await?;
+
+    // Entries of the same priority are not displaced
+    let result = planner
+        .insert_entry_preempting(system.id, system.at(0), system.at(60), Capabilities::A, 0)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::CapacityExceeded { .. })
+    ));
+    assert_eq!(planner.list_entries(system.id, None).await?.len(), 2);
+
+    // The most recently inserted is displaced first, and only as many as needed
+    let (critical, evicted) = planner
+        .insert_entry_preempting(system.id, system.at(0), system.at(60), Capabilities::A, 5)
+        .await?;
+    assert_eq!(evicted, [second]);
+    let (_, evicted) = planner
+        .insert_entry_preempting(system.id, system.at(30), system.at(90), Capabilities::A, 5)
+        .await?;
+    assert_eq!(evicted, [first]);
+    let result = planner
+        .insert_entry_preempting(system.id, system.at(40), system.at(50), Capabilities::A, 5)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::CapacityExceeded { .. })
+    ));
+    let ids: Vec<_> = planner
+        .list_entries(system.id, None)
+        .await?
+        .iter()
+        .map(|e| e.allocation_id)
+        .collect();
+    assert_eq!(ids.len(), 2);
+    assert!(ids.contains(&critical));
+
+    // Lower priorities go first, sparing those which turn out not to be needed
+    let system = SystemBuilder::new(&planner).capacity(2).declare().await?;
+    let insert = |start, end, priority| {
+        planner.insert_entry_preempting(
+            system.id,
+            system.at(start),
+            system.at(end),
+            Capabilities::A,
+            priority,
+        )
+    };
+    insert(0, 10, 0).await?;
+    insert(20, 30, 1).await?;
+    let (longest, _) = insert(0, 60, 1).await?;
+    let (_, evicted) = insert(0, 60, 5).await?;
+    assert_eq!(evicted, [longest]);
+
+    // Outages are never displaced
+    planner
+        .insert_planned_outage(system.id, system.at(120), system.at(180))
+        .await?;
+    let result = insert(130, 140, 100).await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::CapabilityOutage { .. })
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn concurrent_inserts_respect_capacity(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = Arc::new(SystemAllocation::new(pool));
+    let system = SystemBuilder::new(planner.as_ref()).declare().await?;
+
+    let (id, start) = (system.id, system.now);
+    let end = start + Duration::minutes(15);
+    let inserts: Vec<_> = (0..8)
+        .map(|_| {
+            let planner = planner.clone();
+            tokio::spawn(async move { planner.insert_entry(id, start, end, Capabilities::A).await })
+        })
+        .collect();
+
+    let mut inserted = 0;
+    for insert in inserts {
+        match insert.await? {
+            Ok(_) => inserted += 1,
+            Err(err) => assert!(
+                matches!(err, AllocationError::CapacityExceeded { ref conflicts, .. } if conflicts.len() == 1),
+                "{err:?}"
+            ),
+        }
+    }
+    assert_eq!(inserted, 1);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn min_notice(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).capacity(2).declare().await?;
+
+    // Without a notice, entries may start right away
+    system.entry_at(0, 15, Capabilities::A).await?;
+
+    planner
+        .set_min_notice(system.id, Duration::hours(2))
+        .await?;
+    assert_eq!(
+        planner.get_system(system.id).await?.unwrap().min_notice,
+        Some(Duration::hours(2))
+    );
+
+    let result = system.entry_at(60, 15, Capabilities::A).await;
+    assert!(matches!(result, Err(AllocationError::TooSoon { .. })));
+    let result = planner
+        .hold_entry(
+            system.id,
+            system.at(60),
+            system.at(75),
+            Capabilities::A,
+            Duration::minutes(5),
+        )
+        .await;
+    assert!(matches!(result, Err(AllocationError::TooSoon { .. })));
+    let result = planner
+        .insert_entries(
+            system.id,
+            &[
+                (system.at(180), system.at(195), Capabilities::A),
+                (system.at(60), system.at(75), Capabilities::A),
+            ],
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::Batch { index: 1, ref error }) if matches!(**error, AllocationError::TooSoon { .. })
+    ));
+
+    // Existing entries may not be moved to start on short notice, while still resized
+    let entry = system.entry_at(180, 15, Capabilities::A).await?;
+    let result = planner
+        .update_entry(entry, system.at(60), system.at(75), Capabilities::A)
+        .await;
+    assert!(matches!(result, Err(AllocationError::TooSoon { .. })));
+    planner
+        .update_entry(entry, system.at(180), system.at(240), Capabilities::A)
+        .await?;
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = false)]
+async fn migrate(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let expected = allocation_poc::MIGRATOR
+        .iter()
+        .map(|m| m.version)
+        .max()
+        .unwrap();
+
+    assert_eq!(planner.schema_version().await?, None);
+    let result = planner.ensure_schema().await;
+    assert!(
+        matches!(
+            result,
+            Err(AllocationError::SchemaMismatch { applied: None, expected: e }) if e == expected
+        ),
+        "{result:?}"
+    );
+
+    planner.migrate().await?;
+    assert_eq!(planner.schema_version().await?, Some(expected));
+    planner.ensure_schema().await?;
+
+    // Migrating an up to date schema is a no-op
+    planner.migrate().await?;
+    SystemBuilder::new(&planner).declare().await?;
+
+    Ok(())
+}
+
+#[cfg(feature = "notify")]
+#[sqlx::test]
+async fn subscribe_changes(pool: PgPool) -> Result<(), anyhow::Error> {
+    use allocation_poc::AllocationEvent;
+    use futures_util::StreamExt;
+
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).declare().await?;
+    let changes = planner.subscribe_changes().await?;
+    futures_util::pin_mut!(changes);
+    let timeout = std::time::Duration::from_secs(5);
+
+    let entry = system.entry_at(0, 60, Capabilities::A).await?;
+    match tokio::time::timeout(timeout, changes.next()).await? {
+        Some(AllocationEvent::Inserted(allocation)) => {
+            assert_eq!(allocation.allocation_id, entry);
+            assert_eq!(allocation.system_id, system.id);
+            assert_eq!(allocation.kind, AllocationKind::Entry);
+            assert_eq!(
+                (allocation.start_time, allocation.end_time),
+                (system.at(0), Some(system.at(60)))
+            );
+            assert_eq!(allocation.capabilities, Capabilities::A);
+        }
+        other => panic!("expected the entry to be inserted, got {other:?}"),
+    }
+
+    // Open-ended outages have no end
+    let outage = planner
+        .insert_unplanned_outage(system.id, system.at(120), Duration::hours(1))
+        .await?;
+    assert!(matches!(
+        tokio::time::timeout(timeout, changes.next()).await?,
+        Some(AllocationEvent::Inserted(allocation))
+            if allocation.allocation_id == outage && allocation.end_time.is_none()
+    ));
+
+    planner.remove_entry(entry).await?;
+    assert!(matches!(
+        tokio::time::timeout(timeout, changes.next()).await?,
+        Some(AllocationEvent::Removed(allocation)) if allocation.allocation_id == entry
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn utilization_report(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).capacity(2).declare().await?;
+    let midnight = (system.now + Duration::days(2)).duration_trunc(Duration::days(1))?;
+    let at =
+        |hours: i64, minutes: i64| midnight + Duration::hours(hours) + Duration::minutes(minutes);
+
+    // Straddling midnight, overlapping another entry before it
+    planner
+        .insert_entry(system.id, at(-2, 0), at(2, 0), Capabilities::A)
+        .await?;
+    planner
+        .insert_entry(system.id, at(-1, 0), at(-1, 30), Capabilities::B)
+        .await?;
+    planner
+        .insert_planned_outage(system.id, at(10, 0), at(12, 0))
+        .await?;
+    planner
+        .insert_planned_capability_outage(system.id, Capabilities::B, at(12, 0), at(13, 0))
+        .await?;
+    planner
+        .insert_unplanned_outage(system.id, at(18, 0), Duration::hours(1))
+        .await?;
+
+    let report = planner
+        .utilization_report(system.id, at(-24, 0), at(24, 0), Duration::days(1))
+        .await?;
+    assert_eq!(report.len(), 2);
+    let (before, after) = (&report[0], &report[1]);
+
+    assert_eq!((before.start, before.end), (at(-24, 0), midnight));
+    assert_eq!(before.booked, Duration::minutes(150));
+    assert_eq!(before.peak.concurrent, 2);
+    assert_eq!(before.peak.at, at(-1, 0));
+    assert_eq!(before.capacity, 2);
+    assert_eq!(before.unplanned_outage, Duration::zero());
+
+    assert_eq!((after.start, after.end), (midnight, at(24, 0)));
+    assert_eq!(after.booked, Duration::hours(2));
+    assert_eq!(after.average_occupancy, 2.0 / 24.0);
+    assert_eq!(after.peak.concurrent, 1);
+    assert_eq!(after.peak.at, midnight);
+    assert_eq!(after.planned_outage, Duration::hours(2));
+    assert_eq!(after.capability_outage, Duration::hours(1));
+    // The unresolved outage counts until the end of the report
+    assert_eq!(after.unplanned_outage, Duration::hours(6));
+
+    let result = planner
+        .utilization_report(system.id, at(0, 0), at(24, 0), Duration::zero())
+        .await;
+    assert!(matches!(result, Err(AllocationError::Validation(_))));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn find_available_systems(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = Arc::new(SystemAllocation::new(pool));
+    let busy = SystemBuilder::new(planner.as_ref())
+        .capacity(2)
+        .declare()
+        .await?;
+    let idle = SystemBuilder::new(planner.as_ref())
+        .capacity(2)
+        .declare()
+        .await?;
+    let lacking = SystemBuilder::new(planner.as_ref())
+        .capabilities(Capabilities::B)
+        .declare()
+        .await?;
+    let out = SystemBuilder::new(planner.as_ref()).declare().await?;
+    let (start, end) = (busy.at(0), busy.at(60));
+
+    busy.entry_at(30, 60, Capabilities::A).await?;
+    planner
+        .insert_unplanned_outage(out.id, out.at(-60), Duration::minutes(15))
+        .await?;
+
+    let systems = [lacking.id, out.id, busy.id, idle.id, Uuid::new_v4()];
+    let available = planner
+        .find_available_systems(&systems, start, end, Capabilities::A)
+        .await?;
+    assert_eq!(available, [idle.id, busy.id]);
+
+    // Concurrent inserts take the remaining slots, and fail once none are left
+    let inserts: Vec<_> = (0..6)
+        .map(|_| {
+            let planner = planner.clone();
+            tokio::spawn(async move {
+                planner
+                    .insert_entry_any(&systems, start, end, Capabilities::A)
+                    .await
+            })
+        })
+        .collect();
+    let mut inserted = Vec::new();
+    for insert in inserts {
+        match insert.await? {
+            Ok((system, _)) => inserted.push(system),
+            Err(AllocationError::NoSystemAvailable(n)) => assert_eq!(n, systems.len()),
+            Err(err) => panic!("unexpected error {err:?}"),
+        }
+    }
+    inserted.sort();
+    let mut expected = vec![idle.id, idle.id, busy.id];
+    expected.sort();
+    assert_eq!(inserted, expected);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn insert_entry_on_any(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let full = SystemBuilder::new(&planner).declare().await?;
+    let out = SystemBuilder::new(&planner).declare().await?;
+    let busy = SystemBuilder::new(&planner).capacity(2).declare().await?;
+    let idle = SystemBuilder::new(&planner).capacity(2).declare().await?;
+    let (start, end) = (full.at(0), full.at(60));
+
+    full.entry_at(0, 60, Capabilities::A).await?;
+    planner
+        .insert_planned_outage(out.id, out.at(-60), out.at(120))
+        .await?;
+    busy.entry_at(0, 60, Capabilities::A).await?;
+
+    // The first with room is picked, regardless of how loaded it is
+    let candidates = [full.id, out.id, busy.id, idle.id];
+    let system = planner
+        .insert_entry_on_any(&candidates, start, end, Capabilities::A)
+        .await?;
+    assert_eq!(system, busy.id);
+    assert_eq!(planner.list_entries(busy.id, None).await?.len(), 2);
+
+    // The conflicts of every candidate are listed once none have room
+    let result = planner
+        .insert_entry_on_any(&candidates[..3], start, end, Capabilities::A)
+        .await;
+    match result {
+        Err(AllocationError::CapacityExceeded { conflicts, .. }) => {
+            let mut systems: Vec<_> = conflicts.iter().map(|c| c.allocation.system_id).collect();
+            systems.dedup();
+            assert_eq!(systems, [full.id, out.id, busy.id]);
+        }
+        other => panic!("expected capacity exceeded, got {other:?}"),
+    }
+
+    let result = planner
+        .insert_entry_on_any(&[Uuid::new_v4(), idle.id], start, end, Capabilities::A)
+        .await;
+    assert!(matches!(result, Err(AllocationError::SystemNotFound(_))));
+    assert!(planner.list_entries(idle.id, None).await?.is_empty());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn verify_consistency(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool.clone());
+    let system = SystemBuilder::new(&planner).capacity(3).declare().await?;
+
+    let orphaned = system.entry_at(0, 60, Capabilities::A).await?;
+    let mismatched = system.entry_at(0, 60, Capabilities::A).await?;
+    let outage = planner
+        .insert_planned_outage(system.id, system.at(120), system.at(180))
+        .await?;
+    system.entry_at(60, 60, Capabilities::A).await?;
+    planner
+        .hold_entry(
+            system.id,
+            system.at(200),
+            system.at(260),
+            Capabilities::A,
+            Duration::hours(1),
+        )
+        .await?;
+    assert_eq!(
+        planner.verify_consistency(true).await?,
+        ConsistencyReport::default()
+    );
+
+    // Corrupt the allocations behind the back of the planner
+    sqlx::query("DELETE FROM allocations WHERE allocation_id = $1")
+        .bind(orphaned)
+        .execute(&pool)
+        .await?;
+    sqlx::query("UPDATE allocations SET planned = false WHERE allocation_id = $1")
+        .bind(mismatched)
+        .execute(&pool)
+        .await?;
+    sqlx::query("UPDATE allocations SET end_time = $2 WHERE allocation_id = $1")
+        .bind(outage)
+        .bind(system.at(240))
+        .execute(&pool)
+        .await?;
+    let stray = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO allocations(system_id, allocation_id, kind, start_time, end_time, capabilities)
+        VALUES ($1, $2, 'entry', $3, $4, 1)",
+    )
+    .bind(system.id)
+    .bind(stray)
+    .bind(system.at(300))
+    .bind(system.at(360))
+    .execute(&pool)
+    .await?;
+
+    let report = planner.verify_consistency(false).await?;
+    assert_eq!(report.repaired, 0);
+    assert_eq!(
+        report.inconsistencies,
+        [
+            Inconsistency::OrphanedRow {
+                table: SourceTable::Entries,
+                allocation_id: orphaned,
+            },
+            Inconsistency::OrphanedAllocation {
+                allocation_id: stray,
+                system_id: system.id,
+                kind: AllocationKind::Entry,
+            },
+            Inconsistency::KindMismatch {
+                table: SourceTable::Entries,
+                allocation_id: mismatched,
+                kind: AllocationKind::Entry,
+                planned: false,
+            },
+            Inconsistency::ColumnMismatch {
+                table: SourceTable::Planned,
+                allocation_id: outage,
+                found: MirroredColumns {
+                    system_id: system.id,
+                    start_time: system.at(120),
+                    end_time: Some(system.at(240)),
+                    capabilities: Capabilities::all().bits() as i64,
+                },
+                expected: MirroredColumns {
+                    system_id: system.id,
+                    start_time: system.at(120),
+                    end_time: Some(system.at(180)),
+                    capabilities: Capabilities::all().bits() as i64,
+                },
+            },
+        ]
+    );
+    assert_eq!(planner.verify_consistency(false).await?, report);
+
+    // All but the kind mismatch are repaired
+    let repaired = planner.verify_consistency(true).await?;
+    assert_eq!(repaired.inconsistencies, report.inconsistencies);
+    assert_eq!(repaired.repaired, 3);
+    let remaining = planner.verify_consistency(false).await?;
+    assert!(matches!(
+        remaining.inconsistencies[..],
+        [Inconsistency::KindMismatch { .. }]
+    ));
+    let outages = planner
+        .list_outages(system.id, system.at(0), system.at(300))
+        .await?;
+    assert!(matches!(outages[..], [Outage::Planned { end, .. }] if end == system.at(180)));
+    assert!(planner.get_allocation(stray).await?.is_none());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn shift_entries(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).declare().await?;
+
+    let first = planner
+        .insert_entry_with_params(
+            system.id,
+            system.at(0),
+            system.at(15),
+            Capabilities::A,
+            EntryParams::new().label("first"),
+        )
+        .await?;
+    let second = system.entry_at(15, 15, Capabilities::A).await?;
+    let later = system.entry_at(60, 15, Capabilities::A).await?;
+    planner
+        .insert_planned_outage(system.id, system.at(180), system.at(240))
+        .await?;
+    let placement = |entries: Vec<Entry>| -> Vec<_> {
+        entries
+            .into_iter()
+            .map(|e| (e.allocation_id, e.start_time))
+            .collect()
+    };
+
+    // Each is evaluated where the others end up, the first moving onto where the second was
+    let shifted = planner
+        .shift_entries(
+            system.id,
+            (system.at(0), system.at(30)),
+            Duration::minutes(15),
+        )
+        .await?;
+    assert_eq!(shifted, [first, second]);
+    let entries = planner.list_entries(system.id, None).await?;
+    assert_eq!(entries[0].params.label.as_deref(), Some("first"));
+    let expected = [
+        (first, system.at(15)),
+        (second, system.at(30)),
+        (later, system.at(60)),
+    ];
+    assert_eq!(placement(entries), expected);
+
+    // Running into an entry outside the range moves nothing at all
+    let result = planner
+        .shift_entries(
+            system.id,
+            (system.at(0), system.at(45)),
+            Duration::minutes(30),
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::CapacityExceeded { .. })
+    ));
+    assert_eq!(
+        placement(planner.list_entries(system.id, None).await?),
+        expected
+    );
+
+    let result = planner
+        .shift_entries(
+            system.id,
+            (system.at(60), system.at(75)),
+            Duration::hours(2),
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::CapabilityOutage { .. })
+    ));
+
+    let shifted = planner
+        .shift_entries(
+            system.id,
+            (system.at(90), system.at(120)),
+            Duration::hours(1),
+        )
+        .await?;
+    assert!(shifted.is_empty());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn open_entries(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).capacity(2).declare().await?;
+
+    let open = planner
+        .insert_open_entry(system.id, system.at(0), Capabilities::A)
+        .await?;
+    system.entry_at(30, 30, Capabilities::A).await?;
+
+    // Overlapping everything after its start, counting against the capacity as any other entry
+    planner
+        .insert_entry(system.id, system.at(1000), system.at(1060), Capabilities::A)
+        .await?;
+    let result = planner
+        .insert_entry(system.id, system.at(1000), system.at(1060), Capabilities::A)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::CapacityExceeded { .. })
+    ));
+    let result = planner
+        .insert_planned_outage(system.id, system.at(2000), system.at(2060))
+        .await;
+    assert!(matches!(result, Err(AllocationError::Conflict { .. })));
+
+    let entries = planner
+        .list_entries(system.id, Some((system.at(5000), system.at(5060))))
+        .await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].allocation_id, open);
+    assert_eq!(entries[0].end_time, None);
+    let timeline = planner
+        .capacity_timeline(system.id, system.at(5000), system.at(5060))
+        .await?;
+    assert_eq!((timeline[0].entries, timeline[0].remaining), (1, 1));
+
+    planner.close_entry(open, system.at(60)).await?;
+    let entries = planner.list_entries(system.id, None).await?;
+    assert_eq!(entries[0].end_time, Some(system.at(60)));
+    planner
+        .insert_entry(system.id, system.at(1000), system.at(1060), Capabilities::A)
+        .await?;
+    let outage = planner
+        .insert_planned_outage(system.id, system.at(2000), system.at(2060))
+        .await?;
+
+    // Only an open entry may be closed, and only after it started
+    let result = planner.close_entry(open, system.at(90)).await;
+    assert!(matches!(result, Err(AllocationError::Validation(_))));
+    let result = planner.close_entry(outage, system.at(90)).await;
+    assert!(matches!(result, Err(AllocationError::WrongKind { .. })));
+    let later = planner
+        .insert_open_entry(system.id, system.at(3000), Capabilities::A)
+        .await?;
+    let result = planner.close_entry(later, system.at(3000)).await;
+    assert!(matches!(result, Err(AllocationError::InvalidRange { .. })));
+
+    // Evicted by a sweep once within the sliding window, still without an end
+    let swept = SystemBuilder::new(&planner).declare().await?;
+    let open = planner
+        .insert_open_entry(swept.id, swept.at(10), Capabilities::A)
+        .await?;
+    planner
+        .insert_unplanned_outage(swept.id, swept.at(-60), Duration::hours(1))
+        .await?;
+    assert_eq!(planner.sweep_unplanned_windows(swept.id).await?, [open]);
+    let evictions = planner.list_evictions(swept.id, swept.at(-60)).await?;
+    assert_eq!(evictions[0].end_time, None);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn capacity_reduction(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).capacity(4).declare().await?;
+    let window = Duration::hours(2);
+
+    let mut near = Vec::new();
+    let mut far = Vec::new();
+    for _ in 0..4 {
+        near.push(system.entry_at(30, 60, Capabilities::A).await?);
+        far.push(system.entry_at(300, 60, Capabilities::A).await?);
+    }
+
+    // Only the most recently inserted entry within the window is removed to make room
+    let (first, removed) = planner
+        .insert_capacity_reduction(system.id, system.at(0), window, 1)
+        .await?;
+    let removed: Vec<_> = removed.iter().map(|e| e.allocation_id).collect();
+    assert_eq!(removed, [near[3]]);
+    let evictions = planner.list_evictions(system.id, system.now).await?;
+    assert_eq!(evictions.len(), 1);
+    assert_eq!(evictions[0].allocation_id, near[3]);
+
+    // New entries are evaluated against the reduced capacity
+    let result = system.entry_at(30, 15, Capabilities::B).await;
+    match result {
+        Err(AllocationError::CapacityExceeded { occupancy, .. }) => {
+            let occupancy = occupancy.unwrap();
+            assert_eq!((occupancy.occupied, occupancy.capacity), (3, 3));
+        }
+        other => panic!("expected capacity exceeded, got {other:?}"),
+    }
+
+    // As is the reported availability and occupancy
+    let availability = planner
+        .get_availability(system.id, system.at(-30), system.at(30), Capabilities::A)
+        .await?;
+    assert_eq!(
+        availability,
+        [
+            (system.at(-30), system.at(0), 4),
+            (system.at(0), system.at(30), 3)
+        ]
+    );
+    let timeline = planner
+        .capacity_timeline(system.id, system.at(-30), system.at(30))
+        .await?;
+    let remaining: Vec<_> = timeline.iter().map(|s| (s.start, s.remaining)).collect();
+    assert_eq!(remaining, [(system.at(-30), 4), (system.at(0), 3)]);
+
+    // While the entries beyond the window stay put, and may be modified as they are
+    assert_eq!(
+        planner
+            .list_entries(system.id, Some((system.at(300), system.at(360))))
+            .await?
+            .len(),
+        4
+    );
+    planner
+        .update_entry(far[0], system.at(305), system.at(365), Capabilities::A)
+        .await?;
+
+    // Concurrent reductions sum up
+    let (second, removed) = planner
+        .insert_capacity_reduction(system.id, system.at(0), window, 2)
+        .await?;
+    let mut removed: Vec<_> = removed.iter().map(|e| e.allocation_id).collect();
+    let mut expected = vec![near[1], near[2]];
+    removed.sort();
+    expected.sort();
+    assert_eq!(removed, expected);
+    system.entry_at(180, 60, Capabilities::A).await?;
+    let result = system.entry_at(180, 60, Capabilities::A).await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::CapacityExceeded { .. })
+    ));
+
+    // Once resolved, the declared capacity applies again after their end
+    planner
+        .resolve_capacity_reduction(first, system.at(120))
+        .await?;
+    planner
+        .resolve_capacity_reduction(second, system.at(120))
+        .await?;
+    for _ in 0..3 {
+        system.entry_at(180, 60, Capabilities::A).await?;
+    }
+    let result = system.entry_at(60, 15, Capabilities::A).await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::CapacityExceeded { .. })
+    ));
+
+    let result = planner
+        .resolve_capacity_reduction(first, system.at(150))
+        .await;
+    assert!(matches!(result, Err(AllocationError::Validation(_))));
+    let result = planner
+        .resolve_capacity_reduction(Uuid::new_v4(), system.at(150))
+        .await;
+    assert!(matches!(result, Err(AllocationError::NotFound(_))));
+    let result = planner
+        .insert_capacity_reduction(system.id, system.at(0), window, 0)
+        .await;
+    assert!(matches!(result, Err(AllocationError::Validation(_))));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn outage_impact(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).capacity(4).declare().await?;
+    let owner = Uuid::new_v4();
+
+    let owned = planner
+        .insert_entry_with_params(
+            system.id,
+            system.at(60),
+            system.at(90),
+            Capabilities::A,
+            EntryParams::new().owner(owner),
+        )
+        .await?;
+    let unowned = system.entry_at(75, 30, Capabilities::A).await?;
+    let later = system.entry_at(120, 30, Capabilities::B).await?;
+    planner
+        .insert_planned_capability_outage(
+            system.id,
+            Capabilities::B,
+            system.at(200),
+            system.at(260),
+        )
+        .await?;
+
+    // Only the entries sharing the capabilities are affected, for the time within the outage
+    let report = planner
+        .preview_outage_impact(system.id, system.at(70), system.at(130), Capabilities::A)
+        .await?;
+    let entries: Vec<_> = report.entries.iter().map(|e| e.allocation_id).collect();
+    assert_eq!(entries, [owned, unowned]);
+    assert_eq!(report.booked, Duration::minutes(50));
+    assert!(report.outages.is_empty());
+    assert!(report.after_window.is_empty());
+    let by_owner = report.by_owner();
+    assert_eq!(by_owner[&Some(owner)][0].allocation_id, owned);
+    assert_eq!(by_owner[&None][0].allocation_id, unowned);
+
+    // Outages in the way are reported along, while nothing is inserted
+    let report = planner
+        .preview_outage_impact(
+            system.id,
+            system.at(190),
+            system.at(210),
+            Capabilities::all(),
+        )
+        .await?;
+    assert!(report.entries.is_empty());
+    assert_eq!(report.outages.len(), 1);
+    assert_eq!(
+        planner
+            .list_outages(system.id, system.at(0), system.at(300))
+            .await?
+            .len(),
+        1
+    );
+
+    // An unplanned outage affects the entries within its sliding window, while those after it
+    // stay put
+    let report = planner
+        .preview_unplanned_impact(system.id, system.at(50), Duration::hours(1))
+        .await?;
+    let entries: Vec<_> = report.entries.iter().map(|e| e.allocation_id).collect();
+    assert_eq!(entries, [owned, unowned]);
+    assert_eq!(report.booked, Duration::minutes(60));
+    assert_eq!(report.outages.len(), 1);
+    let after: Vec<_> = report
+        .after_window
+        .iter()
+        .map(|e| e.allocation_id)
+        .collect();
+    assert_eq!(after, [later]);
+
+    let result = planner
+        .preview_outage_impact(Uuid::new_v4(), system.at(0), system.at(60), Capabilities::A)
+        .await;
+    assert!(matches!(result, Err(AllocationError::SystemNotFound(_))));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn purge_before(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).capacity(3).declare().await?;
+
+    let key = Uuid::new_v4();
+    let past = planner
+        .insert_entry_idempotent(
+            system.id,
+            key,
+            system.at(-180),
+            system.at(-120),
+            Capabilities::A,
+        )
+        .await?;
+    let ongoing = system.entry_at(-30, 60, Capabilities::A).await?;
+    let open = planner
+        .insert_open_entry(system.id, system.at(-240), Capabilities::B)
+        .await?;
+    let planned = planner
+        .insert_planned_capability_outage(
+            system.id,
+            Capabilities::C,
+            system.at(-300),
+            system.at(-240),
+        )
+        .await?;
+    let resolved = planner
+        .insert_unplanned_capability_outage(
+            system.id,
+            Capabilities::C,
+            system.at(-200),
+            Duration::minutes(10),
+        )
+        .await?;
+    planner
+        .resolve_unplanned_outage(resolved, system.at(-150))
+        .await?;
+    let unresolved = planner
+        .insert_unplanned_capability_outage(
+            system.id,
+            Capabilities::D,
+            system.at(-100),
+            Duration::minutes(10),
+        )
+        .await?;
+
+    // Only what ended before the cutoff is removed, from every table
+    assert_eq!(planner.purge_before(system.at(-60)).await?, 3);
+    for removed in [past, planned, resolved] {
+        assert!(planner.get_allocation(removed).await?.is_none());
+    }
+    for kept in [ongoing, open, unresolved] {
+        assert!(planner.get_allocation(kept).await?.is_some());
+    }
+    assert_eq!(
+        planner.verify_consistency(false).await?,
+        ConsistencyReport::default()
+    );
+
+    // The idempotency key of a removed entry is free to be used again
+    let again = planner
+        .insert_entry_idempotent(
+            system.id,
+            key,
+            system.at(60),
+            system.at(90),
+            Capabilities::A,
+        )
+        .await?;
+    assert_ne!(again, past);
+
+    assert_eq!(planner.purge_before(system.at(-60)).await?, 0);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn suggest_outage_window(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).declare().await?;
+
+    // A busy week of back to back entries, but for a single free stretch of 8 hours and a
+    // planned outage of 8 hours.
+    let base = system.at(0).duration_trunc(Duration::hours(1))? + Duration::days(1);
+    let hours = |h: i64| base + Duration::hours(h);
+    planner
+        .insert_planned_outage(system.id, hours(100), hours(108))
+        .await?;
+    for block in (0..168).step_by(4) {
+        if ![48, 52, 100, 104].contains(&block) {
+            planner
+                .insert_entry(system.id, hours(block), hours(block + 4), Capabilities::A)
+                .await?;
+        }
+    }
+
+    // The free stretch is the only window without conflicts, followed by the earliest windows
+    // at round hours, not overlapping it or each other
+    let suggested = planner
+        .suggest_outage_window(
+            system.id,
+            Duration::hours(8),
+            base - Duration::minutes(30),
+            hours(168),
+            Capabilities::all(),
+            3,
+        )
+        .await?;
+    let windows: Vec<_> = suggested
+        .iter()
+        .map(|w| (w.start, w.end, w.conflicts))
+        .collect();
+    assert_eq!(
+        windows,
+        [
+            (hours(48), hours(56), 0),
+            (hours(0), hours(8), 2),
+            (hours(8), hours(16), 2),
+        ]
+    );
+
+    // The planned outage is never suggested over, despite being free of entries
+    let suggested = planner
+        .suggest_outage_window(
+            system.id,
+            Duration::hours(8),
+            hours(96),
+            hours(120),
+            Capabilities::all(),
+            1,
+        )
+        .await?;
+    assert_eq!(suggested.len(), 1);
+    assert_eq!(
+        (suggested[0].start, suggested[0].conflicts),
+        (hours(108), 2)
+    );
+
+    // A window longer than the search range fits nowhere
+    let suggested = planner
+        .suggest_outage_window(
+            system.id,
+            Duration::hours(8),
+            hours(0),
+            hours(4),
+            Capabilities::all(),
+            3,
+        )
+        .await?;
+    assert!(suggested.is_empty());
+
+    let result = planner
+        .suggest_outage_window(
+            system.id,
+            Duration::zero(),
+            hours(0),
+            hours(4),
+            Capabilities::all(),
+            3,
+        )
+        .await;
+    assert!(matches!(result, Err(AllocationError::InvalidRange { .. })));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn system_timezone(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).declare().await?;
+    let oslo = chrono_tz::Europe::Oslo;
+
+    assert_eq!(planner.get_system_timezone(system.id).await?, None);
+    planner.set_timezone(system.id, Some(oslo)).await?;
+    assert_eq!(planner.get_system_timezone(system.id).await?, Some(oslo));
+    assert_eq!(
+        planner.get_system(system.id).await?.unwrap().timezone,
+        Some(oslo)
+    );
+
+    // The entries are stored in UTC, and only localized when presented
+    let start = Utc.with_ymd_and_hms(2030, 7, 1, 10, 0, 0).unwrap();
+    planner
+        .insert_entry(
+            system.id,
+            start,
+            start + Duration::hours(2),
+            Capabilities::A,
+        )
+        .await?;
+    let entries = planner.list_entries(system.id, None).await?;
+    assert_eq!(entries[0].start_time, start);
+    let (local_start, local_end) = entries[0].local_times(oslo);
+    assert_eq!(local_start.naive_local().to_string(), "2030-07-01 12:00:00");
+    assert_eq!(
+        local_end.map(|end| end.naive_local().to_string()),
+        Some("2030-07-01 14:00:00".to_string())
+    );
+
+    planner.set_timezone(system.id, None).await?;
+    assert_eq!(planner.get_system_timezone(system.id).await?, None);
+
+    let unknown = Uuid::new_v4();
+    let result = planner.get_system_timezone(unknown).await;
+    assert!(matches!(result, Err(AllocationError::SystemNotFound(id)) if id == unknown));
+    let result = planner.set_timezone(unknown, Some(oslo)).await;
+    assert!(matches!(result, Err(AllocationError::SystemNotFound(id)) if id == unknown));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn entry_lifecycle(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).declare().await?;
+
+    let entry = system.entry_at(60, 120, Capabilities::A).await?;
+    planner.start_entry(entry).await?;
+    let result = planner.start_entry(entry).await;
+    assert!(matches!(result, Err(AllocationError::Validation(_))));
+
+    // The rest of the timespan is in use until the entry completes early
+    let tail = (system.at(140), system.at(180));
+    let result = planner
+        .insert_entry(system.id, tail.0, tail.1, Capabilities::A)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::CapacityExceeded { .. })
+    ));
+    let result = planner.complete_entry(entry, Some(system.at(200))).await;
+    assert!(matches!(result, Err(AllocationError::Validation(_))));
+    let result = planner.complete_entry(entry, Some(system.at(60))).await;
+    assert!(matches!(result, Err(AllocationError::InvalidRange { .. })));
+    planner.complete_entry(entry, Some(system.at(100))).await?;
+    planner
+        .insert_entry(system.id, tail.0, tail.1, Capabilities::A)
+        .await?;
+
+    // A cancelled entry is listed, while no longer occupying its timespan
+    let cancelled = system.entry_at(240, 60, Capabilities::A).await?;
+    planner.cancel_entry(cancelled, "withdrawn").await?;
+    planner
+        .insert_entry(system.id, system.at(240), system.at(300), Capabilities::A)
+        .await?;
+
+    let entries = planner
+        .list_entries(system.id, Some((system.at(0), system.at(360))))
+        .await?;
+    let statuses: Vec<_> = entries
+        .iter()
+        .map(|e| (e.allocation_id, e.end_time, e.params.status))
+        .collect();
+    assert_eq!(statuses.len(), 4);
+    assert!(statuses.contains(&(entry, Some(system.at(100)), EntryStatus::Completed)));
+    assert!(statuses.contains(&(cancelled, Some(system.at(300)), EntryStatus::Cancelled)));
+    let reason = entries
+        .iter()
+        .find(|e| e.allocation_id == cancelled)
+        .and_then(|e| e.params.cancel_reason.clone());
+    assert_eq!(reason.as_deref(), Some("withdrawn"));
+
+    // Finished entries stay finished
+    for result in [
+        planner.complete_entry(cancelled, None).await,
+        planner.cancel_entry(entry, "too late").await,
+        planner.start_entry(cancelled).await,
+    ] {
+        assert!(matches!(result, Err(AllocationError::Validation(_))));
+    }
+
+    let outage = planner
+        .insert_planned_outage(system.id, system.at(600), system.at(660))
+        .await?;
+    let result = planner.start_entry(outage).await;
+    assert!(matches!(result, Err(AllocationError::WrongKind { .. })));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn availability_multi(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let busy = SystemBuilder::new(&planner).capacity(2).declare().await?;
+    let idle = SystemBuilder::new(&planner).capacity(3).declare().await?;
+    let reduced = SystemBuilder::new(&planner).capacity(3).declare().await?;
+    let limited = Uuid::new_v4();
+    planner
+        .declare_system_with_capacities(limited, &[(Capabilities::A, 6), (Capabilities::B, 1)])
+        .await?;
+
+    busy.entry_at(0, 30, Capabilities::A).await?;
+    busy.entry_at(15, 30, Capabilities::A).await?;
+    planner
+        .insert_planned_capability_outage(busy.id, Capabilities::B, busy.at(60), busy.at(75))
+        .await?;
+    planner
+        .insert_entry(limited, busy.at(0), busy.at(30), Capabilities::B)
+        .await?;
+    planner
+        .insert_capacity_reduction(reduced.id, busy.at(30), Duration::minutes(30), 2)
+        .await?;
+
+    // Every system is reported as by itself, within its own limits
+    let (start, end) = (busy.at(-15), busy.at(90));
+    let systems = [busy.id, idle.id, reduced.id, limited];
+    let availability = planner
+        .availability_multi(&systems, start, end, Capabilities::B)
+        .await?;
+    assert_eq!(availability.len(), 4);
+    for system in systems {
+        assert_eq!(
+            availability[&system],
+            planner
+                .get_availability(system, start, end, Capabilities::B)
+                .await?
+        );
+    }
+    assert_eq!(availability[&idle.id], [(start, end, 3)]);
+    assert_eq!(
+        availability[&reduced.id],
+        [(start, busy.at(30), 3), (busy.at(30), end, 1)]
+    );
+    assert_eq!(
+        availability[&limited],
+        [
+            (start, busy.at(0), 1),
+            (busy.at(0), busy.at(30), 0),
+            (busy.at(30), end, 1)
+        ]
+    );
+
+    let result = planner
+        .availability_multi(&systems, start, end, Capabilities::H)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::UnsupportedCapabilities { .. })
+    ));
+
+    let unknown = Uuid::new_v4();
+    let result = planner
+        .availability_multi(&[busy.id, unknown], start, end, Capabilities::B)
+        .await;
+    assert!(matches!(result, Err(AllocationError::SystemNotFound(id)) if id == unknown));
+
+    let result = planner
+        .availability_multi(&systems, end, start, Capabilities::B)
+        .await;
+    assert!(matches!(result, Err(AllocationError::InvalidRange { .. })));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn waitlist(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).capacity(1).declare().await?;
+
+    let removed = system.entry_at(0, 60, Capabilities::A).await?;
+    let first = planner
+        .join_waitlist(system.id, system.at(0), system.at(30), Capabilities::A)
+        .await?;
+    let second = planner
+        .join_waitlist(system.id, system.at(15), system.at(45), Capabilities::A)
+        .await?;
+    let queued: Vec<_> = planner
+        .list_waitlist(system.id, None)
+        .await?
+        .into_iter()
+        .map(|r| r.waitlist_id)
+        .collect();
+    assert_eq!(queued, [first, second]);
+
+    // Only the earliest joined fits once the room is freed, the other stays queued
+    planner.remove_entry(removed).await?;
+    assert!(planner.get_allocation(first.0).await?.is_some());
+    assert!(planner.get_allocation(second.0).await?.is_none());
+    let queued = planner
+        .list_waitlist(system.id, Some((system.at(0), system.at(60))))
+        .await?;
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].waitlist_id, second);
+
+    // Cancelling the promoted entry promotes the next
+    planner.cancel_entry(first.0, "withdrawn").await?;
+    assert!(planner.get_allocation(second.0).await?.is_some());
+    assert!(planner.list_waitlist(system.id, None).await?.is_empty());
+
+    let third = planner
+        .join_waitlist(system.id, system.at(30), system.at(60), Capabilities::A)
+        .await?;
+    planner
+        .complete_entry(second.0, Some(system.at(30)))
+        .await?;
+    assert!(planner.get_allocation(third.0).await?.is_some());
+
+    let leaving = planner
+        .join_waitlist(system.id, system.at(30), system.at(60), Capabilities::A)
+        .await?;
+    planner.leave_waitlist(leaving).await?;
+    let result = planner.leave_waitlist(leaving).await;
+    assert!(matches!(result, Err(AllocationError::NotFound(id)) if id == leaving.0));
+
+    // A request no longer valid for the system is skipped, without failing the removal
+    let system = SystemBuilder::new(&planner)
+        .capabilities(Capabilities::A | Capabilities::B)
+        .declare()
+        .await?;
+    let removed = system.entry_at(0, 60, Capabilities::A).await?;
+    let stale = planner
+        .join_waitlist(system.id, system.at(0), system.at(30), Capabilities::B)
+        .await?;
+    let valid = planner
+        .join_waitlist(system.id, system.at(30), system.at(60), Capabilities::A)
+        .await?;
+    planner
+        .update_capabilities(system.id, Capabilities::A)
+        .await?;
+    planner.remove_entry(removed).await?;
+    assert!(planner.get_allocation(valid.0).await?.is_some());
+    let queued = planner.list_waitlist(system.id, None).await?;
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].waitlist_id, stale);
+
+    // Cancelling a recurring entry and closing an open-ended one promote as well
+    let system = SystemBuilder::new(&planner).declare().await?;
+    let series = planner
+        .insert_recurring_entry(
+            system.id,
+            system.at(0),
+            Duration::hours(1),
+            Duration::days(1),
+            2,
+            Capabilities::A,
+        )
+        .await?;
+    let second_day = system.at(24 * 60);
+    let queued = planner
+        .join_waitlist(
+            system.id,
+            second_day,
+            second_day + Duration::hours(1),
+            Capabilities::A,
+        )
+        .await?;
+    planner.cancel_recurring_group(series.group_id).await?;
+    assert!(planner.get_allocation(queued.0).await?.is_some());
+
+    let open = planner
+        .insert_open_entry(system.id, system.at(3 * 24 * 60), Capabilities::A)
+        .await?;
+    let queued = planner
+        .join_waitlist(
+            system.id,
+            system.at(4 * 24 * 60),
+            system.at(4 * 24 * 60 + 60),
+            Capabilities::A,
+        )
+        .await?;
+    planner
+        .close_entry(open, system.at(3 * 24 * 60 + 60))
+        .await?;
+    assert!(planner.get_allocation(queued.0).await?.is_some());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn entry_priority(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).capacity(1).declare().await?;
+
+    let routine = planner
+        .insert_entry_with_params(
+            system.id,
+            system.at(0),
+            system.at(60),
+            Capabilities::A,
+            EntryParams::new().priority(3),
+        )
+        .await?;
+    let entries = planner.list_entries(system.id, None).await?;
+    assert_eq!(entries[0].params.priority, 3);
+
+    // Only a strictly higher priority displaces the entry
+    for priority in [0, 3] {
+        let result = planner
+            .insert_entry_preempting(
+                system.id,
+                system.at(0),
+                system.at(60),
+                Capabilities::A,
+                priority,
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(AllocationError::CapacityExceeded { .. })
+        ));
+    }
+    let (emergency, evicted) = planner
+        .insert_entry_preempting(system.id, system.at(0), system.at(60), Capabilities::A, 4)
+        .await?;
+    assert_eq!(evicted, [routine]);
+    let entries = planner.list_entries(system.id, None).await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].allocation_id, emergency);
+    assert_eq!(entries[0].params.priority, 4);
+
+    // The priority is kept along with an eviction, as reported when removed
+    let (_, removed) = planner
+        .insert_capacity_reduction(system.id, system.at(0), Duration::hours(2), 1)
+        .await?;
+    assert_eq!(removed[0].allocation_id, emergency);
+    let evictions = planner.list_evictions(system.id, system.now).await?;
+    assert_eq!(evictions[0].allocation_id, emergency);
+    assert_eq!(evictions[0].params.priority, 4);
+    assert_eq!(evictions[0].params, removed[0].params);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn booking_policy(pool: PgPool) -> Result<(), anyhow::Error> {
+    // The clock is pinned well ahead of the actual time, which the policy must not follow
+    let now = Utc::now().duration_trunc(Duration::seconds(1))? + Duration::days(365);
+    let planner = SystemAllocation::new(pool).with_clock(move || now);
+    let system = SystemBuilder::new(&planner).capacity(3).declare().await?;
+    let at = |minutes| now + Duration::minutes(minutes);
+
+    let policy = BookingPolicy {
+        min_notice: Some(Duration::minutes(30)),
+        max_horizon: Some(Duration::days(90)),
+    };
+    planner.set_booking_policy(system.id, policy).await?;
+    let info = planner.get_system(system.id).await?.unwrap();
+    assert_eq!(
+        (info.min_notice, info.max_horizon),
+        (policy.min_notice, policy.max_horizon)
+    );
+
+    let result = planner
+        .insert_entry(system.id, at(10), at(70), Capabilities::A)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::TooSoon { earliest, .. }) if earliest == at(30)
+    ));
+    let far = at(91 * 24 * 60);
+    let result = planner
+        .insert_entry(system.id, far, far + Duration::hours(1), Capabilities::A)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::TooFarInFuture { latest, .. }) if latest == at(90 * 24 * 60)
+    ));
+
+    // Checking whether an entry fits is rejected alike
+    let result = planner
+        .can_insert_entry(system.id, at(10), at(70), Capabilities::A)
+        .await;
+    assert!(matches!(result, Err(AllocationError::TooSoon { .. })));
+    let result = planner
+        .check_entry_fits(system.id, far, far + Duration::hours(1), Capabilities::A)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::TooFarInFuture { .. })
+    ));
+    assert!(
+        planner
+            .can_insert_entry(system.id, at(30), at(90), Capabilities::A)
+            .await?
+    );
+
+    let entry = planner
+        .insert_entry(system.id, at(30), at(90), Capabilities::A)
+        .await?;
+
+    // Moving an entry beyond the horizon is rejected as inserting it would be
+    let result = planner
+        .update_entry(entry, far, far + Duration::hours(1), Capabilities::A)
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::TooFarInFuture { .. })
+    ));
+
+    // So is shifting it, leaving it where it was
+    let result = planner
+        .shift_entries(system.id, (at(30), at(90)), Duration::days(91))
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::TooFarInFuture { .. })
+    ));
+    let result = planner
+        .shift_entries(system.id, (at(30), at(90)), Duration::minutes(-20))
+        .await;
+    assert!(matches!(
+        result,
+        Err(AllocationError::TooSoon { start, .. }) if start == at(10)
+    ));
+    let shifted = planner
+        .shift_entries(system.id, (at(30), at(90)), Duration::minutes(15))
+        .await?;
+    assert_eq!(shifted, [entry]);
+
+    // Administrative inserts and outages are not subject to the policy
+    planner
+        .insert_entry_bypassing_policy(
+            system.id,
+            at(10),
+            at(70),
+            Capabilities::A,
+            EntryParams::new(),
+        )
+        .await?;
+    planner
+        .insert_planned_capability_outage(system.id, Capabilities::B, at(5), at(20))
+        .await?;
+    planner
+        .insert_planned_outage(system.id, far, far + Duration::hours(1))
+        .await?;
+
+    let result = planner
+        .set_booking_policy(
+            system.id,
+            BookingPolicy {
+                min_notice: Some(Duration::days(2)),
+                max_horizon: Some(Duration::days(1)),
+            },
+        )
+        .await;
+    assert!(matches!(result, Err(AllocationError::Validation(_))));
+
+    planner
+        .set_booking_policy(system.id, BookingPolicy::default())
+        .await?;
+    planner
+        .insert_entry(system.id, at(10), at(70), Capabilities::A)
+        .await?;
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn capacity_reduction_priority(pool: PgPool) -> Result<(), anyhow::Error> {
+    let planner = SystemAllocation::new(pool);
+    let system = SystemBuilder::new(&planner).capacity(3).declare().await?;
+    let window = Duration::hours(2);
+
+    let entry = |priority| {
+        planner.insert_entry_with_params(
+            system.id,
+            system.at(30),
+            system.at(90),
+            Capabilities::A,
+            EntryParams::new().priority(priority),
+        )
+    };
+    let older = entry(0).await?;
+    let high = entry(5).await?;
+    let newer = entry(0).await?;
+
+    // The most recently inserted among those of the lowest priority goes first
+    let (_, removed) = planner
+        .insert_capacity_reduction(system.id, system.at(0), window, 1)
+        .await?;
+    let removed: Vec<_> = removed.iter().map(|e| e.allocation_id).collect();
+    assert_eq!(removed, [newer]);
+
+    // An older entry of a lower priority goes before a newer one of a higher priority
+    let (_, removed) = planner
+        .insert_capacity_reduction(system.id, system.at(0), window, 1)
+        .await?;
+    let removed: Vec<_> = removed.iter().map(|e| e.allocation_id).collect();
+    assert_eq!(removed, [older]);
+    assert!(planner.get_allocation(high).await?.is_some());
 
     Ok(())
 }
@@ -0,0 +1,305 @@
+//! Run the same invariant tests against every backend of the allocations
+
+use allocation_poc::test_util::SystemBuilder;
+use allocation_poc::{AllocationError, AllocationKind, AllocationStore, Capabilities, Outage};
+
+use chrono::Duration;
+use uuid::Uuid;
+
+/// Run each of the listed tests against the database backend and the in-memory backend.
+macro_rules! backends {
+    ($($test:ident),* $(,)?) => {
+        mod postgres {
+            use allocation_poc::SystemAllocation;
+            use sqlx::PgPool;
+
+            $(
+                #[sqlx::test(migrator = "allocation_poc::MIGRATOR")]
+                async fn $test(pool: PgPool) -> Result<(), anyhow::Error> {
+                    super::$test(&SystemAllocation::new(pool)).await
+                }
+            )*
+        }
+
+        mod memory {
+            use allocation_poc::test_util::MemoryStore;
+
+            $(
+                #[sqlx::test]
+                async fn $test() -> Result<(), anyhow::Error> {
+                    super::$test(&MemoryStore::default()).await
+                }
+            )*
+        }
+    };
+}
+
+backends!(
+    capacity,
+    capability_outage,
+    unplanned_outage,
+    unplanned_capability_outage,
+    remove_and_list,
+    invalid_requests,
+);
+
+async fn capacity<S: AllocationStore>(store: &S) -> Result<(), anyhow::Error> {
+    let system = SystemBuilder::new(store).capacity(2).declare().await?;
+
+    system.entry_at(0, 60, Capabilities::A).await?;
+    system.entry_at(30, 60, Capabilities::B).await?;
+
+    // A third entry overlapping both exceeds the capacity
+    let result = system.entry_at(45, 10, Capabilities::C).await;
+    assert!(
+        matches!(&result, Err(AllocationError::CapacityExceeded { conflicts, .. }) if conflicts.len() == 2),
+        "{result:?}"
+    );
+    // Both are taken for the whole of it
+    let Err(AllocationError::CapacityExceeded {
+        occupancy: Some(occupancy),
+        ..
+    }) = result
+    else {
+        unreachable!();
+    };
+    assert_eq!((occupancy.occupied, occupancy.capacity), (2, 2));
+    assert_eq!(occupancy.capability, None);
+    assert_eq!(occupancy.peak_start, system.at(45));
+    assert_eq!(occupancy.peak_end, Some(system.at(55)));
+
+    // Touching the end of the first entry only overlaps the second
+    system.entry_at(60, 30, Capabilities::C).await?;
+
+    Ok(())
+}
+
+async fn capability_outage<S: AllocationStore>(store: &S) -> Result<(), anyhow::Error> {
+    let system = SystemBuilder::new(store).capacity(4).declare().await?;
+
+    store
+        .insert_planned_capability_outage(system.id, Capabilities::A, system.at(0), system.at(60))
+        .await?;
+
+    // Entries requiring the capability are blocked, others are not
+    let result = system
+        .entry_at(30, 60, Capabilities::A | Capabilities::B)
+        .await;
+    assert!(
+        matches!(result, Err(AllocationError::CapabilityOutage { .. })),
+        "{result:?}"
+    );
+    system.entry_at(30, 60, Capabilities::B).await?;
+    system.entry_at(60, 60, Capabilities::A).await?;
+
+    // Entries requiring the capability are in the way of another outage of it
+    let result = store
+        .insert_planned_capability_outage(system.id, Capabilities::A, system.at(90), system.at(120))
+        .await;
+    assert!(
+        matches!(&result, Err(AllocationError::Conflict { conflicts }) if conflicts.len() == 1),
+        "{result:?}"
+    );
+
+    // A full outage is in the way of both the entries and the capability outage
+    let result = store
+        .insert_planned_outage(system.id, system.at(0), system.at(120))
+        .await;
+    assert!(
+        matches!(&result, Err(AllocationError::Conflict { conflicts }) if conflicts.len() == 3),
+        "{result:?}"
+    );
+
+    Ok(())
+}
+
+async fn unplanned_outage<S: AllocationStore>(store: &S) -> Result<(), anyhow::Error> {
+    let system = SystemBuilder::new(store).capacity(2).declare().await?;
+
+    let entry = system.entry_at(60, 30, Capabilities::A).await?;
+    system.entry_at(180, 30, Capabilities::A).await?;
+
+    // The entry starting within the sliding window is in the way, the later one is not
+    let result = store
+        .insert_unplanned_outage(system.id, system.at(0), Duration::hours(2))
+        .await;
+    assert!(
+        matches!(&result, Err(AllocationError::Conflict { conflicts }) if conflicts.len() == 1),
+        "{result:?}"
+    );
+
+    store.remove_entry(entry).await?;
+    let outage = store
+        .insert_unplanned_outage(system.id, system.at(0), Duration::hours(2))
+        .await?;
+
+    // No new entries may start once the outage has started
+    let result = system.entry_at(240, 30, Capabilities::A).await;
+    assert!(
+        matches!(result, Err(AllocationError::WindowViolation { .. })),
+        "{result:?}"
+    );
+
+    let outages = store
+        .list_outages(system.id, system.at(0), system.at(60))
+        .await?;
+    assert!(
+        matches!(
+            outages.as_slice(),
+            [Outage::Unplanned { allocation_id, sliding_window, resolved_at: None, .. }]
+                if *allocation_id == outage && *sliding_window == Duration::hours(2)
+        ),
+        "{outages:?}"
+    );
+
+    Ok(())
+}
+
+async fn unplanned_capability_outage<S: AllocationStore>(store: &S) -> Result<(), anyhow::Error> {
+    let system = SystemBuilder::new(store).capacity(2).declare().await?;
+
+    system.entry_at(60, 30, Capabilities::A).await?;
+    let entry = system.entry_at(60, 30, Capabilities::B).await?;
+
+    // Only the entry sharing the failed capability is in the way
+    let result = store
+        .insert_unplanned_capability_outage(
+            system.id,
+            Capabilities::B,
+            system.at(0),
+            Duration::hours(2),
+        )
+        .await;
+    assert!(
+        matches!(&result, Err(AllocationError::Conflict { conflicts })
+            if conflicts.len() == 1 && conflicts[0].allocation.allocation_id == entry),
+        "{result:?}"
+    );
+
+    store.remove_entry(entry).await?;
+    store
+        .insert_unplanned_capability_outage(
+            system.id,
+            Capabilities::B,
+            system.at(0),
+            Duration::hours(2),
+        )
+        .await?;
+
+    // New entries are denied only for the failed capability
+    let result = system.entry_at(240, 30, Capabilities::B).await;
+    assert!(
+        matches!(result, Err(AllocationError::WindowViolation { .. })),
+        "{result:?}"
+    );
+    system.entry_at(240, 30, Capabilities::A).await?;
+
+    let outages = store
+        .list_outages(system.id, system.at(0), system.at(60))
+        .await?;
+    assert!(
+        matches!(
+            outages.as_slice(),
+            [Outage::Unplanned { capabilities, resolved_at: None, .. }]
+                if *capabilities == Capabilities::B
+        ),
+        "{outages:?}"
+    );
+
+    Ok(())
+}
+
+async fn remove_and_list<S: AllocationStore>(store: &S) -> Result<(), anyhow::Error> {
+    let system = SystemBuilder::new(store).capacity(2).declare().await?;
+
+    let first = system.entry_at(0, 60, Capabilities::A).await?;
+    let second = system.entry_at(60, 60, Capabilities::B).await?;
+    let outage = store
+        .insert_planned_outage(system.id, system.at(120), system.at(180))
+        .await?;
+
+    let entries = store.list_entries(system.id, None).await?;
+    let ids: Vec<_> = entries.iter().map(|e| e.allocation_id).collect();
+    assert_eq!(ids, [first, second]);
+
+    // The range is half-open, leaving out the entry ending at its start
+    let entries = store
+        .list_entries(system.id, Some((system.at(60), system.at(180))))
+        .await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].allocation_id, second);
+    assert_eq!(entries[0].start_time, system.at(60));
+
+    let outages = store
+        .list_outages(system.id, system.at(0), system.at(240))
+        .await?;
+    assert!(
+        matches!(outages.as_slice(), [Outage::Planned { allocation_id, .. }] if *allocation_id == outage),
+        "{outages:?}"
+    );
+
+    let allocation = store.get_allocation(outage).await?.unwrap();
+    assert_eq!(allocation.kind, AllocationKind::Full);
+    assert_eq!(
+        (allocation.start_time, allocation.end_time),
+        (system.at(120), Some(system.at(180)))
+    );
+
+    store.remove_entry(first).await?;
+    assert_eq!(store.get_allocation(first).await?, None);
+    let result = store.remove_entry(first).await;
+    assert!(
+        matches!(result, Err(AllocationError::NotFound(id)) if id == first),
+        "{result:?}"
+    );
+    let result = store.remove_entry(outage).await;
+    assert!(
+        matches!(
+            result,
+            Err(AllocationError::WrongKind {
+                kind: AllocationKind::Full,
+                ..
+            })
+        ),
+        "{result:?}"
+    );
+    assert_eq!(store.list_entries(system.id, None).await?.len(), 1);
+
+    Ok(())
+}
+
+async fn invalid_requests<S: AllocationStore>(store: &S) -> Result<(), anyhow::Error> {
+    let system = SystemBuilder::new(store)
+        .capabilities(Capabilities::A | Capabilities::B)
+        .declare()
+        .await?;
+
+    let result = system.entry_at(0, 0, Capabilities::A).await;
+    assert!(
+        matches!(result, Err(AllocationError::InvalidRange { .. })),
+        "{result:?}"
+    );
+    let result = system.entry_at(0, 60, Capabilities::C).await;
+    assert!(
+        matches!(result, Err(AllocationError::UnsupportedCapabilities { .. })),
+        "{result:?}"
+    );
+
+    let unknown = Uuid::new_v4();
+    let result = store
+        .insert_entry(unknown, system.at(0), system.at(60), Capabilities::A)
+        .await;
+    assert!(
+        matches!(result, Err(AllocationError::SystemNotFound(id)) if id == unknown),
+        "{result:?}"
+    );
+    let result = store
+        .insert_planned_outage(unknown, system.at(0), system.at(60))
+        .await;
+    assert!(
+        matches!(result, Err(AllocationError::SystemNotFound(id)) if id == unknown),
+        "{result:?}"
+    );
+
+    Ok(())
+}